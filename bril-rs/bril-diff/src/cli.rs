@@ -0,0 +1,13 @@
+use clap::Parser;
+
+#[derive(Parser)]
+#[command(about, version, author)] // keeps the cli synced with Cargo.toml
+pub struct Cli {
+    /// The original bril file, either text or JSON
+    pub old: String,
+    /// The changed bril file, either text or JSON
+    pub new: String,
+    /// The number of unchanged instructions to show around each change
+    #[arg(short = 'U', long, default_value_t = 3)]
+    pub context: usize,
+}
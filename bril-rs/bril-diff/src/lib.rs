@@ -0,0 +1,291 @@
+#![warn(clippy::all, clippy::pedantic, clippy::nursery, clippy::cargo)]
+#![warn(missing_docs)]
+#![warn(clippy::allow_attributes)]
+#![doc = include_str!("../README.md")]
+
+#[doc(hidden)]
+pub mod cli;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use bril_rs::alpha::{Renaming, alpha_equivalent, for_each_name};
+use bril_rs::{Code, Function, Instruction, Program};
+
+/// One line of the diff between two functions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    /// A line found in both functions
+    Same(String),
+    /// A line only found in the old function
+    Removed(String),
+    /// A line only found in the new function
+    Added(String),
+}
+
+// What is left of a piece of code once its variables and labels are erased
+fn shape(code: &Code) -> String {
+    match code {
+        Code::Label { .. } => ".:".to_string(),
+        Code::Instruction(Instruction::Constant {
+            const_type, value, ..
+        }) => format!("_: {const_type} = const {value}"),
+        Code::Instruction(Instruction::Value {
+            op,
+            op_type,
+            funcs,
+            args,
+            labels,
+            ..
+        }) => format!(
+            "_: {op_type} = {op} {funcs:?} {} {}",
+            args.len(),
+            labels.len()
+        ),
+        Code::Instruction(Instruction::Effect {
+            op,
+            funcs,
+            args,
+            labels,
+            ..
+        }) => format!("{op} {funcs:?} {} {}", args.len(), labels.len()),
+    }
+}
+
+// The variables and labels of a piece of code in the same order as `for_each_name`
+fn names(code: &Code) -> (Vec<&str>, Vec<&str>) {
+    match code {
+        Code::Label { label, .. } => (Vec::new(), vec![label.as_str()]),
+        Code::Instruction(Instruction::Constant { dest, .. }) => (vec![dest.as_str()], Vec::new()),
+        Code::Instruction(Instruction::Value {
+            dest, args, labels, ..
+        }) => (
+            args.iter()
+                .map(String::as_str)
+                .chain(std::iter::once(dest.as_str()))
+                .collect(),
+            labels.iter().map(String::as_str).collect(),
+        ),
+        Code::Instruction(Instruction::Effect { args, labels, .. }) => (
+            args.iter().map(String::as_str).collect(),
+            labels.iter().map(String::as_str).collect(),
+        ),
+    }
+}
+
+// The pairs of indices of a longest common subsequence of `a` and `b`
+fn lcs<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    // table[i][j] is the length of the longest common subsequence of a[i..] and b[j..]
+    let mut table = vec![vec![0_usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            table[i][j] = if a[i] == b[j] {
+                table[i + 1][j + 1] + 1
+            } else {
+                table[i + 1][j].max(table[i][j + 1])
+            };
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if table[i + 1][j] >= table[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+// Picks the most popular mapping for each name such that no two names are mapped to the same name
+// Names without a mapping keep their own name unless it is already taken
+fn assign<'a>(
+    votes: HashMap<(&'a str, &'a str), usize>,
+    new_names: &[&'a str],
+    old_names: &HashSet<&'a str>,
+) -> HashMap<String, String> {
+    let mut votes: Vec<_> = votes.into_iter().collect();
+    votes.sort_by(|(p1, c1), (p2, c2)| c2.cmp(c1).then(p1.cmp(p2)));
+
+    let mut map = HashMap::new();
+    let mut taken = HashSet::new();
+    for ((new, old), _) in votes {
+        if !map.contains_key(new) && !taken.contains(old) {
+            map.insert(new.to_string(), old.to_string());
+            taken.insert(old.to_string());
+        }
+    }
+
+    for new in new_names {
+        if map.contains_key(*new) {
+            continue;
+        }
+        let mut fresh = (*new).to_string();
+        let mut k = 1;
+        while taken.contains(&fresh) || (fresh != *new && old_names.contains(fresh.as_str())) {
+            fresh = format!("{new}_{k}");
+            k += 1;
+        }
+        taken.insert(fresh.clone());
+        map.insert((*new).to_string(), fresh);
+    }
+    map
+}
+
+/// Finds a renaming of the variables and labels of `new` which agrees with `old` wherever their instructions line up
+#[must_use]
+pub fn align_names(old: &Function, new: &Function) -> Renaming {
+    let mut var_votes = HashMap::new();
+    let mut label_votes = HashMap::new();
+
+    for (o, n) in old.args.iter().zip(&new.args) {
+        *var_votes
+            .entry((n.name.as_str(), o.name.as_str()))
+            .or_insert(0) += 1;
+    }
+
+    let old_shapes: Vec<_> = old.instrs.iter().map(shape).collect();
+    let new_shapes: Vec<_> = new.instrs.iter().map(shape).collect();
+    for (i, j) in lcs(&old_shapes, &new_shapes) {
+        let (old_vars, old_labels) = names(&old.instrs[i]);
+        let (new_vars, new_labels) = names(&new.instrs[j]);
+        for (o, n) in old_vars.into_iter().zip(new_vars) {
+            *var_votes.entry((n, o)).or_insert(0) += 1;
+        }
+        for (o, n) in old_labels.into_iter().zip(new_labels) {
+            *label_votes.entry((n, o)).or_insert(0) += 1;
+        }
+    }
+
+    let (mut old_vars, mut old_labels) = (HashSet::new(), HashSet::new());
+    for_each_name(
+        old,
+        |v| {
+            old_vars.insert(v);
+        },
+        |l| {
+            old_labels.insert(l);
+        },
+    );
+    let (mut new_vars, mut new_labels) = (Vec::new(), Vec::new());
+    for_each_name(
+        new,
+        |v| {
+            if !new_vars.contains(&v) {
+                new_vars.push(v);
+            }
+        },
+        |l| {
+            if !new_labels.contains(&l) {
+                new_labels.push(l);
+            }
+        },
+    );
+
+    Renaming {
+        vars: assign(var_votes, &new_vars, &old_vars),
+        labels: assign(label_votes, &new_labels, &old_labels),
+    }
+}
+
+/// The line by line diff of the text of `old` and `new` after renaming `new` with [`align_names`]
+#[must_use]
+pub fn diff_functions(old: &Function, new: &Function) -> Vec<Edit> {
+    let new = align_names(old, new).apply(new);
+    let old_text = old.to_string();
+    let new_text = new.to_string();
+    let old_lines: Vec<_> = old_text.lines().collect();
+    let new_lines: Vec<_> = new_text.lines().collect();
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (pi, pj) in lcs(&old_lines, &new_lines)
+        .into_iter()
+        .chain(std::iter::once((old_lines.len(), new_lines.len())))
+    {
+        edits.extend(
+            old_lines[i..pi]
+                .iter()
+                .map(|l| Edit::Removed((*l).to_string())),
+        );
+        edits.extend(
+            new_lines[j..pj]
+                .iter()
+                .map(|l| Edit::Added((*l).to_string())),
+        );
+        if pi < old_lines.len() {
+            edits.push(Edit::Same(old_lines[pi].to_string()));
+        }
+        i = pi + 1;
+        j = pj + 1;
+    }
+    edits
+}
+
+// Writes the changes in `edits` as unified diff hunks with `context` unchanged lines around each change
+fn write_hunks(out: &mut String, name: &str, edits: &[Edit], context: usize) {
+    let changed: Vec<_> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| !matches!(e, Edit::Same(_)))
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut idx = 0;
+    while idx < changed.len() {
+        let start = changed[idx].saturating_sub(context);
+        let mut end = changed[idx] + 1;
+        // Merge any changes whose context would overlap into the same hunk
+        while idx + 1 < changed.len() && changed[idx + 1] <= end + 2 * context {
+            idx += 1;
+            end = changed[idx] + 1;
+        }
+        let end = (end + context).min(edits.len());
+
+        writeln!(out, "@@ @{name} @@").unwrap();
+        for edit in &edits[start..end] {
+            match edit {
+                Edit::Same(l) => writeln!(out, " {l}"),
+                Edit::Removed(l) => writeln!(out, "-{l}"),
+                Edit::Added(l) => writeln!(out, "+{l}"),
+            }
+            .unwrap();
+        }
+        idx += 1;
+    }
+}
+
+/// Diffs the functions of `old` and `new` with matching names, returning an empty string if every function is [`alpha_equivalent`]
+#[must_use]
+pub fn diff_programs(old: &Program, new: &Program, context: usize) -> String {
+    let mut out = String::new();
+    for old_func in &old.functions {
+        let edits = match new.functions.iter().find(|f| f.name == old_func.name) {
+            Some(new_func) if alpha_equivalent(old_func, new_func) => continue,
+            Some(new_func) => diff_functions(old_func, new_func),
+            None => old_func
+                .to_string()
+                .lines()
+                .map(|l| Edit::Removed(l.to_string()))
+                .collect(),
+        };
+        write_hunks(&mut out, &old_func.name, &edits, context);
+    }
+    for new_func in &new.functions {
+        if !old.functions.iter().any(|f| f.name == new_func.name) {
+            let edits: Vec<_> = new_func
+                .to_string()
+                .lines()
+                .map(|l| Edit::Added(l.to_string()))
+                .collect();
+            write_hunks(&mut out, &new_func.name, &edits, context);
+        }
+    }
+    out
+}
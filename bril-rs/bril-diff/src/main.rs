@@ -0,0 +1,31 @@
+use std::fs::File;
+
+use bril_diff::cli::Cli;
+use bril_diff::diff_programs;
+use bril_rs::{Program, load_abstract_program_from_read};
+use bril2json::parse_abstract_program;
+use clap::Parser;
+
+// Loads either a Bril text file or a Bril JSON file depending on its extension
+fn load(path: &str) -> Program {
+    let program = if std::path::Path::new(path)
+        .extension()
+        .is_some_and(|e| e == "bril")
+    {
+        parse_abstract_program(false, false, Some(path.to_string()))
+    } else {
+        load_abstract_program_from_read(File::open(path).unwrap())
+    };
+    program.try_into().unwrap()
+}
+
+fn main() {
+    let args = Cli::parse();
+    let diff = diff_programs(&load(&args.old), &load(&args.new), args.context);
+    if !diff.is_empty() {
+        println!("--- {}", args.old);
+        println!("+++ {}", args.new);
+        print!("{diff}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,12 @@
+use bril_rs::extensions::Extension;
+use clap::Parser;
+
+#[derive(Parser)]
+#[command(about, version, author)] // keeps the cli synced with Cargo.toml
+pub struct Cli {
+    /// The extensions the program is allowed to use. If none are given, any extension is allowed.
+    pub supported: Vec<Extension>,
+    /// The bril file to check, either text or JSON. A JSON program on stdin is assumed if no file is provided.
+    #[arg(short, long)]
+    pub file: Option<String>,
+}
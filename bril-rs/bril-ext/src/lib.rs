@@ -0,0 +1,24 @@
+#![warn(clippy::all, clippy::pedantic, clippy::nursery, clippy::cargo)]
+#![warn(missing_docs)]
+#![warn(clippy::allow_attributes)]
+#![doc = include_str!("../README.md")]
+
+#[doc(hidden)]
+pub mod cli;
+
+use std::fmt::Write;
+
+use bril_rs::extensions::ExtensionUsage;
+
+/// Prints each extension with what uses it followed by every operation in the program
+#[must_use]
+pub fn report(usage: &ExtensionUsage) -> String {
+    let mut out = String::new();
+    for (ext, uses) in &usage.extensions {
+        let uses: Vec<_> = uses.iter().map(String::as_str).collect();
+        writeln!(out, "{ext}: {}", uses.join(" ")).unwrap();
+    }
+    let ops: Vec<_> = usage.ops.iter().map(String::as_str).collect();
+    writeln!(out, "ops: {}", ops.join(" ")).unwrap();
+    out
+}
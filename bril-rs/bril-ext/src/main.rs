@@ -0,0 +1,38 @@
+use std::fs::File;
+
+use bril_ext::cli::Cli;
+use bril_ext::report;
+use bril_rs::extensions::ExtensionUsage;
+use bril_rs::{AbstractProgram, load_abstract_program, load_abstract_program_from_read};
+use bril2json::parse_abstract_program;
+use clap::Parser;
+
+// Loads either a Bril text file or a Bril JSON file depending on its extension
+fn load(path: &str) -> AbstractProgram {
+    if std::path::Path::new(path)
+        .extension()
+        .is_some_and(|e| e == "bril")
+    {
+        parse_abstract_program(false, false, Some(path.to_string()))
+    } else {
+        load_abstract_program_from_read(File::open(path).unwrap())
+    }
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let program = args
+        .file
+        .as_deref()
+        .map_or_else(load_abstract_program, load);
+    let usage = ExtensionUsage::from(&program);
+    print!("{}", report(&usage));
+
+    if !args.supported.is_empty()
+        && let Err(e) = usage.check(&args.supported)
+    {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
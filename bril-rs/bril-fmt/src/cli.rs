@@ -0,0 +1,14 @@
+use clap::Parser;
+
+#[derive(Parser)]
+#[command(about, version, author)] // keeps the cli synced with Cargo.toml
+pub struct Cli {
+    /// The bril files to format. stdin is assumed if no files are provided.
+    pub files: Vec<String>,
+    /// Exit with an error if any of the files is not formatted instead of printing them
+    #[arg(short, long, action, conflicts_with = "write")]
+    pub check: bool,
+    /// Rewrite the files in place instead of printing them
+    #[arg(short, long, action)]
+    pub write: bool,
+}
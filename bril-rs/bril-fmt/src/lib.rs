@@ -0,0 +1,100 @@
+#![warn(clippy::all, clippy::pedantic, clippy::nursery, clippy::cargo)]
+#![warn(missing_docs)]
+#![warn(clippy::allow_attributes)]
+#![doc = include_str!("../README.md")]
+
+#[doc(hidden)]
+pub mod cli;
+
+use bril_rs::{AbstractCode, AbstractFunction, AbstractInstruction, AbstractProgram};
+use bril2json::parse_abstract_program_from_read_with_comments;
+
+/// Formats the Bril text in `input`, keeping its comments
+/// # Panics
+/// Will panic if the input is not well-formed Bril text
+#[must_use]
+pub fn format_text(input: &str) -> String {
+    let program =
+        parse_abstract_program_from_read_with_comments(input.as_bytes(), false, false, None);
+    format_program(program)
+        .to_string()
+        .lines()
+        .map(|line| format_float_const(line) + "\n")
+        .collect()
+}
+
+/// Puts an [`AbstractProgram`] into canonical form for printing as formatted Bril text
+#[must_use]
+pub fn format_program(mut program: AbstractProgram) -> AbstractProgram {
+    // A stable sort keeps the order of multiple imports from the same file
+    program.imports.sort_by(|a, b| a.path.cmp(&b.path));
+    for i in &mut program.imports {
+        trim_start(&mut i.comments.leading);
+    }
+
+    let has_imports = !program.imports.is_empty();
+    for (idx, func) in program.functions.iter_mut().enumerate() {
+        format_function(func, has_imports || idx != 0);
+    }
+
+    trim_end(&mut program.end_comments);
+    program
+}
+
+fn format_function(func: &mut AbstractFunction, blank_line_before: bool) {
+    trim_start(&mut func.comments.leading);
+    if blank_line_before {
+        func.comments.leading.insert(0, String::new());
+    }
+
+    for (idx, code) in func.instrs.iter_mut().enumerate() {
+        let comments = match code {
+            AbstractCode::Label { comments, .. }
+            | AbstractCode::Instruction(
+                AbstractInstruction::Constant { comments, .. }
+                | AbstractInstruction::Value { comments, .. }
+                | AbstractInstruction::Effect { comments, .. },
+            ) => comments,
+        };
+        if idx == 0 {
+            trim_start(&mut comments.leading);
+        }
+    }
+
+    if func.instrs.is_empty() {
+        trim_start(&mut func.end_comments.leading);
+    }
+    trim_end(&mut func.end_comments.leading);
+}
+
+// Float constants with integral values like `1` are printed as `1.0`.
+// `Literal`'s `Display` prints these without a decimal point, so this is done on the formatted text.
+fn format_float_const(line: &str) -> String {
+    if !line.trim_start().starts_with('#')
+        && let Some((instr, rest)) = line.split_once(": float = const ")
+        && let Some((value, rest)) = rest.split_once(';')
+        && !value.is_empty()
+        && value
+            .strip_prefix('-')
+            .unwrap_or(value)
+            .chars()
+            .all(|c| c.is_ascii_digit())
+    {
+        format!("{instr}: float = const {value}.0;{rest}")
+    } else {
+        line.to_string()
+    }
+}
+
+// Removes blank lines from the start of a list of comment lines
+fn trim_start(lines: &mut Vec<String>) {
+    let blank = lines.iter().take_while(|l| l.is_empty()).count();
+    lines.drain(..blank);
+}
+
+// Removes blank lines from the end of a list of comment lines
+fn trim_end(lines: &mut Vec<String>) {
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+}
@@ -0,0 +1,46 @@
+use std::io::Read;
+
+use bril_fmt::cli::Cli;
+use bril_fmt::format_text;
+use clap::Parser;
+
+fn main() {
+    let args = Cli::parse();
+
+    if args.files.is_empty() {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input).unwrap();
+        let output = format_text(&input);
+        if args.check {
+            if output != input {
+                eprintln!("stdin is not formatted");
+                std::process::exit(1);
+            }
+        } else {
+            print!("{output}");
+        }
+        return;
+    }
+
+    let mut unformatted = false;
+    for file in &args.files {
+        let input = std::fs::read_to_string(file).unwrap();
+        let output = format_text(&input);
+        if args.check {
+            if output != input {
+                eprintln!("{file} is not formatted");
+                unformatted = true;
+            }
+        } else if args.write {
+            if output != input {
+                std::fs::write(file, output).unwrap();
+            }
+        } else {
+            print!("{output}");
+        }
+    }
+
+    if unformatted {
+        std::process::exit(1);
+    }
+}
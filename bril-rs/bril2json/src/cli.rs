@@ -9,4 +9,7 @@ pub struct Cli {
     /// Flag for whether position information should be included
     #[arg(short, action = Count)]
     pub position: u8,
+    /// Flag for whether comments should be attached to the code they describe
+    #[arg(short, long, action)]
+    pub comments: bool,
 }
@@ -9,8 +9,10 @@ pub mod bril_grammar;
 #[doc(hidden)]
 pub mod cli;
 use std::fs::File;
+use std::sync::LazyLock;
 
-use bril_rs::{AbstractProgram, ColRow, Position};
+use bril_rs::{AbstractProgram, ColRow, Comments, Position};
+use regex::Regex;
 
 /// A helper function for processing the accepted Bril characters from their text representation
 #[must_use]
@@ -34,9 +36,22 @@ pub fn escape_control_chars(s: &str) -> Option<char> {
 pub struct Lines {
     use_pos: bool,
     with_end: bool,
+    use_comments: bool,
     #[expect(clippy::struct_field_names, reason = "Names are hard")]
     new_lines: Vec<usize>,
     src_name: Option<String>,
+    // Only filled in when `use_comments` is set
+    src: String,
+    comments: Vec<Comment>,
+}
+
+// The byte range of a `#` comment in the source text, not including the line ending
+#[derive(Clone)]
+struct Comment {
+    start: usize,
+    end: usize,
+    // Whether only whitespace precedes the comment on its line
+    own_line: bool,
 }
 
 // For use in the parser
@@ -47,11 +62,25 @@ enum ParsingArgs {
 }
 
 impl Lines {
-    fn new(input: &str, use_pos: bool, with_end: bool, src_name: Option<String>) -> Self {
+    fn new(
+        input: &str,
+        use_pos: bool,
+        with_end: bool,
+        use_comments: bool,
+        src_name: Option<String>,
+    ) -> Self {
+        let (src, comments) = if use_comments {
+            (input.to_string(), find_comments(input))
+        } else {
+            (String::new(), Vec::new())
+        };
         Self {
             use_pos,
             with_end,
+            use_comments,
             src_name,
+            src,
+            comments,
             new_lines: input
                 .as_bytes()
                 .iter()
@@ -77,6 +106,63 @@ impl Lines {
         }
     }
 
+    // Collects the comment lines and blank lines between the previous piece of code and `index`
+    fn get_leading(&self, index: usize) -> Vec<String> {
+        let mut leading = Vec::new();
+        let mut end = index;
+        let mut next = self.comments.partition_point(|c| c.start < index);
+        while next > 0 {
+            let c = &self.comments[next - 1];
+            let gap = &self.src[c.end..end];
+            if !c.own_line || !gap.trim().is_empty() {
+                break;
+            }
+            if gap.matches('\n').count() > 1 {
+                leading.push(String::new());
+            }
+            leading.push(self.src[c.start..c.end].trim_end().to_string());
+            end = c.start;
+            next -= 1;
+        }
+        // A blank line between the previous piece of code and the first comment, except at the start of the file
+        let prev_end = self.src[..end].trim_end().len();
+        if prev_end != 0 && self.src[prev_end..end].matches('\n').count() > 1 {
+            leading.push(String::new());
+        }
+        leading.reverse();
+        leading
+    }
+
+    // Finds a comment on the same line, after `index`
+    fn get_trailing(&self, index: usize) -> Option<String> {
+        let c = self
+            .comments
+            .get(self.comments.partition_point(|c| c.start < index))?;
+        if c.own_line || !self.src[index..c.start].trim().is_empty() {
+            return None;
+        }
+        Some(self.src[c.start..c.end].trim_end().to_string())
+    }
+
+    fn get_comments(&self, starting_index: usize, ending_index: usize) -> Comments {
+        if self.use_comments {
+            Comments {
+                leading: self.get_leading(starting_index),
+                trailing: self.get_trailing(ending_index),
+            }
+        } else {
+            Comments::default()
+        }
+    }
+
+    fn get_end_comments(&self) -> Vec<String> {
+        if self.use_comments {
+            self.get_leading(self.src.len())
+        } else {
+            Vec::new()
+        }
+    }
+
     fn get_row_col(&self, index: usize) -> Option<ColRow> {
         if self.use_pos {
             Some(
@@ -112,13 +198,55 @@ impl Lines {
     }
 }
 
+// Matches comments, along with the string and character literals which may contain a `#`
+static COMMENT_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#""[^"]*"|'\\[0abtnvfr]'|'.'|#[^\n\r]*"#).unwrap());
+
+// Finds every comment in `input`, skipping over the string and character literals which may contain a `#`
+fn find_comments(input: &str) -> Vec<Comment> {
+    COMMENT_RE
+        .find_iter(input)
+        .filter(|m| m.as_str().starts_with('#'))
+        .map(|m| {
+            let line_start = input[..m.start()].rfind(['\n', '\r']).map_or(0, |i| i + 1);
+            Comment {
+                start: m.start(),
+                end: m.end(),
+                own_line: input[line_start..m.start()].trim().is_empty(),
+            }
+        })
+        .collect()
+}
+
 /// The entrance point to the bril2json parser. It takes an ```input```:[`std::io::Read`] which should be the Bril text file. You can control whether it includes source code positions with ```use_pos```.
 /// # Panics
 /// Will panic if the input is not well-formed Bril text
 pub fn parse_abstract_program_from_read<R: std::io::Read>(
+    input: R,
+    use_pos: bool,
+    with_end: bool,
+    file_name: Option<String>,
+) -> AbstractProgram {
+    parse_from_read(input, use_pos, with_end, false, file_name)
+}
+
+/// Like [`parse_abstract_program_from_read`], but comments and blank lines are attached to the code they describe so that printing the program keeps them.
+/// # Panics
+/// Will panic if the input is not well-formed Bril text
+pub fn parse_abstract_program_from_read_with_comments<R: std::io::Read>(
+    input: R,
+    use_pos: bool,
+    with_end: bool,
+    file_name: Option<String>,
+) -> AbstractProgram {
+    parse_from_read(input, use_pos, with_end, true, file_name)
+}
+
+fn parse_from_read<R: std::io::Read>(
     mut input: R,
     use_pos: bool,
     with_end: bool,
+    use_comments: bool,
     file_name: Option<String>,
 ) -> AbstractProgram {
     let mut buffer = String::new();
@@ -128,10 +256,21 @@ pub fn parse_abstract_program_from_read<R: std::io::Read>(
     let src_name = file_name.map(|f| std::fs::canonicalize(f).unwrap().display().to_string());
 
     parser
-        .parse(&Lines::new(&buffer, use_pos, with_end, src_name), &buffer)
+        .parse(
+            &Lines::new(&buffer, use_pos, with_end, use_comments, src_name),
+            &buffer,
+        )
         .unwrap()
 }
 
+// Opens `file_name`, or stdin if there is none
+fn open_input(file_name: Option<&String>) -> Box<dyn std::io::Read> {
+    file_name.map_or_else(
+        || -> Box<dyn std::io::Read> { Box::new(std::io::stdin()) },
+        |f| Box::new(File::open(f).unwrap()),
+    )
+}
+
 #[must_use]
 /// A wrapper around [`parse_abstract_program_from_read`] which assumes [`std::io::Stdin`] if `file_name` is [`None`]
 /// # Panics
@@ -141,10 +280,22 @@ pub fn parse_abstract_program(
     with_end: bool,
     file_name: Option<String>,
 ) -> AbstractProgram {
-    let input = file_name.clone().map_or_else(
-        || -> Box<dyn std::io::Read> { Box::new(std::io::stdin()) },
-        |f| Box::new(File::open(f).unwrap()),
-    );
+    parse_abstract_program_from_read(open_input(file_name.as_ref()), use_pos, with_end, file_name)
+}
 
-    parse_abstract_program_from_read(input, use_pos, with_end, file_name)
+#[must_use]
+/// A wrapper around [`parse_abstract_program_from_read_with_comments`] which assumes [`std::io::Stdin`] if `file_name` is [`None`]
+/// # Panics
+/// Will panic if the input is not well-formed Bril text or if `file_name` does not exist
+pub fn parse_abstract_program_with_comments(
+    use_pos: bool,
+    with_end: bool,
+    file_name: Option<String>,
+) -> AbstractProgram {
+    parse_abstract_program_from_read_with_comments(
+        open_input(file_name.as_ref()),
+        use_pos,
+        with_end,
+        file_name,
+    )
 }
@@ -1,13 +1,14 @@
 use bril_rs::output_abstract_program;
 use bril2json::cli::Cli;
-use bril2json::parse_abstract_program;
+use bril2json::{parse_abstract_program, parse_abstract_program_with_comments};
 use clap::Parser;
 
 fn main() {
     let args = Cli::parse();
-    output_abstract_program(&parse_abstract_program(
-        args.position >= 1,
-        args.position >= 2,
-        args.file,
-    ))
+    let parse = if args.comments {
+        parse_abstract_program_with_comments
+    } else {
+        parse_abstract_program
+    };
+    output_abstract_program(&parse(args.position >= 1, args.position >= 2, args.file))
 }
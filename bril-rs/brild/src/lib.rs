@@ -48,6 +48,7 @@ fn mangle_instr(code: AbstractCode, name_resolution_map: &HashMap<String, String
             labels,
             pos,
             op_type,
+            comments,
         }) => AbstractCode::Instruction(AbstractInstruction::Value {
             op,
             funcs: funcs
@@ -64,6 +65,7 @@ fn mangle_instr(code: AbstractCode, name_resolution_map: &HashMap<String, String
             labels,
             pos,
             op_type,
+            comments,
         }),
         AbstractCode::Instruction(AbstractInstruction::Effect {
             op,
@@ -71,6 +73,7 @@ fn mangle_instr(code: AbstractCode, name_resolution_map: &HashMap<String, String
             args,
             labels,
             pos,
+            comments,
         }) => AbstractCode::Instruction(AbstractInstruction::Effect {
             op,
             funcs: funcs
@@ -85,6 +88,7 @@ fn mangle_instr(code: AbstractCode, name_resolution_map: &HashMap<String, String
             args,
             labels,
             pos,
+            comments,
         }),
         _ => code,
     }
@@ -97,6 +101,8 @@ fn mangle_function(
         instrs,
         pos,
         return_type,
+        comments,
+        end_comments,
     }: AbstractFunction,
     name_resolution_map: &HashMap<String, String>,
     is_toplevel: bool,
@@ -114,6 +120,8 @@ fn mangle_function(
             .collect(),
         pos,
         return_type,
+        comments,
+        end_comments,
     }
 }
 
@@ -171,6 +179,7 @@ pub fn handle_program<S: BuildHasher>(
     // Do mangling
     let mangled_program = AbstractProgram {
         imports: Vec::new(),
+        end_comments: Vec::new(),
         functions: program
             .functions
             .into_iter()
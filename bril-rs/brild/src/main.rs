@@ -24,6 +24,7 @@ fn main() -> Result<(), BrildError> {
         AbstractProgram {
             imports: Vec::new(),
             functions: Vec::new(),
+            end_comments: Vec::new(),
         },
         |mut acc, (_, p)| {
             acc.functions.append(&mut p.unwrap().functions);
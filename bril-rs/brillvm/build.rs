@@ -0,0 +1,48 @@
+use std::{env, path::PathBuf, process::Command};
+
+// Builds the runtime library into LLVM bitcode so that it can be embedded into brillvm
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let runtime_dir = manifest_dir.join("runtime");
+    // A separate target directory avoids waiting on the lock held by the cargo building brillvm
+    let target_dir = out_dir.join("runtime");
+
+    println!(
+        "cargo::rerun-if-changed={}",
+        runtime_dir.join("src").display()
+    );
+    println!(
+        "cargo::rerun-if-changed={}",
+        runtime_dir.join("Cargo.toml").display()
+    );
+
+    let status = Command::new(env::var("CARGO").unwrap())
+        .args(["rustc", "--lib", "--release", "--manifest-path"])
+        .arg(runtime_dir.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .args(["--", "--emit=llvm-bc"])
+        // These are meant for brillvm and not the runtime
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .env_remove("RUSTFLAGS")
+        .status()
+        .unwrap();
+    assert!(status.success(), "Failed to build the runtime library");
+
+    // Like `make rt`, take the newest bitcode file in case there are stale ones around
+    let bitcode = std::fs::read_dir(target_dir.join("release").join("deps"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension().is_some_and(|e| e == "bc")
+                && path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .starts_with("runtime-")
+        })
+        .max_by_key(|path| path.metadata().unwrap().modified().unwrap())
+        .expect("The runtime library did not produce any bitcode");
+    std::fs::copy(bitcode, out_dir.join("rt.bc")).unwrap();
+}
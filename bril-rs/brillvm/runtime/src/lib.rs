@@ -1,7 +1,7 @@
 #![no_std]
 
 use core::ffi::{CStr, c_char};
-use libc::{strtoll, strtod};
+use libc::{strtod, strtoll};
 
 #[unsafe(no_mangle)]
 pub extern "C" fn _bril_print_int(i: i64) {
@@ -45,6 +45,21 @@ pub extern "C" fn _bril_print_float(f: f64) {
     }
 }
 
+#[unsafe(no_mangle)]
+pub extern "C" fn _bril_print_char(c: u32) {
+    let mut buf = [0_u8; 4];
+    // Print byte by byte so that '\0' is not mistaken for the end of a string
+    for b in char::from_u32(c)
+        .unwrap_or_default()
+        .encode_utf8(&mut buf)
+        .bytes()
+    {
+        unsafe {
+            libc::putchar(b.into());
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn _bril_print_sep() {
     unsafe {
@@ -78,3 +93,58 @@ pub unsafe extern "C" fn _bril_parse_bool(arg: *const c_char) -> bool {
 pub unsafe extern "C" fn _bril_parse_float(arg: *const c_char) -> f64 {
     unsafe { strtod(arg, core::ptr::null_mut()) }
 }
+
+// Reports an error in the same format as brilirs and exits with its error code
+fn error(msg: &CStr) -> ! {
+    unsafe {
+        libc::fflush(core::ptr::null_mut());
+        libc::write(2, c"error: ".as_ptr().cast(), 7);
+        libc::write(2, msg.as_ptr().cast(), msg.count_bytes());
+        libc::write(2, c"\n".as_ptr().cast(), 1);
+        libc::exit(2)
+    }
+}
+
+#[unsafe(no_mangle)]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn _bril_parse_char(arg: *const c_char) -> u32 {
+    let c_str = unsafe { CStr::from_ptr(arg) };
+    let c = match c_str.to_bytes() {
+        b"\\0" => Some('\u{0000}'),
+        b"\\a" => Some('\u{0007}'),
+        b"\\b" => Some('\u{0008}'),
+        b"\\t" => Some('\u{0009}'),
+        b"\\n" => Some('\u{000A}'),
+        b"\\v" => Some('\u{000B}'),
+        b"\\f" => Some('\u{000C}'),
+        b"\\r" => Some('\u{000D}'),
+        s => core::str::from_utf8(s).ok().and_then(|s| {
+            let mut chars = s.chars();
+            chars.next().filter(|_| chars.next().is_none())
+        }),
+    };
+    match c {
+        Some(c) => c.into(),
+        None => error(c"char must have one character"),
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _bril_int2char(i: i64) -> u32 {
+    match u32::try_from(i).ok().and_then(char::from_u32) {
+        Some(c) => c.into(),
+        None => {
+            // Room for "value " and any i64 and the rest of the message
+            let mut buf = [0_u8; 64];
+            unsafe {
+                libc::snprintf(
+                    buf.as_mut_ptr().cast(),
+                    buf.len(),
+                    c"value %lld cannot be converted to char".as_ptr().cast(),
+                    i,
+                );
+                error(CStr::from_ptr(buf.as_ptr().cast()))
+            }
+        }
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use bril_rs::{
+    Code, ConstOps, EffectOps, Function, Instruction, Literal, Position, Program, Type, ValueOps,
+};
+use thiserror::Error;
+
+use crate::llvm::{Types, block_types, is_terminating_instr};
+
+/// The reasons a Bril program can not be compiled by brillvm
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum CheckError {
+    /// More than one function has this name
+    #[error("multiple functions named `@{0}`")]
+    DuplicateFunction(String),
+    /// More than one label in a function has this name
+    #[error("multiple labels named `.{0}`")]
+    DuplicateLabel(String),
+    /// A function with this name does not exist
+    #[error("no function named `@{0}`")]
+    FuncNotFound(String),
+    /// A label with this name does not exist in the function
+    #[error("no label named `.{0}`")]
+    LabelNotFound(String),
+    /// A variable with this name is never assigned in the function
+    #[error("undefined variable `{0}`")]
+    VarUndefined(String),
+    /// (expected, actual)
+    #[error("expected {0} arguments, found {1}")]
+    BadNumArgs(usize, usize),
+    /// (expected, actual)
+    #[error("expected {0} labels, found {1}")]
+    BadNumLabels(usize, usize),
+    /// (expected, actual)
+    #[error("expected {0} functions, found {1}")]
+    BadNumFuncs(usize, usize),
+    /// (variable, expected, actual)
+    #[error("expected `{0}` to have type `{1}`, found `{2}`")]
+    BadArgType(String, Type, Type),
+    /// (expected, actual)
+    #[error("expected the result to have type `{0}`, found `{1}`")]
+    BadDestType(Type, Type),
+    /// (variable, actual)
+    #[error("expected `{0}` to be a pointer, found `{1}`")]
+    ExpectedPointer(String, Type),
+    /// (type, literal)
+    #[error("a constant of type `{0}` can not have the value `{1}`")]
+    BadConstType(Type, Literal),
+    /// A call to a function which does not return anything is used as a value
+    #[error("`@{0}` does not return a value")]
+    NoReturnValue(String),
+    /// A value is returned from a function which does not return anything
+    #[error("the function does not return a value")]
+    NonEmptyReturn,
+    /// No value is returned from a function which returns something
+    #[error("expected a return value of type `{0}`")]
+    MissingReturnValue(Type),
+    /// The name of the function clashes with a function that brillvm generates
+    #[error("unsupported: `@{0}` clashes with a name that brillvm uses")]
+    ReservedName(String),
+    /// `@main` can only take arguments which can be parsed from the command line
+    #[error("unsupported: `@main` can not take the pointer argument `{0}`")]
+    PointerMainArg(String),
+    /// The runtime has no way to print pointers
+    #[error("unsupported: can not print the pointer `{0}`")]
+    PrintPointer(String),
+    /// Brillvm needs to know which type a variable has at each use
+    #[error("unsupported: the type of `{0}` depends on the path taken to reach this use")]
+    AmbiguousType(String),
+}
+
+/// A [`CheckError`] along with where in the program it happened
+#[derive(Error, Debug, Clone, PartialEq)]
+pub struct PositionalCheckError {
+    /// The underlying error
+    pub e: CheckError,
+    /// The position of the instruction or function, if the program has positions
+    pub pos: Option<Position>,
+    /// The function the error is in
+    pub func: String,
+}
+
+impl Display for PositionalCheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(pos) = &self.pos {
+            write!(f, "Line {}, Column {}: ", pos.pos.row, pos.pos.col)?;
+        }
+        write!(f, "{} in @{}", self.e, self.func)
+    }
+}
+
+/// Checks that `prog` is well typed and only uses what brillvm can compile
+///
+/// This follows the same rules as [`crate::llvm::create_module_from_program`] so that any program which passes can be compiled without panicking.
+/// # Errors
+/// Returns every error found, in the order that they occur in the program
+pub fn check_program(prog: &Program) -> Result<(), Vec<PositionalCheckError>> {
+    let mut errors = Vec::new();
+    let mut funcs = HashMap::new();
+    for func in &prog.functions {
+        let error = |e| PositionalCheckError {
+            e,
+            pos: func.pos.clone(),
+            func: func.name.clone(),
+        };
+        if funcs.insert(func.name.as_str(), func).is_some() {
+            errors.push(error(CheckError::DuplicateFunction(func.name.clone())));
+        }
+        // `@main` is renamed to `_main` and replaced by an entry point which parses its arguments
+        if func.name == "_main" || func.name.starts_with("_bril_") {
+            errors.push(error(CheckError::ReservedName(func.name.clone())));
+        }
+        if func.name == "main" {
+            errors.extend(
+                func.args
+                    .iter()
+                    .filter(|a| matches!(a.arg_type, Type::Pointer(_)))
+                    .map(|a| error(CheckError::PointerMainArg(a.name.clone()))),
+            );
+        }
+    }
+
+    for func in &prog.functions {
+        FuncChecker::new(&funcs, func).check(&mut errors);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+struct FuncChecker<'a> {
+    funcs: &'a HashMap<&'a str, &'a Function>,
+    func: &'a Function,
+    labels: HashSet<&'a String>,
+    // Every type that each variable is assigned in the function, which is how brillvm allocates them
+    slots: HashMap<&'a String, Vec<&'a Type>>,
+    // The type of each variable at the instruction being checked
+    current: Types<'a>,
+}
+
+impl<'a> FuncChecker<'a> {
+    fn new(funcs: &'a HashMap<&'a str, &'a Function>, func: &'a Function) -> Self {
+        let mut slots: HashMap<_, Vec<_>> = HashMap::new();
+        let dests = func.instrs.iter().filter_map(|code| match code {
+            Code::Instruction(
+                Instruction::Constant {
+                    dest,
+                    const_type: ty,
+                    ..
+                }
+                | Instruction::Value {
+                    dest, op_type: ty, ..
+                },
+            ) => Some((dest, ty)),
+            _ => None,
+        });
+        for (name, ty) in func
+            .args
+            .iter()
+            .map(|a| (&a.name, &a.arg_type))
+            .chain(dests)
+        {
+            let types = slots.entry(name).or_default();
+            if !types.contains(&ty) {
+                types.push(ty);
+            }
+        }
+        Self {
+            funcs,
+            func,
+            labels: HashSet::new(),
+            slots,
+            current: Types::new(),
+        }
+    }
+
+    fn check(mut self, errors: &mut Vec<PositionalCheckError>) {
+        for code in &self.func.instrs {
+            if let Code::Label { label, pos, .. } = code
+                && !self.labels.insert(label)
+            {
+                errors.push(PositionalCheckError {
+                    e: CheckError::DuplicateLabel(label.clone()),
+                    pos: pos.clone(),
+                    func: self.func.name.clone(),
+                });
+            }
+        }
+
+        let types = block_types(&self.func.args, &self.func.instrs);
+        self.current = self
+            .func
+            .args
+            .iter()
+            .map(|a| (&a.name, Some(a.arg_type.clone())))
+            .collect();
+        let mut last_instr = None;
+        for code in &self.func.instrs {
+            match code {
+                Code::Label { label, .. } => {
+                    self.current = types.get(label).cloned().unwrap_or_default();
+                    last_instr = None;
+                }
+                // Unreachable instructions are skipped when building the function
+                Code::Instruction(_) if is_terminating_instr(last_instr) => {}
+                Code::Instruction(i) => {
+                    if let Err(e) = self.instruction(i) {
+                        errors.push(PositionalCheckError {
+                            e,
+                            pos: i.get_pos(),
+                            func: self.func.name.clone(),
+                        });
+                    }
+                    if let Instruction::Constant {
+                        dest,
+                        const_type: ty,
+                        ..
+                    }
+                    | Instruction::Value {
+                        dest, op_type: ty, ..
+                    } = i
+                    {
+                        self.current.insert(dest, Some(ty.clone()));
+                    }
+                    last_instr = Some(i);
+                }
+            }
+        }
+    }
+
+    // The type of `name` at this point, following the same rules as brillvm uses to pick its stack location
+    fn var(&self, name: &String) -> Result<Type, CheckError> {
+        match self.slots.get(name).map(Vec::as_slice) {
+            None => Err(CheckError::VarUndefined(name.clone())),
+            Some([ty]) => Ok((*ty).clone()),
+            Some(_) => match self.current.get(name) {
+                Some(Some(ty)) => Ok(ty.clone()),
+                _ => Err(CheckError::AmbiguousType(name.clone())),
+            },
+        }
+    }
+
+    fn arg(&self, name: &String, expected: &Type) -> Result<(), CheckError> {
+        let ty = self.var(name)?;
+        if ty == *expected {
+            Ok(())
+        } else {
+            Err(CheckError::BadArgType(name.clone(), expected.clone(), ty))
+        }
+    }
+
+    fn pointer(&self, name: &String) -> Result<Type, CheckError> {
+        match self.var(name)? {
+            Type::Pointer(ty) => Ok(*ty),
+            ty => Err(CheckError::ExpectedPointer(name.clone(), ty)),
+        }
+    }
+
+    fn label(&self, label: &String) -> Result<(), CheckError> {
+        if self.labels.contains(label) {
+            Ok(())
+        } else {
+            Err(CheckError::LabelNotFound(label.clone()))
+        }
+    }
+
+    // Checks the arguments of a call to `@name`, returning its return type
+    fn call(&self, funcs: &[String], args: &[String]) -> Result<Option<&'a Type>, CheckError> {
+        num(1, funcs.len(), CheckError::BadNumFuncs)?;
+        let callee = self
+            .funcs
+            .get(funcs[0].as_str())
+            .ok_or_else(|| CheckError::FuncNotFound(funcs[0].clone()))?;
+        num(callee.args.len(), args.len(), CheckError::BadNumArgs)?;
+        for (arg, param) in args.iter().zip(&callee.args) {
+            self.arg(arg, &param.arg_type)?;
+        }
+        Ok(callee.return_type.as_ref())
+    }
+
+    fn instruction(&self, i: &Instruction) -> Result<(), CheckError> {
+        match i {
+            Instruction::Constant {
+                dest: _,
+                op: ConstOps::Const,
+                const_type,
+                value,
+                pos: _,
+            } => match (const_type, value) {
+                (Type::Int | Type::Float, Literal::Int(_))
+                | (Type::Bool, Literal::Bool(_))
+                | (Type::Float, Literal::Float(_))
+                | (Type::Char, Literal::Char(_)) => Ok(()),
+                _ => Err(CheckError::BadConstType(const_type.clone(), value.clone())),
+            },
+            Instruction::Value {
+                args,
+                dest: _,
+                funcs,
+                labels,
+                op,
+                op_type,
+                pos: _,
+            } => {
+                num(0, labels.len(), CheckError::BadNumLabels)?;
+                if *op != ValueOps::Call {
+                    num(0, funcs.len(), CheckError::BadNumFuncs)?;
+                }
+                let (params, result) = match op {
+                    ValueOps::Add | ValueOps::Sub | ValueOps::Mul | ValueOps::Div => {
+                        (vec![Type::Int, Type::Int], Type::Int)
+                    }
+                    ValueOps::Eq | ValueOps::Lt | ValueOps::Gt | ValueOps::Le | ValueOps::Ge => {
+                        (vec![Type::Int, Type::Int], Type::Bool)
+                    }
+                    ValueOps::Not => (vec![Type::Bool], Type::Bool),
+                    ValueOps::And | ValueOps::Or => (vec![Type::Bool, Type::Bool], Type::Bool),
+                    ValueOps::Id => (vec![op_type.clone()], op_type.clone()),
+                    ValueOps::Call => {
+                        let ty = self
+                            .call(funcs, args)?
+                            .ok_or_else(|| CheckError::NoReturnValue(funcs[0].clone()))?;
+                        (Vec::new(), ty.clone())
+                    }
+                    ValueOps::Fadd | ValueOps::Fsub | ValueOps::Fmul | ValueOps::Fdiv => {
+                        (vec![Type::Float, Type::Float], Type::Float)
+                    }
+                    ValueOps::Feq
+                    | ValueOps::Flt
+                    | ValueOps::Fgt
+                    | ValueOps::Fle
+                    | ValueOps::Fge => (vec![Type::Float, Type::Float], Type::Bool),
+                    ValueOps::Ceq
+                    | ValueOps::Clt
+                    | ValueOps::Cgt
+                    | ValueOps::Cle
+                    | ValueOps::Cge => (vec![Type::Char, Type::Char], Type::Bool),
+                    ValueOps::Char2int => (vec![Type::Char], Type::Int),
+                    ValueOps::Int2char => (vec![Type::Int], Type::Char),
+                    ValueOps::Alloc => {
+                        if !matches!(op_type, Type::Pointer(_)) {
+                            return Err(CheckError::BadDestType(
+                                Type::Pointer(Box::new(op_type.clone())),
+                                op_type.clone(),
+                            ));
+                        }
+                        (vec![Type::Int], op_type.clone())
+                    }
+                    ValueOps::Load => {
+                        num(1, args.len(), CheckError::BadNumArgs)?;
+                        (Vec::new(), self.pointer(&args[0])?)
+                    }
+                    ValueOps::PtrAdd => {
+                        num(2, args.len(), CheckError::BadNumArgs)?;
+                        let ty = Type::Pointer(Box::new(self.pointer(&args[0])?));
+                        (vec![ty.clone(), Type::Int], ty)
+                    }
+                    ValueOps::Get | ValueOps::Undef => (Vec::new(), op_type.clone()),
+                };
+                // Calls and loads have checked their own arguments
+                if !matches!(op, ValueOps::Call | ValueOps::Load) {
+                    num(params.len(), args.len(), CheckError::BadNumArgs)?;
+                    for (arg, ty) in args.iter().zip(&params) {
+                        self.arg(arg, ty)?;
+                    }
+                }
+                if result == *op_type {
+                    Ok(())
+                } else {
+                    Err(CheckError::BadDestType(result, op_type.clone()))
+                }
+            }
+            Instruction::Effect {
+                args,
+                funcs,
+                labels,
+                op,
+                pos: _,
+            } => {
+                if *op != EffectOps::Call {
+                    num(0, funcs.len(), CheckError::BadNumFuncs)?;
+                }
+                if !matches!(op, EffectOps::Jump | EffectOps::Branch) {
+                    num(0, labels.len(), CheckError::BadNumLabels)?;
+                }
+                match op {
+                    EffectOps::Jump => {
+                        num(0, args.len(), CheckError::BadNumArgs)?;
+                        num(1, labels.len(), CheckError::BadNumLabels)?;
+                        self.label(&labels[0])
+                    }
+                    EffectOps::Branch => {
+                        num(1, args.len(), CheckError::BadNumArgs)?;
+                        num(2, labels.len(), CheckError::BadNumLabels)?;
+                        self.arg(&args[0], &Type::Bool)?;
+                        self.label(&labels[0])?;
+                        self.label(&labels[1])
+                    }
+                    EffectOps::Call => self.call(funcs, args).map(|_| ()),
+                    EffectOps::Return => match (&self.func.return_type, args.as_slice()) {
+                        (None, []) => Ok(()),
+                        (None, _) => Err(CheckError::NonEmptyReturn),
+                        (Some(ty), [arg]) => self.arg(arg, ty),
+                        (Some(ty), []) => Err(CheckError::MissingReturnValue(ty.clone())),
+                        (Some(_), _) => Err(CheckError::BadNumArgs(1, args.len())),
+                    },
+                    EffectOps::Print => args.iter().try_for_each(|a| match self.var(a)? {
+                        Type::Pointer(_) => Err(CheckError::PrintPointer(a.clone())),
+                        _ => Ok(()),
+                    }),
+                    EffectOps::Nop => num(0, args.len(), CheckError::BadNumArgs),
+                    EffectOps::Store => {
+                        num(2, args.len(), CheckError::BadNumArgs)?;
+                        let ty = self.pointer(&args[0])?;
+                        self.arg(&args[1], &ty)
+                    }
+                    EffectOps::Free => {
+                        num(1, args.len(), CheckError::BadNumArgs)?;
+                        self.pointer(&args[0]).map(|_| ())
+                    }
+                    // The first argument names a shadow variable rather than a variable
+                    EffectOps::Set => {
+                        num(2, args.len(), CheckError::BadNumArgs)?;
+                        self.var(&args[1]).map(|_| ())
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn num(
+    expected: usize,
+    actual: usize,
+    error: fn(usize, usize) -> CheckError,
+) -> Result<(), CheckError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(error(expected, actual))
+    }
+}
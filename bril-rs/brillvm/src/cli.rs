@@ -1,4 +1,17 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+
+/// The kinds of output that brillvm can produce
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Emit {
+    /// Textual LLVM IR
+    Ll,
+    /// LLVM bitcode
+    Bc,
+    /// A native object file
+    Obj,
+    /// A native executable, linked with the system C compiler or `$CC`
+    Exe,
+}
 
 #[derive(Parser)]
 #[command(about, version, author)] // keeps the cli synced with Cargo.toml
@@ -8,7 +21,7 @@ pub struct Cli {
     #[arg(short, long, action)]
     pub file: Option<String>,
 
-    /// The path to the runtime library. Defaults to rt.bc
+    /// The path to a runtime library to use instead of the one built into brillvm
     #[arg(short, long, action)]
     pub runtime: Option<String>,
 
@@ -16,6 +29,30 @@ pub struct Cli {
     #[arg(short, long, action)]
     pub interpreter: bool,
 
+    /// The kind of output to produce
+    #[arg(long, value_enum, default_value_t = Emit::Ll)]
+    pub emit: Emit,
+
+    /// Where to write the output. Textual LLVM IR is written to stdout if this is not provided.
+    #[arg(short, long, action, required_if_eq_any([("emit", "bc"), ("emit", "obj"), ("emit", "exe")]))]
+    pub output: Option<String>,
+
+    /// The LLVM optimization level, from 0 to 3
+    #[arg(short = 'O', action, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=3))]
+    pub opt_level: u8,
+
+    /// A custom LLVM pass pipeline to run instead of the one for the optimization level, like "mem2reg,instcombine"
+    #[arg(short, long, action)]
+    pub passes: Option<String>,
+
+    /// Whether to emit DWARF debug info from the source positions in the program
+    #[arg(short = 'g', long, action)]
+    pub debug_info: bool,
+
+    /// The target triple to compile for. Defaults to the host.
+    #[arg(short, long, action)]
+    pub target: Option<String>,
+
     /// Arguments for the main function
     #[arg(action)]
     pub args: Vec<String>,
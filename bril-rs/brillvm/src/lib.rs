@@ -7,6 +7,9 @@
 // When you run with --all-targets, you also get --target=all which includes --target=redox. This pulls in redox_sys via a chain of deps through inkwell-parking_lot which uses an older version of bitflags 1.3.2. Given backwards compatibility, it's going to be a very long time, if ever, that this gets updated(because of msrv changes).
 #![allow(clippy::multiple_crate_versions)]
 
+/// Checks that a Bril program can be compiled before building it.
+pub mod check;
+
 #[doc(hidden)]
 pub mod cli;
 
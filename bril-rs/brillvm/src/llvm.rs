@@ -1,17 +1,25 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use inkwell::{
     AddressSpace, FloatPredicate, IntPredicate,
     basic_block::BasicBlock,
     builder::Builder,
     context::Context,
-    module::Module,
+    debug_info::{
+        AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants, DISubprogram,
+        DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder,
+    },
+    module::{FlagBehavior, Module},
     types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType},
     values::{BasicValueEnum, FloatValue, FunctionValue, IntValue, PointerValue},
 };
 
 use bril_rs::{
-    Argument, Code, ConstOps, EffectOps, Function, Instruction, Literal, Program, Type, ValueOps,
+    Argument, Code, ConstOps, EffectOps, Function, Instruction, Literal, Position, Program, Type,
+    ValueOps,
 };
 
 /// A helper function for performing operations over LLVM types
@@ -23,10 +31,13 @@ where
         Type::Int => fn_map(context.i64_type().into()),
         Type::Bool => fn_map(context.bool_type().into()),
         Type::Float => fn_map(context.f64_type().into()),
+        // Chars are stored as their unicode code point
+        Type::Char => fn_map(context.i32_type().into()),
         Type::Pointer(_) => fn_map(context.ptr_type(AddressSpace::default()).into()),
     }
 }
 
+// The checker only lets `alloc` and `ptradd` produce pointer types
 fn unwrap_bril_ptrtype(ty: &Type) -> &Type {
     match ty {
         Type::Pointer(ty) => ty,
@@ -78,10 +89,19 @@ impl<'a> WrappedPointer<'a> {
     }
 }
 
+// The type of each variable at some point in a function, or `None` if it depends on the path taken to get there
+pub(crate) type Types<'b> = HashMap<&'b String, Option<Type>>;
+
 #[derive(Default)]
 struct Heap<'a, 'b> {
-    // Map variable names in Bril to their type and location on the stack.
-    map: HashMap<&'b String, WrappedPointer<'a>>,
+    // Map variable names in Bril to their locations on the stack, one for each type the variable is assigned.
+    map: HashMap<&'b String, Vec<WrappedPointer<'a>>>,
+    // The shadow variables of the SSA extension, which are kept on the stack the same way so that `mem2reg` can turn them into phi nodes
+    shadows: HashMap<&'b String, Vec<WrappedPointer<'a>>>,
+    // The type of each variable at the instruction currently being built
+    current: Types<'b>,
+    // The type of the destination of the instruction currently being built
+    dest_ty: Option<Type>,
 }
 
 impl<'a, 'b> Heap<'a, 'b> {
@@ -96,28 +116,319 @@ impl<'a, 'b> Heap<'a, 'b> {
         name: &'b String,
         ty: &Type,
     ) -> WrappedPointer<'a> {
-        let result = self
-            .map
-            .entry(name)
-            .or_insert_with(|| WrappedPointer::new(builder, context, name, ty))
-            .clone();
-        if result.ty != *ty {
-            println!(
-                "`{}` had type `{}` but is now being assigned type `{}`",
-                name, result.ty, ty
+        add_slot(
+            self.map.entry(name).or_default(),
+            builder,
+            context,
+            name,
+            ty,
+        )
+    }
+
+    fn add_shadow(
+        &mut self,
+        builder: &'a Builder,
+        context: &'a Context,
+        name: &'b String,
+        ty: &Type,
+    ) -> WrappedPointer<'a> {
+        add_slot(
+            self.shadows.entry(name).or_default(),
+            builder,
+            context,
+            &format!("shadow.{name}"),
+            ty,
+        )
+    }
+
+    // The shadow variable of `name` for values of type `ty`, if anything ever reads it
+    fn get_shadow(&self, name: &String, ty: &Type) -> Option<WrappedPointer<'a>> {
+        self.shadows
+            .get(name)?
+            .iter()
+            .find(|slot| slot.ty == *ty)
+            .cloned()
+    }
+
+    // Record that `name` now holds a value of type `ty`
+    fn define(&mut self, name: &'b String, ty: &Type) {
+        self.current.insert(name, Some(ty.clone()));
+    }
+
+    fn get(&self, name: &String) -> WrappedPointer<'a> {
+        let slots = self.map.get(name).unwrap();
+        // Most variables only ever have one type
+        if let [slot] = slots.as_slice() {
+            return slot.clone();
+        }
+        match self.current.get(name) {
+            Some(Some(ty)) => slots.iter().find(|slot| slot.ty == *ty).unwrap().clone(),
+            // `check_program` rejects uses like this with `CheckError::AmbiguousType`, since it
+            // tracks types exactly the same way
+            _ => unreachable!("the type of `{name}` depends on the path taken"),
+        }
+    }
+
+    // Like `get` but for the destination of the instruction currently being built
+    fn get_dest(&self, name: &String) -> WrappedPointer<'a> {
+        self.map
+            .get(name)
+            .unwrap()
+            .iter()
+            .find(|slot| Some(&slot.ty) == self.dest_ty.as_ref())
+            .unwrap()
+            .clone()
+    }
+}
+
+// Finds the stack location in `slots` for values of type `ty`, allocating a new one if there isn't one yet
+fn add_slot<'a>(
+    slots: &mut Vec<WrappedPointer<'a>>,
+    builder: &'a Builder,
+    context: &'a Context,
+    name: &str,
+    ty: &Type,
+) -> WrappedPointer<'a> {
+    if let Some(slot) = slots.iter().find(|slot| slot.ty == *ty) {
+        slot.clone()
+    } else {
+        let slot = WrappedPointer::new(builder, context, name, ty);
+        slots.push(slot.clone());
+        slot
+    }
+}
+
+// Joins the types of variables coming from another block into `into`, returning whether anything changed
+fn join_types<'b>(into: &mut Types<'b>, from: &Types<'b>) -> bool {
+    let mut changed = false;
+    for (name, ty) in from {
+        match into.get(name) {
+            None => {
+                into.insert(*name, ty.clone());
+                changed = true;
+            }
+            Some(Some(t)) if Some(t) != ty.as_ref() => {
+                into.insert(*name, None);
+                changed = true;
+            }
+            Some(_) => {}
+        }
+    }
+    changed
+}
+
+// A forward dataflow analysis for the type of each variable at the start of each labelled block
+// This is only needed to pick the right stack location for variables that are assigned more than one type
+pub(crate) fn block_types<'b>(
+    args: &'b [Argument],
+    instrs: &'b [Code],
+) -> HashMap<&'b String, Types<'b>> {
+    struct Block<'b> {
+        label: Option<&'b String>,
+        defs: Vec<(&'b String, &'b Type)>,
+        succs: Vec<&'b String>,
+    }
+
+    let mut blocks = vec![Block {
+        label: None,
+        defs: Vec::new(),
+        succs: Vec::new(),
+    }];
+    let mut terminated = false;
+    for code in instrs {
+        let block = blocks.last_mut().unwrap();
+        match code {
+            Code::Label { label, .. } => {
+                if !terminated {
+                    block.succs.push(label);
+                }
+                blocks.push(Block {
+                    label: Some(label),
+                    defs: Vec::new(),
+                    succs: Vec::new(),
+                });
+                terminated = false;
+            }
+            // Unreachable instructions are skipped when building the function
+            Code::Instruction(_) if terminated => {}
+            Code::Instruction(
+                Instruction::Constant {
+                    dest,
+                    const_type: ty,
+                    ..
+                }
+                | Instruction::Value {
+                    dest, op_type: ty, ..
+                },
+            ) => block.defs.push((dest, ty)),
+            Code::Instruction(Instruction::Effect {
+                labels,
+                op: EffectOps::Jump | EffectOps::Branch,
+                ..
+            }) => {
+                block.succs.extend(labels);
+                terminated = true;
+            }
+            Code::Instruction(Instruction::Effect {
+                op: EffectOps::Return,
+                ..
+            }) => terminated = true,
+            Code::Instruction(Instruction::Effect { .. }) => {}
+        }
+    }
+
+    let entry: Types = args
+        .iter()
+        .map(|Argument { name, arg_type }| (name, Some(arg_type.clone())))
+        .collect();
+    let mut ins: HashMap<&String, Types> = HashMap::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in &blocks {
+            let mut types = block.label.map_or_else(
+                || entry.clone(),
+                |l| ins.get(l).cloned().unwrap_or_default(),
             );
-            unimplemented!(
-                "brillvm does not currently support variables within a function having different types. Implementing this might require a control flow analysis? Feel free to try and implement this."
-            )
+            for (dest, ty) in &block.defs {
+                types.insert(*dest, Some((*ty).clone()));
+            }
+            for succ in &block.succs {
+                changed |= join_types(ins.entry(*succ).or_default(), &types);
+            }
         }
-        result
     }
+    ins
+}
 
-    fn get(&self, name: &String) -> WrappedPointer<'a> {
-        self.map.get(name).unwrap().clone()
+// Builds the DWARF debug info which maps the generated code back to the Bril source positions
+struct DebugInfo<'a> {
+    builder: DebugInfoBuilder<'a>,
+    unit: DICompileUnit<'a>,
+    // The source file of any position which does not name one
+    source: PathBuf,
+}
+
+impl<'a> DebugInfo<'a> {
+    fn new(context: &'a Context, module: &Module<'a>, source: &Path) -> Self {
+        // LLVM drops debug info without these flags
+        if module.get_flag("Debug Info Version").is_none() {
+            module.add_basic_value_flag(
+                "Debug Info Version",
+                FlagBehavior::Warning,
+                context.i32_type().const_int(3, false),
+            );
+        }
+        if module.get_flag("Dwarf Version").is_none() {
+            module.add_basic_value_flag(
+                "Dwarf Version",
+                FlagBehavior::Warning,
+                context.i32_type().const_int(4, false),
+            );
+        }
+
+        let (file_name, directory) = split_path(source);
+        let (builder, unit) = module.create_debug_info_builder(
+            true,
+            // Bril does not have its own language code so pretend to be C like most small languages do
+            DWARFSourceLanguage::C,
+            &file_name,
+            &directory,
+            "brillvm",
+            false,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        Self {
+            builder,
+            unit,
+            source: source.to_path_buf(),
+        }
+    }
+
+    fn file(&self, pos: Option<&Position>) -> DIFile<'a> {
+        let path = pos
+            .and_then(|p| p.src.as_deref())
+            .map_or(self.source.as_path(), Path::new);
+        let (file_name, directory) = split_path(path);
+        self.builder.create_file(&file_name, &directory)
+    }
+
+    // Attaches a scope for the Bril function `name` to `llvm_func`
+    fn function(
+        &self,
+        llvm_func: FunctionValue<'a>,
+        name: &str,
+        pos: Option<&Position>,
+    ) -> DISubprogram<'a> {
+        let file = self.file(pos);
+        let (line, _) = line_col(pos);
+        let ty = self
+            .builder
+            .create_subroutine_type(file, None, &[], DIFlags::PUBLIC);
+        let subprogram = self.builder.create_function(
+            self.unit.as_debug_info_scope(),
+            name,
+            None,
+            file,
+            line,
+            ty,
+            true,
+            true,
+            line,
+            DIFlags::PUBLIC,
+            false,
+        );
+        llvm_func.set_subprogram(subprogram);
+        subprogram
+    }
+
+    // Any instructions built after this are attributed to `pos` in `scope`
+    fn set_location(
+        &self,
+        context: &'a Context,
+        builder: &Builder<'a>,
+        scope: DISubprogram<'a>,
+        pos: Option<&Position>,
+    ) {
+        let (line, col) = line_col(pos);
+        let location = self.builder.create_debug_location(
+            context,
+            line,
+            col,
+            scope.as_debug_info_scope(),
+            None,
+        );
+        builder.set_current_debug_location(location);
     }
 }
 
+fn split_path(path: &Path) -> (String, String) {
+    (
+        path.file_name()
+            .map_or_else(String::new, |f| f.to_string_lossy().into_owned()),
+        path.parent()
+            .map_or_else(String::new, |d| d.to_string_lossy().into_owned()),
+    )
+}
+
+// Line 0 is used by DWARF for code without a source location
+fn line_col(pos: Option<&Position>) -> (u32, u32) {
+    pos.map_or((0, 0), |p| {
+        (
+            u32::try_from(p.pos.row).unwrap_or(0),
+            u32::try_from(p.pos.col).unwrap_or(0),
+        )
+    })
+}
+
 #[derive(Default)]
 struct Fresh {
     count: u64,
@@ -153,7 +464,7 @@ fn build_op<'a, 'b>(
 ) {
     builder
         .build_store(
-            heap.get(dest).ptr,
+            heap.get_dest(dest).ptr,
             op(args
                 .iter()
                 .map(|n| build_load(context, builder, &heap.get(n), &fresh.fresh_var()))
@@ -208,11 +519,12 @@ fn build_instruction<'a, 'b>(
             op: ConstOps::Const,
             const_type: Type::Float,
             value: Literal::Int(i),
+            pos: _,
         } => {
             #[expect(clippy::cast_precision_loss)]
             builder
                 .build_store(
-                    heap.get(dest).ptr,
+                    heap.get_dest(dest).ptr,
                     context.f64_type().const_float(*i as f64),
                 )
                 .unwrap();
@@ -222,11 +534,12 @@ fn build_instruction<'a, 'b>(
             op: ConstOps::Const,
             const_type: _,
             value: Literal::Int(i),
+            pos: _,
         } => {
             #[expect(clippy::cast_sign_loss, reason = "u64 because of the C++/C API")]
             builder
                 .build_store(
-                    heap.get(dest).ptr,
+                    heap.get_dest(dest).ptr,
                     context.i64_type().const_int(*i as u64, true),
                 )
                 .unwrap();
@@ -236,10 +549,11 @@ fn build_instruction<'a, 'b>(
             op: ConstOps::Const,
             const_type: _,
             value: Literal::Bool(b),
+            pos: _,
         } => {
             builder
                 .build_store(
-                    heap.get(dest).ptr,
+                    heap.get_dest(dest).ptr,
                     context.bool_type().const_int((*b).into(), false),
                 )
                 .unwrap();
@@ -249,9 +563,24 @@ fn build_instruction<'a, 'b>(
             op: ConstOps::Const,
             const_type: _,
             value: Literal::Float(f),
+            pos: _,
+        } => {
+            builder
+                .build_store(heap.get_dest(dest).ptr, context.f64_type().const_float(*f))
+                .unwrap();
+        }
+        Instruction::Constant {
+            dest,
+            op: ConstOps::Const,
+            const_type: _,
+            value: Literal::Char(c),
+            pos: _,
         } => {
             builder
-                .build_store(heap.get(dest).ptr, context.f64_type().const_float(*f))
+                .build_store(
+                    heap.get_dest(dest).ptr,
+                    context.i32_type().const_int(u32::from(*c).into(), false),
+                )
                 .unwrap();
         }
         Instruction::Value {
@@ -261,6 +590,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Add,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -289,6 +619,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Sub,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -317,6 +648,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Mul,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -345,6 +677,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Div,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -373,6 +706,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Eq,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -402,6 +736,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Lt,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -431,6 +766,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Gt,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -460,6 +796,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Le,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -489,6 +826,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Ge,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -518,6 +856,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Not,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -542,6 +881,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::And,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -570,6 +910,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Or,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -598,6 +939,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Call,
             op_type: _,
+            pos: _,
         } => {
             let func_name = if funcs[0] == "main" {
                 "_main"
@@ -637,7 +979,36 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Id,
             op_type: _,
+            pos: _,
         } => build_op(context, builder, heap, fresh, |v| v[0], args, dest),
+        Instruction::Value {
+            args: _,
+            dest,
+            funcs: _,
+            labels: _,
+            op: ValueOps::Get,
+            op_type,
+            pos: _,
+        } => {
+            // The shadow variable is always allocated for a `get` so this can't fail
+            let shadow = heap.get_shadow(dest, op_type).unwrap();
+            builder
+                .build_store(
+                    heap.get_dest(dest).ptr,
+                    build_load(context, builder, &shadow, &fresh.fresh_var()),
+                )
+                .unwrap();
+        }
+        // Reading an undefined variable is undefined behaviour in Bril so there is nothing to store
+        Instruction::Value {
+            args: _,
+            dest: _,
+            funcs: _,
+            labels: _,
+            op: ValueOps::Undef,
+            op_type: _,
+            pos: _,
+        } => {}
         Instruction::Value {
             args,
             dest,
@@ -645,6 +1016,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Fadd,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -673,6 +1045,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Fsub,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -701,6 +1074,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Fmul,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -729,6 +1103,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Fdiv,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -757,6 +1132,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Feq,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -786,6 +1162,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Flt,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -815,6 +1192,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Fgt,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -844,6 +1222,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Fle,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -873,6 +1252,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Fge,
             op_type: _,
+            pos: _,
         } => {
             let ret_name = fresh.fresh_var();
             build_op(
@@ -895,11 +1275,220 @@ fn build_instruction<'a, 'b>(
                 dest,
             );
         }
+        Instruction::Value {
+            args,
+            dest,
+            funcs: _,
+            labels: _,
+            op: ValueOps::Ceq,
+            op_type: _,
+            pos: _,
+        } => {
+            let ret_name = fresh.fresh_var();
+            build_op(
+                context,
+                builder,
+                heap,
+                fresh,
+                |v| {
+                    builder
+                        .build_int_compare::<IntValue>(
+                            IntPredicate::EQ,
+                            v[0].try_into().unwrap(),
+                            v[1].try_into().unwrap(),
+                            &ret_name,
+                        )
+                        .unwrap()
+                        .into()
+                },
+                args,
+                dest,
+            );
+        }
+        Instruction::Value {
+            args,
+            dest,
+            funcs: _,
+            labels: _,
+            op: ValueOps::Clt,
+            op_type: _,
+            pos: _,
+        } => {
+            let ret_name = fresh.fresh_var();
+            build_op(
+                context,
+                builder,
+                heap,
+                fresh,
+                |v| {
+                    builder
+                        .build_int_compare::<IntValue>(
+                            IntPredicate::ULT,
+                            v[0].try_into().unwrap(),
+                            v[1].try_into().unwrap(),
+                            &ret_name,
+                        )
+                        .unwrap()
+                        .into()
+                },
+                args,
+                dest,
+            );
+        }
+        Instruction::Value {
+            args,
+            dest,
+            funcs: _,
+            labels: _,
+            op: ValueOps::Cgt,
+            op_type: _,
+            pos: _,
+        } => {
+            let ret_name = fresh.fresh_var();
+            build_op(
+                context,
+                builder,
+                heap,
+                fresh,
+                |v| {
+                    builder
+                        .build_int_compare::<IntValue>(
+                            IntPredicate::UGT,
+                            v[0].try_into().unwrap(),
+                            v[1].try_into().unwrap(),
+                            &ret_name,
+                        )
+                        .unwrap()
+                        .into()
+                },
+                args,
+                dest,
+            );
+        }
+        Instruction::Value {
+            args,
+            dest,
+            funcs: _,
+            labels: _,
+            op: ValueOps::Cle,
+            op_type: _,
+            pos: _,
+        } => {
+            let ret_name = fresh.fresh_var();
+            build_op(
+                context,
+                builder,
+                heap,
+                fresh,
+                |v| {
+                    builder
+                        .build_int_compare::<IntValue>(
+                            IntPredicate::ULE,
+                            v[0].try_into().unwrap(),
+                            v[1].try_into().unwrap(),
+                            &ret_name,
+                        )
+                        .unwrap()
+                        .into()
+                },
+                args,
+                dest,
+            );
+        }
+        Instruction::Value {
+            args,
+            dest,
+            funcs: _,
+            labels: _,
+            op: ValueOps::Cge,
+            op_type: _,
+            pos: _,
+        } => {
+            let ret_name = fresh.fresh_var();
+            build_op(
+                context,
+                builder,
+                heap,
+                fresh,
+                |v| {
+                    builder
+                        .build_int_compare::<IntValue>(
+                            IntPredicate::UGE,
+                            v[0].try_into().unwrap(),
+                            v[1].try_into().unwrap(),
+                            &ret_name,
+                        )
+                        .unwrap()
+                        .into()
+                },
+                args,
+                dest,
+            );
+        }
+        Instruction::Value {
+            args,
+            dest,
+            funcs: _,
+            labels: _,
+            op: ValueOps::Char2int,
+            op_type: _,
+            pos: _,
+        } => {
+            let ret_name = fresh.fresh_var();
+            build_op(
+                context,
+                builder,
+                heap,
+                fresh,
+                |v| {
+                    builder
+                        .build_int_z_extend::<IntValue>(
+                            v[0].try_into().unwrap(),
+                            context.i64_type(),
+                            &ret_name,
+                        )
+                        .unwrap()
+                        .into()
+                },
+                args,
+                dest,
+            );
+        }
+        Instruction::Value {
+            args,
+            dest,
+            funcs: _,
+            labels: _,
+            op: ValueOps::Int2char,
+            op_type: _,
+            pos: _,
+        } => {
+            // The runtime checks that the int is a valid code point
+            let int2char = module.get_function("_bril_int2char").unwrap();
+            let ret_name = fresh.fresh_var();
+            build_op(
+                context,
+                builder,
+                heap,
+                fresh,
+                |v| {
+                    builder
+                        .build_call(int2char, &[v[0].into()], &ret_name)
+                        .unwrap()
+                        .try_as_basic_value()
+                        .basic()
+                        .unwrap()
+                },
+                args,
+                dest,
+            );
+        }
         Instruction::Effect {
             args,
             funcs: _,
             labels: _,
             op: EffectOps::Return,
+            pos: _,
         } => {
             if args.is_empty() {
                 builder.build_return(None).unwrap();
@@ -919,6 +1508,7 @@ fn build_instruction<'a, 'b>(
             funcs,
             labels: _,
             op: EffectOps::Call,
+            pos: _,
         } => {
             let func_name = if funcs[0] == "main" {
                 "_main"
@@ -952,16 +1542,37 @@ fn build_instruction<'a, 'b>(
             funcs: _,
             labels: _,
             op: EffectOps::Nop,
+            pos: _,
         } => {}
+        Instruction::Effect {
+            args,
+            funcs: _,
+            labels: _,
+            op: EffectOps::Set,
+            pos: _,
+        } => {
+            let value = heap.get(&args[1]);
+            // A shadow variable that is never read with a `get` of this type does not need to be written
+            if let Some(shadow) = heap.get_shadow(&args[0], &value.ty) {
+                builder
+                    .build_store(
+                        shadow.ptr,
+                        build_load(context, builder, &value, &fresh.fresh_var()),
+                    )
+                    .unwrap();
+            }
+        }
         Instruction::Effect {
             args,
             funcs: _,
             labels: _,
             op: EffectOps::Print,
+            pos: _,
         } => {
             let print_int = module.get_function("_bril_print_int").unwrap();
             let print_bool = module.get_function("_bril_print_bool").unwrap();
             let print_float = module.get_function("_bril_print_float").unwrap();
+            let print_char = module.get_function("_bril_print_char").unwrap();
             let print_sep = module.get_function("_bril_print_sep").unwrap();
             let print_end = module.get_function("_bril_print_end").unwrap();
             /*            let ret_name = fresh.fresh_var(); */
@@ -997,6 +1608,12 @@ fn build_instruction<'a, 'b>(
                             .build_call(print_float, &[v.into()], "print_float")
                             .unwrap();
                     }
+                    Type::Char => {
+                        builder
+                            .build_call(print_char, &[v.into()], "print_char")
+                            .unwrap();
+                    }
+                    // The checker rejects printing pointers
                     Type::Pointer(_) => {
                         unreachable!()
                     }
@@ -1012,6 +1629,7 @@ fn build_instruction<'a, 'b>(
             funcs: _,
             labels,
             op: EffectOps::Jump,
+            pos: _,
         } => {
             builder
                 .build_unconditional_branch(block_map_get(
@@ -1024,6 +1642,7 @@ fn build_instruction<'a, 'b>(
             funcs: _,
             labels,
             op: EffectOps::Branch,
+            pos: _,
         } => {
             let then_block = block_map_get(context, llvm_func, block_map, &labels[0]);
             let else_block = block_map_get(context, llvm_func, block_map, &labels[1]);
@@ -1040,64 +1659,6 @@ fn build_instruction<'a, 'b>(
                 args,
             );
         }
-        /* Instruction::Value {
-            args,
-            dest,
-            funcs: _,
-            labels,
-            op: ValueOps::Phi,
-            op_type,
-        } => {
-            let name = fresh.fresh_var();
-            let blocks = labels
-                .iter()
-                .map(|l| block_map_get(context, llvm_func, block_map, l))
-                .collect::<Vec<_>>();
-
-            // Phi nodes must always go before all other instructions
-            // Note, this changes the order of user provided phi nodes
-            // If this bril code follows LLVM's phi node restrictions, then
-            // there should be no observable sideeffects
-            let current_block = builder.get_insert_block().unwrap();
-            if let Some(instruction) = current_block.get_first_instruction() {
-                builder.position_before(&instruction);
-            }
-
-            let phi = builder
-                .build_phi(context.ptr_type(AddressSpace::default()), &name)
-                .unwrap();
-
-            let pointers = args.iter().map(|a| heap.get(a).ptr).collect::<Vec<_>>();
-
-            // The phi node is a little non-standard since we can't load in values from the stack before the phi instruction. Instead, the phi instruction will be over stack locations which will then be loaded into the corresponding output location.
-            phi.add_incoming(
-                pointers
-                    .iter()
-                    .zip(blocks.iter())
-                    .map(|(val, block)| (val as &dyn BasicValue, *block))
-                    .collect::<Vec<_>>()
-                    .as_slice(),
-            );
-
-            // Because we set the position to the start of the block, we should
-            // reset it afterwards
-            builder.position_at_end(current_block);
-
-            builder
-                .build_store(
-                    heap.get(dest).ptr,
-                    build_load(
-                        context,
-                        builder,
-                        &WrappedPointer {
-                            ty: op_type.clone(),
-                            ptr: phi.as_basic_value().into_pointer_value(),
-                        },
-                        &fresh.fresh_var(),
-                    ),
-                )
-                .unwrap();
-        } */
         Instruction::Value {
             args,
             dest,
@@ -1105,6 +1666,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Alloc,
             op_type,
+            pos: _,
         } => {
             let alloc_name = fresh.fresh_var();
             let ty = unwrap_bril_ptrtype(op_type);
@@ -1132,6 +1694,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::Load,
             op_type,
+            pos: _,
         } => {
             let name = fresh.fresh_var();
             llvm_type_map(context, op_type, |pointee_ty| {
@@ -1157,6 +1720,7 @@ fn build_instruction<'a, 'b>(
             labels: _,
             op: ValueOps::PtrAdd,
             op_type,
+            pos: _,
         } => {
             let name = fresh.fresh_var();
             let op_type = unwrap_bril_ptrtype(op_type);
@@ -1187,6 +1751,7 @@ fn build_instruction<'a, 'b>(
             funcs: _,
             labels: _,
             op: EffectOps::Store,
+            pos: _,
         } => {
             build_effect_op(
                 context,
@@ -1204,6 +1769,7 @@ fn build_instruction<'a, 'b>(
             funcs: _,
             labels: _,
             op: EffectOps::Free,
+            pos: _,
         } => {
             build_effect_op(
                 context,
@@ -1220,7 +1786,7 @@ fn build_instruction<'a, 'b>(
 }
 
 // Check for instructions that end a block
-const fn is_terminating_instr(i: Option<&Instruction>) -> bool {
+pub(crate) const fn is_terminating_instr(i: Option<&Instruction>) -> bool {
     matches!(
         i,
         Some(Instruction::Effect {
@@ -1228,21 +1794,25 @@ const fn is_terminating_instr(i: Option<&Instruction>) -> bool {
             funcs: _,
             labels: _,
             op: EffectOps::Branch | EffectOps::Jump | EffectOps::Return,
+            pos: _,
         })
     )
 }
 
 /// Given a Bril program, create an LLVM module from it
 /// The `runtime_module` is the module containing the runtime library
+/// If `debug_source` is provided, DWARF debug info is generated from the source positions in the program. Positions that do not name their source file are assumed to come from `debug_source`.
 /// # Panics
-/// Panics if the program is invalid
+/// Panics if the program is invalid, which [`crate::check::check_program`] rules out
 #[must_use]
 pub fn create_module_from_program<'a>(
     context: &'a Context,
     Program { functions, .. }: &Program,
     runtime_module: Module<'a>,
+    debug_source: Option<&Path>,
 ) -> Module<'a> {
     let builder = context.create_builder();
+    let debug = debug_source.map(|source| DebugInfo::new(context, &runtime_module, source));
 
     // "Global" counter for creating labels/temp variable names
     let mut fresh = Fresh::new();
@@ -1255,6 +1825,7 @@ pub fn create_module_from_program<'a>(
                  args,
                  instrs,
                  name,
+                 pos,
                  return_type,
              }| {
                 // Setup function in module
@@ -1270,6 +1841,9 @@ pub fn create_module_from_program<'a>(
                 let func_name = if name == "main" { "_main" } else { name };
 
                 let llvm_func = runtime_module.add_function(func_name, ty, None);
+                let subprogram = debug
+                    .as_ref()
+                    .map(|debug| debug.function(llvm_func, name, pos.as_ref()));
                 args.iter().zip(llvm_func.get_param_iter()).for_each(
                     |(Argument { name, .. }, bve)| {
                         use inkwell::values::BasicValueEnum;
@@ -1277,6 +1851,7 @@ pub fn create_module_from_program<'a>(
                             BasicValueEnum::IntValue(i) => i.set_name(name),
                             BasicValueEnum::FloatValue(f) => f.set_name(name),
                             BasicValueEnum::PointerValue(p) => p.set_name(name),
+                            // Every Bril type is an LLVM int, float or pointer
                             BasicValueEnum::ArrayValue(_)
                             | BasicValueEnum::StructValue(_)
                             | BasicValueEnum::VectorValue(_)
@@ -1293,6 +1868,7 @@ pub fn create_module_from_program<'a>(
                 llvm_func.get_param_iter().enumerate().for_each(|(i, arg)| {
                     let Argument { name, arg_type } = &args[i];
                     let ptr = heap.add(&builder, context, name, arg_type).ptr;
+                    heap.define(name, arg_type);
                     builder.build_store(ptr, arg).unwrap();
                 });
 
@@ -1303,22 +1879,46 @@ pub fn create_module_from_program<'a>(
                     }) => {
                         heap.add(&builder, context, dest, const_type);
                     }
+                    Code::Instruction(Instruction::Value {
+                        dest,
+                        op: ValueOps::Get,
+                        op_type,
+                        ..
+                    }) => {
+                        heap.add(&builder, context, dest, op_type);
+                        heap.add_shadow(&builder, context, dest, op_type);
+                    }
                     Code::Instruction(Instruction::Value { dest, op_type, .. }) => {
                         heap.add(&builder, context, dest, op_type);
                     }
                 });
 
-                (llvm_func, instrs, block, heap, return_type)
+                let types = block_types(args, instrs);
+
+                (
+                    llvm_func,
+                    instrs,
+                    block,
+                    heap,
+                    types,
+                    return_type,
+                    pos,
+                    subprogram,
+                )
             },
         )
         .collect();
 
     // Now actually build each function
-    funcs
-        .into_iter()
-        .for_each(|(llvm_func, instrs, mut block, heap, return_type)| {
+    funcs.into_iter().for_each(
+        |(llvm_func, instrs, mut block, mut heap, types, return_type, pos, subprogram)| {
             let mut last_instr = None;
 
+            // Code which is not from a Bril instruction is attributed to the start of the function
+            if let (Some(debug), Some(subprogram)) = (&debug, subprogram) {
+                debug.set_location(context, &builder, subprogram, pos.as_ref());
+            }
+
             // Maps labels to llvm blocks for jumps
             let mut block_map = HashMap::new();
 
@@ -1346,11 +1946,29 @@ pub fn create_module_from_program<'a>(
                         block = new_block;
                         builder.position_at_end(block);
                         last_instr = None;
+                        heap.current = types.get(label).cloned().unwrap_or_default();
                     }
                     bril_rs::Code::Instruction(i) => {
                         // Check if we are in a basic block that has already been terminated
                         // If so, we just keep skipping unreachable instructions until we hit a new block or run out of instructions
                         if !is_terminating_instr(last_instr.as_ref()) {
+                            let dest = match i {
+                                Instruction::Constant {
+                                    dest,
+                                    const_type: ty,
+                                    ..
+                                }
+                                | Instruction::Value {
+                                    dest, op_type: ty, ..
+                                } => Some((dest, ty)),
+                                Instruction::Effect { .. } => None,
+                            };
+                            heap.dest_ty = dest.map(|(_, ty)| ty.clone());
+                            if let (Some(debug), Some(subprogram), Some(pos)) =
+                                (&debug, subprogram, i.get_pos())
+                            {
+                                debug.set_location(context, &builder, subprogram, Some(&pos));
+                            }
                             build_instruction(
                                 i,
                                 context,
@@ -1361,6 +1979,9 @@ pub fn create_module_from_program<'a>(
                                 llvm_func,
                                 &mut fresh,
                             );
+                            if let Some((dest, ty)) = dest {
+                                heap.define(dest, ty);
+                            }
                             last_instr = Some(i.clone());
                         }
                     }
@@ -1383,7 +2004,8 @@ pub fn create_module_from_program<'a>(
                         .unwrap();
                 }
             }
-        });
+        },
+    );
 
     // Add new main function to act as a entry point to the function.
     // Sets up arguments for a _main call
@@ -1401,6 +2023,8 @@ pub fn create_module_from_program<'a>(
 
     let entry_block = context.append_basic_block(entry_func, &fresh.fresh_label());
     builder.position_at_end(entry_block);
+    // The entry point has no Bril source
+    builder.unset_current_debug_location();
 
     let mut heap = Heap::new();
 
@@ -1415,6 +2039,7 @@ pub fn create_module_from_program<'a>(
         let parse_int = runtime_module.get_function("_bril_parse_int").unwrap();
         let parse_bool = runtime_module.get_function("_bril_parse_bool").unwrap();
         let parse_float = runtime_module.get_function("_bril_parse_float").unwrap();
+        let parse_char = runtime_module.get_function("_bril_parse_char").unwrap();
 
         function.get_param_iter().enumerate().for_each(|(i, _)| {
             let Argument { name, arg_type } = &args[i];
@@ -1453,6 +2078,13 @@ pub fn create_module_from_program<'a>(
                     .try_as_basic_value()
                     .basic()
                     .unwrap(),
+                Type::Char => builder
+                    .build_call(parse_char, &[arg_str.into()], "parse_char")
+                    .unwrap()
+                    .try_as_basic_value()
+                    .basic()
+                    .unwrap(),
+                // The checker rejects pointer arguments to `@main`
                 Type::Pointer(_) => unreachable!(),
             };
             builder.build_store(ptr, arg).unwrap();
@@ -1485,6 +2117,10 @@ pub fn create_module_from_program<'a>(
         .build_return(Some(&context.i32_type().const_int(0, true)))
         .unwrap();
 
+    if let Some(debug) = &debug {
+        debug.builder.finalize();
+    }
+
     // Return the module
     runtime_module
 }
@@ -1,43 +1,244 @@
-use bril_rs::load_program_from_read;
-use brillvm::{cli::Cli, llvm::create_module_from_program};
-use clap::Parser;
+use bril_rs::extensions::{Extension, ExtensionUsage};
+use bril_rs::{AbstractProgram, Program};
+use brillvm::{
+    check::check_program,
+    cli::{Cli, Emit},
+    llvm::create_module_from_program,
+};
+use clap::{CommandFactory, Parser, error::ErrorKind};
 use inkwell::{
+    OptimizationLevel,
     context::Context,
+    memory_buffer::MemoryBuffer,
     module::Module,
-    targets::{InitializationConfig, Target},
+    passes::PassBuilderOptions,
+    targets::{
+        CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
+    },
 };
-use std::io::Read;
+use std::{fmt::Display, io::Read, path::Path, process::Command};
+
+// Each kind of failure has its own exit code. 2 is left to clap's usage errors and the runtime library.
+// The input is not a Bril program that brillvm can compile
+const EXIT_INVALID_PROGRAM: i32 = 1;
+// LLVM could not verify, optimize, or generate code for the program
+const EXIT_LLVM: i32 = 3;
+// A file could not be read or written, or the executable could not be linked
+const EXIT_IO: i32 = 4;
+
+fn fail(code: i32, msg: impl Display) -> ! {
+    eprintln!("error: {msg}");
+    std::process::exit(code)
+}
+
+// The runtime library for functions like printing/parsing, built into bitcode by `build.rs`
+static RUNTIME: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/rt.bc"));
+
+// Creates a machine for the target `triple` or the host if none is given
+fn target_machine(triple: Option<&str>, opt_level: OptimizationLevel) -> TargetMachine {
+    Target::initialize_all(&InitializationConfig::default());
+    let (triple, cpu, features) = triple.map_or_else(
+        || {
+            (
+                TargetMachine::get_default_triple(),
+                TargetMachine::get_host_cpu_name().to_string(),
+                TargetMachine::get_host_cpu_features().to_string(),
+            )
+        },
+        |t| {
+            (
+                TargetTriple::create(t),
+                "generic".to_string(),
+                String::new(),
+            )
+        },
+    );
+    Target::from_triple(&triple)
+        .unwrap_or_else(|e| fail(EXIT_LLVM, e))
+        .create_target_machine(
+            &triple,
+            &cpu,
+            &features,
+            opt_level,
+            RelocMode::PIC,
+            CodeModel::Default,
+        )
+        .unwrap_or_else(|| {
+            fail(
+                EXIT_LLVM,
+                format!("could not create a machine for {triple}"),
+            )
+        })
+}
+
+// Links an object file into an executable with the system C compiler, which also provides libc for the runtime
+fn link(object: &Path, output: &str) -> Result<(), String> {
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&cc)
+        .arg(object)
+        .arg("-o")
+        .arg(output)
+        .status()
+        .map_err(|e| format!("could not run {cc}: {e}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{cc} could not link {output}"))
+    }
+}
+
+// Names the Bril function that LLVM rejected, since a bad module is a bug in brillvm
+fn verify(module: &Module) {
+    if let Err(e) = module.verify() {
+        let func = module
+            .get_functions()
+            .find(|f| !f.verify(false))
+            .map_or_else(
+                || "the program".to_string(),
+                |f| match f.get_name().to_string_lossy().as_ref() {
+                    "_main" => "@main".to_string(),
+                    "main" => "the entry point".to_string(),
+                    name => format!("@{name}"),
+                },
+            );
+        fail(
+            EXIT_LLVM,
+            format!(
+                "LLVM rejected the code generated for {func}, please open an issue with your Bril program!\n{e}"
+            ),
+        );
+    }
+}
 
 fn main() {
     let args = Cli::parse();
 
-    let mut src = String::new();
-    if let Some(f) = &args.file {
-        let path = std::fs::canonicalize(f).unwrap();
-        let mut file = std::fs::File::open(path).unwrap();
-        file.read_to_string(&mut src).unwrap()
-    } else {
-        std::io::stdin().read_to_string(&mut src).unwrap()
-    };
-    let prog = load_program_from_read(src.as_bytes());
+    // The runtime library and `cc` only produce executables for the host
+    if !args.interpreter
+        && matches!(args.emit, Emit::Exe)
+        && let Some(target) = &args.target
+        && TargetMachine::normalize_triple(&TargetTriple::create(target))
+            != TargetMachine::normalize_triple(&TargetMachine::get_default_triple())
+    {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                format!(
+                    "--emit exe can only link executables for the host, not {target} (use --emit obj and link it yourself instead)"
+                ),
+            )
+            .exit();
+    }
+
+    let src = match &args.file {
+        Some(f) => std::fs::read_to_string(f),
+        None => {
+            let mut src = String::new();
+            std::io::stdin().read_to_string(&mut src).map(|_| src)
+        }
+    }
+    .unwrap_or_else(|e| {
+        fail(
+            EXIT_IO,
+            format!(
+                "could not read {}: {e}",
+                args.file.as_deref().unwrap_or("stdin")
+            ),
+        )
+    });
+    let abstract_prog: AbstractProgram = serde_json::from_str(&src)
+        .unwrap_or_else(|e| fail(EXIT_INVALID_PROGRAM, format!("invalid Bril JSON: {e}")));
+    // Reject programs using extensions that we can't compile before trying to translate them
+    if let Err(e) = ExtensionUsage::from(&abstract_prog).check(&[
+        Extension::Float,
+        Extension::Memory,
+        Extension::Char,
+        Extension::Ssa,
+    ]) {
+        fail(EXIT_INVALID_PROGRAM, e);
+    }
+    let prog = Program::try_from_all(abstract_prog).unwrap_or_else(|errors| {
+        for e in errors.0 {
+            eprintln!("error: {}", e.to_string_with_context());
+        }
+        std::process::exit(EXIT_INVALID_PROGRAM);
+    });
+    if let Err(errors) = check_program(&prog) {
+        for e in errors {
+            eprintln!("error: {e}");
+        }
+        std::process::exit(EXIT_INVALID_PROGRAM);
+    }
 
     let context = Context::create();
-    let runtime_path = args.runtime.as_ref().map_or("rt.bc", |f| f);
     // create a module from the runtime library for functions like printing/parsing
-    let runtime_module = Module::parse_bitcode_from_path(runtime_path, &context).unwrap();
-    let llvm_prog = create_module_from_program(&context, &prog, runtime_module);
+    let runtime_module = match &args.runtime {
+        Some(path) => Module::parse_bitcode_from_path(path, &context).unwrap_or_else(|e| {
+            fail(
+                EXIT_IO,
+                format!("could not load the runtime library {path}: {e}"),
+            )
+        }),
+        None => Module::parse_bitcode_from_buffer(
+            &MemoryBuffer::create_from_memory_range(RUNTIME, "rt"),
+            &context,
+        )
+        .unwrap(),
+    };
+    // Positions without a source file of their own are attributed to the input
+    let debug_source = args
+        .debug_info
+        .then(|| Path::new(args.file.as_deref().unwrap_or("<stdin>")));
+    let llvm_prog = create_module_from_program(&context, &prog, runtime_module, debug_source);
 
     //println!("{}", prog);
     //llvm_prog.print_to_file("tmp.ll").unwrap();
-    llvm_prog.verify().unwrap();
+    verify(&llvm_prog);
+
+    let opt_level = match args.opt_level {
+        0 => OptimizationLevel::None,
+        1 => OptimizationLevel::Less,
+        2 => OptimizationLevel::Default,
+        _ => OptimizationLevel::Aggressive,
+    };
+    // A custom pipeline replaces the default one for the optimization level
+    let passes = args
+        .passes
+        .clone()
+        .or_else(|| (args.opt_level > 0).then(|| format!("default<O{}>", args.opt_level)));
+
+    // The interpreter always runs on the host
+    let machine = (passes.is_some()
+        || (!args.interpreter
+            && (args.target.is_some() || matches!(args.emit, Emit::Obj | Emit::Exe))))
+    .then(|| {
+        target_machine(
+            args.target.as_deref().filter(|_| !args.interpreter),
+            opt_level,
+        )
+    });
+    if let Some(machine) = &machine {
+        llvm_prog.set_triple(&machine.get_triple());
+        llvm_prog.set_data_layout(&machine.get_target_data().get_data_layout());
+    }
+
+    if let Some(passes) = &passes
+        && let Err(e) = llvm_prog.run_passes(
+            passes,
+            machine.as_ref().unwrap(),
+            PassBuilderOptions::create(),
+        )
+    {
+        fail(EXIT_LLVM, e);
+    }
 
     if args.interpreter {
         Target::initialize_native(&InitializationConfig::default())
-            .expect("Failed to initialize native target");
+            .unwrap_or_else(|e| fail(EXIT_LLVM, e));
 
         let engine = llvm_prog
-            .create_jit_execution_engine(inkwell::OptimizationLevel::None)
-            .unwrap();
+            .create_jit_execution_engine(opt_level)
+            .unwrap_or_else(|e| fail(EXIT_LLVM, e));
 
         let mut args: Vec<&str> = args.args.iter().map(|s| s.as_ref()).collect();
         args.insert(0, "bril_prog");
@@ -45,6 +246,42 @@ fn main() {
             engine.run_function_as_main(llvm_prog.get_function("main").unwrap(), &args);
         }
     } else {
-        println!("{}", llvm_prog.to_string())
+        let write_error =
+            |output: &str, e: &dyn Display| fail(EXIT_IO, format!("could not write {output}: {e}"));
+        match (args.emit, args.output.as_deref()) {
+            (Emit::Ll, None) => println!("{}", llvm_prog.to_string()),
+            (Emit::Ll, Some(output)) => llvm_prog
+                .print_to_file(output)
+                .unwrap_or_else(|e| write_error(output, &e)),
+            (Emit::Bc, Some(output)) => {
+                if !llvm_prog.write_bitcode_to_path(Path::new(output)) {
+                    write_error(output, &"LLVM could not write the bitcode");
+                }
+            }
+            (Emit::Obj, Some(output)) => machine
+                .unwrap()
+                .write_to_file(&llvm_prog, FileType::Object, Path::new(output))
+                .unwrap_or_else(|e| write_error(output, &e)),
+            (Emit::Exe, Some(output)) => {
+                // A fresh temporary file can't clobber any of the user's files (or `output`)
+                let object = tempfile::Builder::new()
+                    .prefix("brillvm-")
+                    .suffix(".o")
+                    .tempfile()
+                    .unwrap_or_else(|e| {
+                        fail(EXIT_IO, format!("could not create a temporary file: {e}"))
+                    });
+                let result = machine
+                    .unwrap()
+                    .write_to_file(&llvm_prog, FileType::Object, object.path())
+                    .map_err(|e| format!("could not write {}: {e}", object.path().display()))
+                    .and_then(|()| link(object.path(), output));
+                // Delete the object file before `fail` exits
+                drop(object);
+                result.unwrap_or_else(|e| fail(EXIT_IO, e));
+            }
+            // clap requires an output file for these
+            (Emit::Bc | Emit::Obj | Emit::Exe, None) => unreachable!(),
+        }
     }
 }
@@ -9,6 +9,9 @@ use crate::program::Position;
 #[cfg(feature = "import")]
 use crate::program::Import;
 
+#[cfg(feature = "comments")]
+use crate::program::Comments;
+
 use serde::{Deserialize, Serialize};
 
 use serde::de::{self, Error, MapAccess, Visitor};
@@ -24,13 +27,25 @@ pub struct AbstractProgram {
     #[cfg(feature = "import")]
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub imports: Vec<Import>,
+    /// Comment lines and blank lines after the last function of the program
+    #[cfg(feature = "comments")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub end_comments: Vec<String>,
 }
 
 impl Display for AbstractProgram {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        #[cfg(feature = "import")]
+        for i in &self.imports {
+            writeln!(f, "{i}")?;
+        }
         for func in &self.functions {
             writeln!(f, "{func}")?;
         }
+        #[cfg(feature = "comments")]
+        for line in &self.end_comments {
+            writeln!(f, "{line}")?;
+        }
         Ok(())
     }
 }
@@ -54,10 +69,20 @@ pub struct AbstractFunction {
     #[serde(rename = "type")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub return_type: Option<AbstractType>,
+    /// Comments before the function and on the line of its opening `{`
+    #[cfg(feature = "comments")]
+    #[serde(default, skip_serializing_if = "Comments::is_empty")]
+    pub comments: Comments,
+    /// Comments before the closing `}` of the function and on the same line as it
+    #[cfg(feature = "comments")]
+    #[serde(default, skip_serializing_if = "Comments::is_empty")]
+    pub end_comments: Comments,
 }
 
 impl Display for AbstractFunction {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        #[cfg(feature = "comments")]
+        self.comments.fmt_leading(f, "")?;
         write!(f, "@{}", self.name)?;
         if !self.args.is_empty() {
             write!(f, "(")?;
@@ -72,11 +97,18 @@ impl Display for AbstractFunction {
         if let Some(tpe) = self.return_type.as_ref() {
             write!(f, ": {tpe}")?;
         }
-        writeln!(f, " {{")?;
+        write!(f, " {{")?;
+        #[cfg(feature = "comments")]
+        self.comments.fmt_trailing(f)?;
+        writeln!(f)?;
         for instr in &self.instrs {
             writeln!(f, "{instr}")?;
         }
+        #[cfg(feature = "comments")]
+        self.end_comments.fmt_leading(f, "  ")?;
         write!(f, "}}")?;
+        #[cfg(feature = "comments")]
+        self.end_comments.fmt_trailing(f)?;
         Ok(())
     }
 }
@@ -113,6 +145,10 @@ pub enum AbstractCode {
         #[cfg(feature = "position")]
         #[serde(flatten, skip_serializing_if = "Option::is_none")]
         pos: Option<Position>,
+        /// Comments attached to the label in source code
+        #[cfg(feature = "comments")]
+        #[serde(default, skip_serializing_if = "Comments::is_empty")]
+        comments: Comments,
     },
     /// <https://capra.cs.cornell.edu/bril/lang/syntax.html#instruction>
     Instruction(AbstractInstruction),
@@ -125,8 +161,24 @@ impl Display for AbstractCode {
                 label,
                 #[cfg(feature = "position")]
                     pos: _,
-            } => write!(f, ".{label}:"),
-            Self::Instruction(instr) => write!(f, "  {instr}"),
+                #[cfg(feature = "comments")]
+                comments,
+            } => {
+                #[cfg(feature = "comments")]
+                comments.fmt_leading(f, "  ")?;
+                write!(f, ".{label}:")?;
+                #[cfg(feature = "comments")]
+                comments.fmt_trailing(f)?;
+                Ok(())
+            }
+            Self::Instruction(instr) => {
+                #[cfg(feature = "comments")]
+                instr.get_comments().fmt_leading(f, "  ")?;
+                write!(f, "  {instr}")?;
+                #[cfg(feature = "comments")]
+                instr.get_comments().fmt_trailing(f)?;
+                Ok(())
+            }
         }
     }
 }
@@ -146,6 +198,10 @@ pub enum AbstractInstruction {
         #[cfg(feature = "position")]
         #[serde(flatten, skip_serializing_if = "Option::is_none")]
         pos: Option<Position>,
+        /// Comments attached to the instruction in source code
+        #[cfg(feature = "comments")]
+        #[serde(default, skip_serializing_if = "Comments::is_empty")]
+        comments: Comments,
         /// Type of variable
         #[serde(rename = "type")]
         const_type: Option<AbstractType>,
@@ -171,6 +227,10 @@ pub enum AbstractInstruction {
         #[cfg(feature = "position")]
         #[serde(flatten, skip_serializing_if = "Option::is_none")]
         pos: Option<Position>,
+        /// Comments attached to the instruction in source code
+        #[cfg(feature = "comments")]
+        #[serde(default, skip_serializing_if = "Comments::is_empty")]
+        comments: Comments,
         /// Type of variable
         #[serde(rename = "type")]
         op_type: Option<AbstractType>,
@@ -192,9 +252,39 @@ pub enum AbstractInstruction {
         #[cfg(feature = "position")]
         #[serde(flatten, skip_serializing_if = "Option::is_none")]
         pos: Option<Position>,
+        /// Comments attached to the instruction in source code
+        #[cfg(feature = "comments")]
+        #[serde(default, skip_serializing_if = "Comments::is_empty")]
+        comments: Comments,
     },
 }
 
+#[cfg(feature = "position")]
+impl AbstractInstruction {
+    /// A helper function to extract the position value if it exists from an instruction
+    #[must_use]
+    pub fn get_pos(&self) -> Option<Position> {
+        match self {
+            Self::Constant { pos, .. } | Self::Value { pos, .. } | Self::Effect { pos, .. } => {
+                pos.clone()
+            }
+        }
+    }
+}
+
+#[cfg(feature = "comments")]
+impl AbstractInstruction {
+    /// A helper function to extract the comments attached to an instruction
+    #[must_use]
+    pub const fn get_comments(&self) -> &Comments {
+        match self {
+            Self::Constant { comments, .. }
+            | Self::Value { comments, .. }
+            | Self::Effect { comments, .. } => comments,
+        }
+    }
+}
+
 impl Display for AbstractInstruction {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
@@ -205,6 +295,8 @@ impl Display for AbstractInstruction {
                 value,
                 #[cfg(feature = "position")]
                     pos: _,
+                #[cfg(feature = "comments")]
+                    comments: _,
             } => match const_type {
                 Some(const_type) => write!(f, "{dest}: {const_type} = {op} {value};"),
                 None => write!(f, "{dest} = {op} {value};"),
@@ -218,6 +310,8 @@ impl Display for AbstractInstruction {
                 labels,
                 #[cfg(feature = "position")]
                     pos: _,
+                #[cfg(feature = "comments")]
+                    comments: _,
             } => {
                 match op_type {
                     Some(op_type) => write!(f, "{dest}: {op_type} = {op}")?,
@@ -241,6 +335,8 @@ impl Display for AbstractInstruction {
                 labels,
                 #[cfg(feature = "position")]
                     pos: _,
+                #[cfg(feature = "comments")]
+                    comments: _,
             } => {
                 write!(f, "{op}")?;
                 for func in funcs {
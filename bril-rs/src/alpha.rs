@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::{Code, Function, Instruction};
+
+/// A consistent renaming of the variables and labels of a [`Function`]
+///
+/// Variables and labels live in separate namespaces so each has its own map. Names without an entry are left unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Renaming {
+    /// Maps the old name of a variable to its new name
+    pub vars: HashMap<String, String>,
+    /// Maps the old name of a label to its new name
+    pub labels: HashMap<String, String>,
+}
+
+impl Renaming {
+    /// Numbers the variables and labels of `func` in the order that they first occur
+    ///
+    /// Two functions which only differ by a consistent renaming will have the same canonical names.
+    #[must_use]
+    pub fn canonical(func: &Function) -> Self {
+        let mut renaming = Self::default();
+        for_each_name(
+            func,
+            |var| {
+                let next = format!("v{}", renaming.vars.len());
+                renaming.vars.entry(var.to_string()).or_insert(next);
+            },
+            |label| {
+                let next = format!("l{}", renaming.labels.len());
+                renaming.labels.entry(label.to_string()).or_insert(next);
+            },
+        );
+        renaming
+    }
+
+    fn var(&self, name: &mut String) {
+        if let Some(new) = self.vars.get(name) {
+            new.clone_into(name);
+        }
+    }
+
+    fn label(&self, name: &mut String) {
+        if let Some(new) = self.labels.get(name) {
+            new.clone_into(name);
+        }
+    }
+
+    /// Returns a copy of `func` with its variables and labels renamed
+    #[must_use]
+    pub fn apply(&self, func: &Function) -> Function {
+        let mut func = func.clone();
+        for arg in &mut func.args {
+            self.var(&mut arg.name);
+        }
+        for code in &mut func.instrs {
+            match code {
+                Code::Label { label, .. } => self.label(label),
+                Code::Instruction(Instruction::Constant { dest, .. }) => self.var(dest),
+                Code::Instruction(Instruction::Value {
+                    dest, args, labels, ..
+                }) => {
+                    self.var(dest);
+                    for a in args {
+                        self.var(a);
+                    }
+                    for l in labels {
+                        self.label(l);
+                    }
+                }
+                Code::Instruction(Instruction::Effect { args, labels, .. }) => {
+                    for a in args {
+                        self.var(a);
+                    }
+                    for l in labels {
+                        self.label(l);
+                    }
+                }
+            }
+        }
+        func
+    }
+}
+
+/// Calls `on_var` and `on_label` on every variable and label of `func` in program order, starting with the arguments
+pub fn for_each_name<'a>(
+    func: &'a Function,
+    mut on_var: impl FnMut(&'a str),
+    mut on_label: impl FnMut(&'a str),
+) {
+    for arg in &func.args {
+        on_var(&arg.name);
+    }
+    for code in &func.instrs {
+        match code {
+            Code::Label { label, .. } => on_label(label),
+            Code::Instruction(Instruction::Constant { dest, .. }) => on_var(dest),
+            Code::Instruction(Instruction::Value {
+                dest, args, labels, ..
+            }) => {
+                for a in args {
+                    on_var(a);
+                }
+                for l in labels {
+                    on_label(l);
+                }
+                on_var(dest);
+            }
+            Code::Instruction(Instruction::Effect { args, labels, .. }) => {
+                for a in args {
+                    on_var(a);
+                }
+                for l in labels {
+                    on_label(l);
+                }
+            }
+        }
+    }
+}
+
+/// Renames `func` with its [`Renaming::canonical`] names and drops any source positions
+#[must_use]
+pub fn canonicalize(func: &Function) -> Function {
+    #[cfg_attr(not(feature = "position"), expect(unused_mut))]
+    let mut func = Renaming::canonical(func).apply(func);
+    #[cfg(feature = "position")]
+    {
+        func.pos = None;
+        for code in &mut func.instrs {
+            match code {
+                Code::Label { pos, .. }
+                | Code::Instruction(
+                    Instruction::Constant { pos, .. }
+                    | Instruction::Value { pos, .. }
+                    | Instruction::Effect { pos, .. },
+                ) => *pos = None,
+            }
+        }
+    }
+    func
+}
+
+/// Whether `a` and `b` are equal up to a consistent renaming of their variables and labels
+///
+/// Function names, including the names of called functions, must match exactly. Source positions are ignored.
+#[must_use]
+pub fn alpha_equivalent(a: &Function, b: &Function) -> bool {
+    canonicalize(a) == canonicalize(b)
+}
+
+/// A hash of `func` which is the same for any two functions that are [`alpha_equivalent`]
+///
+/// The hash is deterministic but is not guaranteed to be stable across versions of Rust.
+#[must_use]
+pub fn canonical_hash(func: &Function) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    canonicalize(func).to_string().hash(&mut hasher);
+    hasher.finish()
+}
@@ -1,8 +1,9 @@
-use std::fmt::Display;
+use std::fmt::{Display, Write};
 
 use crate::{
     AbstractArgument, AbstractCode, AbstractFunction, AbstractInstruction, AbstractProgram,
-    AbstractType, Argument, Code, Function, Instruction, Position, Program, Type,
+    AbstractType, Argument, Code, EffectOps, Function, Instruction, Position, Program, Type,
+    ValueOps,
 };
 
 use thiserror::Error;
@@ -51,95 +52,222 @@ impl ConversionError {
         PositionalConversionError {
             e: self,
             pos: pos_var,
+            func: None,
+            instr: None,
         }
     }
 }
 
 /// Wraps [`ConversionError`] to optionally provide source code positions if they are available.
+///
+/// Errors that come from inside a function also record the name of that function and the text of the offending instruction or argument.
+/// These are not part of its [`Display`] output but are included when printing [`ConversionErrors`].
 #[derive(Error, Debug)]
 pub struct PositionalConversionError {
     #[doc(hidden)]
     pub e: ConversionError,
     #[doc(hidden)]
     pub pos: Option<Position>,
+    func: Option<String>,
+    instr: Option<String>,
 }
 
 impl PositionalConversionError {
     #[doc(hidden)]
     #[must_use]
     pub const fn new(e: ConversionError) -> Self {
-        Self { e, pos: None }
+        Self {
+            e,
+            pos: None,
+            func: None,
+            instr: None,
+        }
+    }
+
+    #[doc(hidden)]
+    #[must_use]
+    pub fn add_func(mut self, name: &str) -> Self {
+        if self.func.is_none() {
+            self.func = Some(name.to_string());
+        }
+        self
+    }
+
+    #[doc(hidden)]
+    #[must_use]
+    pub fn add_instr(mut self, instr: &impl Display) -> Self {
+        if self.instr.is_none() {
+            self.instr = Some(instr.to_string());
+        }
+        self
+    }
+
+    /// The name of the function that this error occurred in, if any
+    #[must_use]
+    pub fn func(&self) -> Option<&str> {
+        self.func.as_deref()
+    }
+
+    /// The text of the instruction or argument that caused this error, if any
+    #[must_use]
+    pub fn instr(&self) -> Option<&str> {
+        self.instr.as_deref()
+    }
+
+    /// Like [`Display`], followed by the function and instruction that this error occurred in when they are known
+    #[must_use]
+    pub fn to_string_with_context(&self) -> String {
+        let mut message = self.to_string();
+        if let Some(func) = &self.func {
+            write!(message, " in @{func}").unwrap();
+        }
+        if let Some(instr) = &self.instr {
+            write!(message, ": `{instr}`").unwrap();
+        }
+        message
     }
 }
 
 impl Display for PositionalConversionError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
+        match &self.pos {
             #[cfg(feature = "position")]
-            Self { e, pos: Some(pos) } => {
-                write!(f, "Line {}, Column {}: {e}", pos.pos.row, pos.pos.col)
-            }
+            Some(pos) => write!(f, "Line {}, Column {}: ", pos.pos.row, pos.pos.col)?,
             #[cfg(not(feature = "position"))]
-            Self { e: _, pos: Some(_) } => {
-                unreachable!()
+            Some(_) => unreachable!(),
+            None => {}
+        }
+        write!(f, "{}", self.e)
+    }
+}
+
+/// Every error found while converting an [`AbstractProgram`] into a [Program] with [`Program::try_from_all`]
+///
+/// This is never empty. Errors are in the order that they occur in the program.
+#[derive(Error, Debug)]
+pub struct ConversionErrors(pub Vec<PositionalConversionError>);
+
+impl Display for ConversionErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, e) in self.0.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
             }
-            Self { e, pos: None } => write!(f, "{e}"),
+            write!(f, "{}", e.to_string_with_context())?;
         }
+        Ok(())
     }
 }
 
-impl TryFrom<AbstractProgram> for Program {
-    type Error = PositionalConversionError;
-    fn try_from(
+// Splits the results of converting many things into either all of the successes or all of the errors
+fn collect_all<T>(
+    results: impl IntoIterator<Item = Result<T, Vec<PositionalConversionError>>>,
+) -> Result<Vec<T>, Vec<PositionalConversionError>> {
+    let mut oks = Vec::new();
+    let mut errors = Vec::new();
+    for r in results {
+        match r {
+            Ok(t) => oks.push(t),
+            Err(e) => errors.extend(e),
+        }
+    }
+    if errors.is_empty() {
+        Ok(oks)
+    } else {
+        Err(errors)
+    }
+}
+
+impl Program {
+    /// Converts an [`AbstractProgram`] into a [Program] like [`TryFrom`] except that it continues past errors to report all of them at once
+    /// # Errors
+    /// Returns every [`PositionalConversionError`] found in `prog`
+    pub fn try_from_all(
         AbstractProgram {
             #[cfg(feature = "import")]
             imports,
             functions,
+            #[cfg(feature = "comments")]
+                end_comments: _,
         }: AbstractProgram,
-    ) -> Result<Self, Self::Error> {
+    ) -> Result<Self, ConversionErrors> {
         Ok(Self {
             #[cfg(feature = "import")]
             imports,
-            functions: functions
-                .into_iter()
-                .map(std::convert::TryInto::try_into)
-                .collect::<Result<Vec<Function>, _>>()?,
+            functions: collect_all(functions.into_iter().map(convert_function))
+                .map_err(ConversionErrors)?,
         })
     }
 }
 
-impl TryFrom<AbstractFunction> for Function {
+impl TryFrom<AbstractProgram> for Program {
     type Error = PositionalConversionError;
-    fn try_from(
-        AbstractFunction {
+    fn try_from(prog: AbstractProgram) -> Result<Self, Self::Error> {
+        Self::try_from_all(prog).map_err(|ConversionErrors(errors)| {
+            errors
+                .into_iter()
+                .next()
+                .expect("There is always at least one error")
+        })
+    }
+}
+
+fn convert_function(
+    AbstractFunction {
+        args,
+        instrs,
+        name,
+        return_type,
+        #[cfg(feature = "position")]
+        pos,
+        #[cfg(feature = "comments")]
+            comments: _,
+        #[cfg(feature = "comments")]
+            end_comments: _,
+    }: AbstractFunction,
+) -> Result<Function, Vec<PositionalConversionError>> {
+    let args = collect_all(args.into_iter().map(|a| match Type::try_from(&a.arg_type) {
+        Ok(arg_type) => Ok(Argument {
+            name: a.name,
+            arg_type,
+        }),
+        Err(e) => Err(vec![e.add_pos(pos.clone()).add_instr(&a)]),
+    }));
+    let return_type = return_type
+        .as_ref()
+        .map(Type::try_from)
+        .transpose()
+        .map_err(|e| vec![e.add_pos(pos.clone())]);
+    let instrs = collect_all(instrs.into_iter().map(convert_code));
+
+    match (args, return_type, instrs) {
+        (Ok(args), Ok(return_type), Ok(instrs)) => Ok(Function {
             args,
             instrs,
             name,
-            return_type,
             #[cfg(feature = "position")]
             pos,
-        }: AbstractFunction,
-    ) -> Result<Self, Self::Error> {
-        Ok(Self {
-            args: args
-                .into_iter()
-                .map(std::convert::TryInto::try_into)
-                .collect::<Result<Vec<Argument>, _>>()
-                .map_err(|e| e.add_pos(pos.clone()))?,
-            instrs: instrs
+            return_type,
+        }),
+        (args, return_type, instrs) => Err(args
+            .err()
+            .into_iter()
+            .chain(return_type.err())
+            .chain(instrs.err())
+            .flatten()
+            .map(|e| e.add_func(&name))
+            .collect()),
+    }
+}
+
+impl TryFrom<AbstractFunction> for Function {
+    type Error = PositionalConversionError;
+    fn try_from(func: AbstractFunction) -> Result<Self, Self::Error> {
+        convert_function(func).map_err(|errors| {
+            errors
                 .into_iter()
-                .map(std::convert::TryInto::try_into)
-                .collect::<Result<Vec<Code>, _>>()?,
-            name,
-            return_type: match return_type {
-                None => None,
-                Some(t) => Some(
-                    t.try_into()
-                        .map_err(|e: ConversionError| e.add_pos(pos.clone()))?,
-                ),
-            },
-            #[cfg(feature = "position")]
-            pos,
+                .next()
+                .expect("There is always at least one error")
         })
     }
 }
@@ -156,105 +284,260 @@ impl TryFrom<AbstractArgument> for Argument {
     }
 }
 
+fn convert_code(c: AbstractCode) -> Result<Code, Vec<PositionalConversionError>> {
+    Ok(match c {
+        AbstractCode::Label {
+            label,
+            #[cfg(feature = "position")]
+            pos,
+            #[cfg(feature = "comments")]
+                comments: _,
+        } => Code::Label {
+            label,
+            #[cfg(feature = "position")]
+            pos,
+        },
+        AbstractCode::Instruction(i) => Code::Instruction(convert_instruction(i)?),
+    })
+}
+
 impl TryFrom<AbstractCode> for Code {
     type Error = PositionalConversionError;
     fn try_from(c: AbstractCode) -> Result<Self, Self::Error> {
-        Ok(match c {
-            AbstractCode::Label {
-                label,
-                #[cfg(feature = "position")]
-                pos,
-            } => Self::Label {
-                label,
-                #[cfg(feature = "position")]
-                pos,
-            },
-            AbstractCode::Instruction(i) => Self::Instruction(i.try_into()?),
+        convert_code(c).map_err(|errors| {
+            errors
+                .into_iter()
+                .next()
+                .expect("There is always at least one error")
         })
     }
 }
 
-impl TryFrom<AbstractInstruction> for Instruction {
-    type Error = PositionalConversionError;
-    fn try_from(i: AbstractInstruction) -> Result<Self, Self::Error> {
-        Ok(match i {
+// The parts of an instruction which can fail to convert
+enum Parsed {
+    Constant(Type),
+    Value(Type, ValueOps),
+    Effect(EffectOps),
+}
+
+fn convert_instruction(
+    i: AbstractInstruction,
+) -> Result<Instruction, Vec<PositionalConversionError>> {
+    // Everything that can fail is converted by reference first so that the whole instruction can be reported
+    let parsed = match &i {
+        AbstractInstruction::Constant { const_type, .. } => Type::try_from(const_type.as_ref())
+            .map(Parsed::Constant)
+            .map_err(|e| vec![e]),
+        AbstractInstruction::Value { op, op_type, .. } => {
+            match (Type::try_from(op_type.as_ref()), op.parse()) {
+                (Ok(op_type), Ok(op)) => Ok(Parsed::Value(op_type, op)),
+                (op_type, op) => Err(op_type.err().into_iter().chain(op.err()).collect()),
+            }
+        }
+        AbstractInstruction::Effect { op, .. } => {
+            op.parse().map(Parsed::Effect).map_err(|e| vec![e])
+        }
+    };
+
+    let parsed = match parsed {
+        Ok(parsed) => parsed,
+        Err(errors) => {
+            #[cfg(feature = "position")]
+            let pos = i.get_pos();
+            return Err(errors
+                .into_iter()
+                .map(|e| e.add_pos(pos.clone()).add_instr(&i))
+                .collect());
+        }
+    };
+
+    Ok(match (i, parsed) {
+        (
             AbstractInstruction::Constant {
                 dest,
                 op,
-                const_type,
-                value,
-                #[cfg(feature = "position")]
-                pos,
-            } => Self::Constant {
-                dest,
-                op,
-                const_type: const_type
-                    .try_into()
-                    .map_err(|e: ConversionError| e.add_pos(pos.clone()))?,
+                const_type: _,
                 value,
                 #[cfg(feature = "position")]
                 pos,
+                #[cfg(feature = "comments")]
+                    comments: _,
             },
+            Parsed::Constant(const_type),
+        ) => Instruction::Constant {
+            dest,
+            op,
+            const_type,
+            value,
+            #[cfg(feature = "position")]
+            pos,
+        },
+        (
             AbstractInstruction::Value {
                 args,
                 dest,
                 funcs,
                 labels,
-                op,
-                op_type,
+                op: _,
+                op_type: _,
                 #[cfg(feature = "position")]
                 pos,
-            } => Self::Value {
-                args,
-                dest,
-                funcs,
-                labels,
-                op_type: op_type
-                    .try_into()
-                    .map_err(|e: ConversionError| e.add_pos(pos.clone()))?,
-                #[cfg(feature = "position")]
-                pos: pos.clone(),
-                op: op.parse().map_err(|e: ConversionError| e.add_pos(pos))?,
+                #[cfg(feature = "comments")]
+                    comments: _,
             },
+            Parsed::Value(op_type, op),
+        ) => Instruction::Value {
+            args,
+            dest,
+            funcs,
+            labels,
+            op,
+            op_type,
+            #[cfg(feature = "position")]
+            pos,
+        },
+        (
             AbstractInstruction::Effect {
                 args,
                 funcs,
                 labels,
-                op,
+                op: _,
                 #[cfg(feature = "position")]
                 pos,
-            } => Self::Effect {
-                args,
-                funcs,
-                labels,
-                #[cfg(feature = "position")]
-                pos: pos.clone(),
-                op: op.parse().map_err(|e: ConversionError| e.add_pos(pos))?,
+                #[cfg(feature = "comments")]
+                    comments: _,
             },
+            Parsed::Effect(op),
+        ) => Instruction::Effect {
+            args,
+            funcs,
+            labels,
+            op,
+            #[cfg(feature = "position")]
+            pos,
+        },
+        _ => unreachable!("Each instruction is parsed into its own kind"),
+    })
+}
+
+impl TryFrom<AbstractInstruction> for Instruction {
+    type Error = PositionalConversionError;
+    fn try_from(i: AbstractInstruction) -> Result<Self, Self::Error> {
+        convert_instruction(i).map_err(|errors| {
+            errors
+                .into_iter()
+                .next()
+                .expect("There is always at least one error")
         })
     }
 }
 
+impl TryFrom<Option<&AbstractType>> for Type {
+    type Error = ConversionError;
+
+    fn try_from(value: Option<&AbstractType>) -> Result<Self, Self::Error> {
+        value.map_or(Err(ConversionError::MissingType), TryInto::try_into)
+    }
+}
+
 impl TryFrom<Option<AbstractType>> for Type {
     type Error = ConversionError;
 
     fn try_from(value: Option<AbstractType>) -> Result<Self, Self::Error> {
-        value.map_or(Err(ConversionError::MissingType), TryInto::try_into)
+        value.as_ref().try_into()
     }
 }
 
-impl TryFrom<AbstractType> for Type {
+impl TryFrom<&AbstractType> for Type {
     type Error = ConversionError;
-    fn try_from(value: AbstractType) -> Result<Self, Self::Error> {
+    fn try_from(value: &AbstractType) -> Result<Self, Self::Error> {
         match value {
             AbstractType::Primitive(t) => t.parse(),
             #[cfg(feature = "memory")]
             AbstractType::Parameterized(t, ty) if t == "ptr" => {
-                Ok(Self::Pointer(Box::new((*ty).try_into()?)))
-            }
-            AbstractType::Parameterized(t, ty) => {
-                Err(ConversionError::InvalidParameterized(t, ty.to_string()))
+                Ok(Self::Pointer(Box::new((&**ty).try_into()?)))
             }
+            AbstractType::Parameterized(t, ty) => Err(ConversionError::InvalidParameterized(
+                t.clone(),
+                ty.to_string(),
+            )),
         }
     }
 }
+
+impl TryFrom<AbstractType> for Type {
+    type Error = ConversionError;
+    fn try_from(value: AbstractType) -> Result<Self, Self::Error> {
+        (&value).try_into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(json: &str) -> AbstractProgram {
+        serde_json::from_str(json).unwrap()
+    }
+
+    const TWO_BAD_FUNCTIONS: &str = r#"{"functions": [
+        {"name": "f", "args": [{"name": "x", "type": "nope"}], "instrs": [
+            {"op": "const", "dest": "a", "type": "int", "value": 1},
+            {"op": "frobnicate", "dest": "b", "type": "int", "args": ["a"]}
+        ]},
+        {"name": "main", "instrs": [
+            {"op": "jump2", "labels": ["l"]}
+        ]}
+    ]}"#;
+
+    #[test]
+    fn try_from_all_collects_every_error_in_order() {
+        let ConversionErrors(errors) =
+            Program::try_from_all(program(TWO_BAD_FUNCTIONS)).unwrap_err();
+        let context: Vec<_> = errors.iter().map(|e| (e.func(), e.instr())).collect();
+        assert_eq!(
+            context,
+            [
+                (Some("f"), Some("x: nope")),
+                (Some("f"), Some("b: int = frobnicate a;")),
+                (Some("main"), Some("jump2 .l;")),
+            ]
+        );
+        assert!(matches!(errors[0].e, ConversionError::InvalidPrimitive(_)));
+        assert!(matches!(errors[1].e, ConversionError::InvalidValueOps(_)));
+        assert!(matches!(errors[2].e, ConversionError::InvalidEffectOps(_)));
+    }
+
+    #[test]
+    fn conversion_errors_display_one_error_per_line_with_context() {
+        let errors = Program::try_from_all(program(TWO_BAD_FUNCTIONS)).unwrap_err();
+        assert_eq!(
+            errors.to_string(),
+            "Expected a primitive type like int or bool, found nope in @f: `x: nope`\n\
+             Expected an value operation, found frobnicate in @f: `b: int = frobnicate a;`\n\
+             Expected an effect operation, found jump2 in @main: `jump2 .l;`"
+        );
+    }
+
+    #[test]
+    fn try_from_keeps_the_first_error_and_its_message() {
+        let e = Program::try_from(program(TWO_BAD_FUNCTIONS)).unwrap_err();
+        assert_eq!(e.func(), Some("f"));
+        assert_eq!(
+            e.to_string(),
+            "Expected a primitive type like int or bool, found nope"
+        );
+    }
+
+    #[test]
+    fn try_from_all_accepts_valid_programs() {
+        let prog = Program::try_from_all(program(
+            r#"{"functions": [{"name": "main", "instrs": [
+                {"op": "const", "dest": "a", "type": "int", "value": 1},
+                {"op": "print", "args": ["a"]}
+            ]}]}"#,
+        ))
+        .unwrap();
+        assert_eq!(prog.functions[0].instrs.len(), 2);
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::{
+    AbstractCode, AbstractInstruction, AbstractProgram, AbstractType, Code, Instruction, Program,
+    Type,
+};
+
+/// A Bril language extension which can be used on top of the Bril core
+///
+/// Unlike the matching cargo features, every extension is always available here so that tools built with only some of the features can still name the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Extension {
+    /// <https://capra.cs.cornell.edu/bril/lang/float.html>
+    Float,
+    /// <https://capra.cs.cornell.edu/bril/lang/memory.html>
+    Memory,
+    /// <https://capra.cs.cornell.edu/bril/lang/ssa2.html>
+    Ssa,
+    /// <https://capra.cs.cornell.edu/bril/lang/spec.html>
+    Speculate,
+    /// <https://capra.cs.cornell.edu/bril/lang/char.html>
+    Char,
+    /// <https://capra.cs.cornell.edu/bril/lang/bitcast.html>
+    Bitcast,
+    /// <https://capra.cs.cornell.edu/bril/lang/dynamic.html>
+    Dynamic,
+    /// <https://capra.cs.cornell.edu/bril/lang/import.html>
+    Import,
+}
+
+impl Extension {
+    /// The extension which introduces the operation `op`, or `None` if it is part of the Bril core
+    /// # Errors
+    /// Returns `Err` if `op` is not an operation of the Bril core or of any extension
+    pub fn of_op(op: &str) -> Result<Option<Self>, UnknownError> {
+        Ok(match op {
+            "const" | "add" | "sub" | "mul" | "div" | "eq" | "lt" | "gt" | "le" | "ge" | "not"
+            | "and" | "or" | "call" | "id" | "jmp" | "br" | "ret" | "print" | "nop" => None,
+            "fadd" | "fsub" | "fmul" | "fdiv" | "feq" | "flt" | "fgt" | "fle" | "fge" => {
+                Some(Self::Float)
+            }
+            "alloc" | "load" | "ptradd" | "store" | "free" => Some(Self::Memory),
+            "get" | "undef" | "set" => Some(Self::Ssa),
+            "speculate" | "commit" | "guard" => Some(Self::Speculate),
+            "ceq" | "clt" | "cgt" | "cle" | "cge" | "char2int" | "int2char" => Some(Self::Char),
+            "float2bits" | "bits2float" => Some(Self::Bitcast),
+            _ => return Err(UnknownError::Op(op.to_string())),
+        })
+    }
+
+    /// The extension which introduces the type `ty`, or `None` if it is part of the Bril core
+    /// # Errors
+    /// Returns `Err` if `ty` is not a type of the Bril core or of any extension
+    pub fn of_type(ty: &AbstractType) -> Result<Option<Self>, UnknownError> {
+        Ok(match ty {
+            AbstractType::Primitive(t) => match t.as_str() {
+                "int" | "bool" => None,
+                "float" => Some(Self::Float),
+                "char" => Some(Self::Char),
+                "any" => Some(Self::Dynamic),
+                _ => return Err(UnknownError::Type(ty.to_string())),
+            },
+            AbstractType::Parameterized(t, _) if t == "ptr" => Some(Self::Memory),
+            AbstractType::Parameterized(..) => return Err(UnknownError::Type(ty.to_string())),
+        })
+    }
+}
+
+impl Display for Extension {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Float => write!(f, "float"),
+            Self::Memory => write!(f, "memory"),
+            Self::Ssa => write!(f, "ssa"),
+            Self::Speculate => write!(f, "speculate"),
+            Self::Char => write!(f, "char"),
+            Self::Bitcast => write!(f, "bitcast"),
+            Self::Dynamic => write!(f, "dynamic"),
+            Self::Import => write!(f, "import"),
+        }
+    }
+}
+
+impl FromStr for Extension {
+    type Err = UnknownError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "float" => Self::Float,
+            "memory" => Self::Memory,
+            "ssa" => Self::Ssa,
+            "speculate" => Self::Speculate,
+            "char" => Self::Char,
+            "bitcast" => Self::Bitcast,
+            "dynamic" => Self::Dynamic,
+            "import" => Self::Import,
+            e => Err(UnknownError::Extension(e.to_string()))?,
+        })
+    }
+}
+
+/// Something that is not part of the Bril core or any known extension
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum UnknownError {
+    /// Unknown operation {0}
+    #[error("Unknown operation {0}")]
+    Op(String),
+    /// Unknown type {0}
+    #[error("Unknown type {0}")]
+    Type(String),
+    /// Unknown extension {0}
+    #[error("Unknown extension {0}")]
+    Extension(String),
+}
+
+/// A program relies on something which a tool does not support
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ExtensionError {
+    /// The {0} extension is not supported but is used by: {1}
+    #[error("The {0} extension is not supported but is used by: {1}")]
+    Unsupported(Extension, String),
+    /// {0}
+    #[error(transparent)]
+    Unknown(#[from] UnknownError),
+}
+
+/// The extensions and operations that a program relies on
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtensionUsage {
+    /// Each extension used, along with the operations, types, or imports which use it
+    pub extensions: BTreeMap<Extension, BTreeSet<String>>,
+    /// Every operation used in the program
+    pub ops: BTreeSet<String>,
+    /// Operations and types which are not part of the Bril core or any known extension
+    pub unknown: BTreeSet<String>,
+}
+
+impl ExtensionUsage {
+    fn add_op(&mut self, op: &str) {
+        self.ops.insert(op.to_string());
+        match Extension::of_op(op) {
+            Ok(Some(ext)) => {
+                self.extensions
+                    .entry(ext)
+                    .or_default()
+                    .insert(op.to_string());
+            }
+            Ok(None) => {}
+            Err(_) => {
+                self.unknown.insert(op.to_string());
+            }
+        }
+    }
+
+    fn add_type(&mut self, ty: &AbstractType) {
+        match Extension::of_type(ty) {
+            Ok(Some(ext)) => {
+                self.extensions
+                    .entry(ext)
+                    .or_default()
+                    .insert(ty.to_string());
+            }
+            Ok(None) => {}
+            Err(_) => {
+                self.unknown.insert(ty.to_string());
+            }
+        }
+        if let AbstractType::Parameterized(_, inner) = ty {
+            self.add_type(inner);
+        }
+    }
+
+    #[cfg(feature = "import")]
+    fn add_imports(&mut self, imports: &[crate::Import]) {
+        if !imports.is_empty() {
+            self.extensions
+                .entry(Extension::Import)
+                .or_default()
+                .extend(
+                    imports
+                        .iter()
+                        .map(|i| format!("import {}", i.path.display())),
+                );
+        }
+    }
+
+    /// The extensions used by the program
+    pub fn used(&self) -> impl Iterator<Item = Extension> + '_ {
+        self.extensions.keys().copied()
+    }
+
+    /// Checks that the program only relies on the Bril core and the `supported` extensions
+    /// # Errors
+    /// Returns `Err` for the first unknown operation or type, or else for the first extension which is not supported
+    pub fn check(&self, supported: &[Extension]) -> Result<(), ExtensionError> {
+        if let Some(u) = self.unknown.first() {
+            return Err(if self.ops.contains(u) {
+                UnknownError::Op(u.clone())
+            } else {
+                UnknownError::Type(u.clone())
+            }
+            .into());
+        }
+        for (ext, uses) in &self.extensions {
+            if !supported.contains(ext) {
+                let uses: Vec<_> = uses.iter().map(String::as_str).collect();
+                return Err(ExtensionError::Unsupported(*ext, uses.join(", ")));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<&AbstractProgram> for ExtensionUsage {
+    fn from(prog: &AbstractProgram) -> Self {
+        let mut usage = Self::default();
+        #[cfg(feature = "import")]
+        usage.add_imports(&prog.imports);
+        for func in &prog.functions {
+            for arg in &func.args {
+                usage.add_type(&arg.arg_type);
+            }
+            if let Some(ty) = &func.return_type {
+                usage.add_type(ty);
+            }
+            for code in &func.instrs {
+                match code {
+                    AbstractCode::Label { .. } => {}
+                    AbstractCode::Instruction(AbstractInstruction::Constant {
+                        op,
+                        const_type,
+                        ..
+                    }) => {
+                        usage.add_op(&op.to_string());
+                        if let Some(ty) = const_type {
+                            usage.add_type(ty);
+                        }
+                    }
+                    AbstractCode::Instruction(AbstractInstruction::Value {
+                        op, op_type, ..
+                    }) => {
+                        usage.add_op(op);
+                        if let Some(ty) = op_type {
+                            usage.add_type(ty);
+                        }
+                    }
+                    AbstractCode::Instruction(AbstractInstruction::Effect { op, .. }) => {
+                        usage.add_op(op);
+                    }
+                }
+            }
+        }
+        usage
+    }
+}
+
+impl From<&Program> for ExtensionUsage {
+    fn from(prog: &Program) -> Self {
+        let mut usage = Self::default();
+        #[cfg(feature = "import")]
+        usage.add_imports(&prog.imports);
+        for func in &prog.functions {
+            for arg in &func.args {
+                usage.add_type(&abstract_type(&arg.arg_type));
+            }
+            if let Some(ty) = &func.return_type {
+                usage.add_type(&abstract_type(ty));
+            }
+            for code in &func.instrs {
+                match code {
+                    Code::Label { .. } => {}
+                    Code::Instruction(Instruction::Constant { op, const_type, .. }) => {
+                        usage.add_op(&op.to_string());
+                        usage.add_type(&abstract_type(const_type));
+                    }
+                    Code::Instruction(Instruction::Value { op, op_type, .. }) => {
+                        usage.add_op(&op.to_string());
+                        usage.add_type(&abstract_type(op_type));
+                    }
+                    Code::Instruction(Instruction::Effect { op, .. }) => {
+                        usage.add_op(&op.to_string());
+                    }
+                }
+            }
+        }
+        usage
+    }
+}
+
+// The unstructured form of a type so that both representations are checked the same way
+fn abstract_type(ty: &Type) -> AbstractType {
+    match ty {
+        #[cfg(feature = "memory")]
+        Type::Pointer(inner) => {
+            AbstractType::Parameterized("ptr".to_string(), Box::new(abstract_type(inner)))
+        }
+        t => AbstractType::Primitive(t.to_string()),
+    }
+}
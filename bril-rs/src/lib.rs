@@ -5,8 +5,12 @@
 
 /// Provides the unstructured representation of Bril programs
 pub mod abstract_program;
+/// Provides equality and hashing of Bril functions up to the renaming of variables and labels
+pub mod alpha;
 /// Provides the Error handling and conversion between [`AbstractProgram`] and [Program]
 pub mod conversion;
+/// Provides detection of which Bril extensions a program uses
+pub mod extensions;
 /// Provides the structured representation of Bril programs
 pub mod program;
 pub use abstract_program::*;
@@ -42,12 +42,18 @@ pub struct Import {
     pub functions: Vec<ImportedFunction>,
     /// The relative path of the file from some lib directory specified by the user
     pub path: std::path::PathBuf,
+    /// Any comments attached to this import in the original source code
+    #[cfg(feature = "comments")]
+    #[serde(default, skip_serializing_if = "Comments::is_empty")]
+    pub comments: Comments,
 }
 
 #[cfg(feature = "import")]
 impl Display for Import {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "from {}", self.path.display())?;
+        #[cfg(feature = "comments")]
+        self.comments.fmt_leading(f, "")?;
+        write!(f, "from \"{}\"", self.path.display())?;
         if !self.functions.is_empty() {
             write!(f, " import ")?;
             for (i, name) in self.functions.iter().enumerate() {
@@ -58,6 +64,8 @@ impl Display for Import {
             }
         }
         write!(f, ";")?;
+        #[cfg(feature = "comments")]
+        self.comments.fmt_trailing(f)?;
         Ok(())
     }
 }
@@ -76,9 +84,9 @@ pub struct ImportedFunction {
 #[cfg(feature = "import")]
 impl Display for ImportedFunction {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.name)?;
+        write!(f, "@{}", self.name)?;
         if let Some(a) = self.alias.as_ref() {
-            write!(f, " as {a}")?;
+            write!(f, " as @{a}")?;
         }
         Ok(())
     }
@@ -805,3 +813,47 @@ pub struct ColRow {
     /// Row
     pub row: u64,
 }
+
+/// Comments and blank lines attached to a piece of Bril code in the text format
+///
+/// Each entry of `leading` is either a full comment line, including its `#`, or an empty string for a blank line.
+/// Runs of blank lines are collapsed into a single empty entry.
+#[cfg(feature = "comments")]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Comments {
+    /// Comment lines and blank lines directly preceding the code
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub leading: Vec<String>,
+    /// A comment on the same line, after the code
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trailing: Option<String>,
+}
+
+#[cfg(feature = "comments")]
+impl Comments {
+    /// Whether there are no comments to attach
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.leading.is_empty() && self.trailing.is_none()
+    }
+
+    /// Writes each leading line with `indent`, leaving blank lines empty
+    pub(crate) fn fmt_leading(&self, f: &mut Formatter<'_>, indent: &str) -> fmt::Result {
+        for line in &self.leading {
+            if line.is_empty() {
+                writeln!(f)?;
+            } else {
+                writeln!(f, "{indent}{line}")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the trailing comment, if any, after a single space
+    pub(crate) fn fmt_trailing(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(c) = self.trailing.as_ref() {
+            write!(f, " {c}")?;
+        }
+        Ok(())
+    }
+}
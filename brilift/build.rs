@@ -0,0 +1,62 @@
+use std::{env, fs, path::PathBuf, process::Command};
+
+// Builds the runtime library into a static library so that it can be embedded into brilift and
+// linked into executables
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let runtime_dir = manifest_dir.join("runtime");
+    // A separate target directory avoids waiting on the lock held by the cargo building brilift
+    let target_dir = out_dir.join("runtime");
+
+    println!(
+        "cargo::rerun-if-changed={}",
+        runtime_dir.join("src").display()
+    );
+    println!(
+        "cargo::rerun-if-changed={}",
+        runtime_dir.join("Cargo.toml").display()
+    );
+    // The runtime wraps the JIT's runtime
+    println!(
+        "cargo::rerun-if-changed={}",
+        manifest_dir.join("src").join("rt.rs").display()
+    );
+
+    let output = Command::new(env::var("CARGO").unwrap())
+        .args(["rustc", "--lib", "--release", "--manifest-path"])
+        .arg(runtime_dir.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .args(["--", "--print", "native-static-libs"])
+        // These are meant for brilift and not the runtime
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .env_remove("RUSTFLAGS")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "Failed to build the runtime library:\n{stderr}"
+    );
+
+    // rustc only reports the system libraries that the runtime needs when it actually compiles
+    // it, so keep them around for when the runtime is already up to date
+    let libs_file = out_dir.join("native-static-libs.txt");
+    if let Some(libs) = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("note: native-static-libs: "))
+    {
+        fs::write(&libs_file, libs).unwrap();
+    }
+    assert!(
+        libs_file.exists(),
+        "The runtime library did not report its native libraries; try `cargo clean`"
+    );
+
+    fs::copy(
+        target_dir.join("release").join("libbrilift_rt.a"),
+        out_dir.join("libbrilift_rt.a"),
+    )
+    .unwrap();
+}
@@ -33,6 +33,25 @@ void _bril_print_float(double f) {
     }
 }
 
+void _bril_print_char(uint32_t c) {
+    // Encode the code point as UTF-8.
+    if (c < 0x80) {
+        putchar(c);
+    } else if (c < 0x800) {
+        putchar(0xC0 | (c >> 6));
+        putchar(0x80 | (c & 0x3F));
+    } else if (c < 0x10000) {
+        putchar(0xE0 | (c >> 12));
+        putchar(0x80 | ((c >> 6) & 0x3F));
+        putchar(0x80 | (c & 0x3F));
+    } else {
+        putchar(0xF0 | (c >> 18));
+        putchar(0x80 | ((c >> 12) & 0x3F));
+        putchar(0x80 | ((c >> 6) & 0x3F));
+        putchar(0x80 | (c & 0x3F));
+    }
+}
+
 void _bril_print_sep() {
     printf(" ");
 }
@@ -60,10 +79,185 @@ double _bril_parse_float(char **args, int64_t idx) {
     return res;
 }
 
+static void _bril_fail_at(const char *msg, int64_t row, int64_t col);
+
+uint32_t _bril_parse_char(char **args, int64_t idx) {
+    // Decode the first UTF-8 code point.
+    unsigned char *arg = (unsigned char *)args[idx];
+    uint32_t c;
+    if (arg[0] < 0x80) {
+        c = arg[0];
+    } else if (arg[0] < 0xE0) {
+        c = ((arg[0] & 0x1F) << 6) | (arg[1] & 0x3F);
+    } else if (arg[0] < 0xF0) {
+        c = ((arg[0] & 0x0F) << 12) | ((arg[1] & 0x3F) << 6) | (arg[2] & 0x3F);
+    } else {
+        c = ((arg[0] & 0x07) << 18) | ((arg[1] & 0x3F) << 12) |
+            ((arg[2] & 0x3F) << 6) | (arg[3] & 0x3F);
+    }
+    // Malformed UTF-8 can decode to surrogates or values past the last code point.
+    if ((c >= 0xD800 && c < 0xE000) || c >= 0x110000) {
+        char msg[64];
+        snprintf(msg, sizeof msg, "value %" PRIu32 " cannot be converted to char", c);
+        _bril_fail_at(msg, 0, 0);
+    }
+    return c;
+}
+
+void _bril_print_dyn_inst(int64_t count) {
+    // Like `brilirs -p`, report the count once all the output is done.
+    fflush(stdout);
+    fprintf(stderr, "total_dyn_inst: %" PRId64 "\n", count);
+}
+
 void *_bril_alloc(int64_t size, int64_t bytes) {
-    return malloc(size * bytes);
+    void *ptr = NULL;
+    int64_t total;
+    if (size >= 0 && !__builtin_mul_overflow(size, bytes, &total) && total < INT64_MAX) {
+        // Always allocate something so that a NULL result means failure.
+        ptr = malloc((size_t)total + 1);
+    }
+    if (!ptr) {
+        fflush(stdout);
+        fprintf(stderr, "error: cannot allocate `%" PRId64 "` entries\n", size);
+        exit(2);
+    }
+    return ptr;
 }
 
 void _bril_free(void *ptr) {
     free(ptr);
 }
+
+// Checked mode: report the same errors as brilirs, with the Bril source position (a row of 0 means
+// there is no position), and exit with brilirs's error code.
+
+static void _bril_fail_at(const char *msg, int64_t row, int64_t col) {
+    fflush(stdout);
+    if (row) {
+        fprintf(stderr, "error: Line %" PRId64 ", Column %" PRId64 ": %s\n", row, col, msg);
+    } else {
+        fprintf(stderr, "error: %s\n", msg);
+    }
+    exit(2);
+}
+
+// The live allocations, sorted by address.
+typedef struct {
+    uintptr_t base;
+    size_t size;
+    size_t elem_size;
+    int64_t index;
+} _bril_allocation;
+
+static _bril_allocation *_bril_live = NULL;
+static size_t _bril_live_len = 0;
+static size_t _bril_live_cap = 0;
+static int64_t _bril_alloc_count = 0;
+
+// Find the number of live allocations that start at or before `ptr`.
+static size_t _bril_live_upper(uintptr_t ptr) {
+    size_t lo = 0, hi = _bril_live_len;
+    while (lo < hi) {
+        size_t mid = lo + (hi - lo) / 2;
+        if (_bril_live[mid].base <= ptr) {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    return lo;
+}
+
+// Describe a pointer like brilirs does: the index of the nearest allocation at or before it, and
+// its offset in elements.
+static void _bril_describe(char *buf, size_t len, const char *fmt, uintptr_t ptr) {
+    size_t i = _bril_live_upper(ptr);
+    if (i) {
+        _bril_allocation *a = &_bril_live[i - 1];
+        char index[32];
+        snprintf(index, sizeof index, "%" PRId64, a->index);
+        snprintf(buf, len, fmt, index, (int64_t)((ptr - a->base) / a->elem_size));
+    } else {
+        char addr[32];
+        snprintf(addr, sizeof addr, "%#" PRIxPTR, ptr);
+        snprintf(buf, len, fmt, addr, (int64_t)0);
+    }
+}
+
+void *_bril_checked_alloc(int64_t size, int64_t bytes, int64_t row, int64_t col) {
+    void *ptr = NULL;
+    int64_t total;
+    if (size >= 0 && !__builtin_mul_overflow(size, bytes, &total) && total < INT64_MAX) {
+        ptr = malloc((size_t)total + 1);
+    }
+    if (ptr && _bril_live_len == _bril_live_cap) {
+        _bril_live_cap = _bril_live_cap ? 2 * _bril_live_cap : 16;
+        _bril_live = realloc(_bril_live, _bril_live_cap * sizeof *_bril_live);
+        if (!_bril_live) {
+            ptr = NULL;
+        }
+    }
+    if (!ptr) {
+        char msg[64];
+        snprintf(msg, sizeof msg, "cannot allocate `%" PRId64 "` entries", size);
+        _bril_fail_at(msg, row, col);
+    }
+
+    size_t i = _bril_live_upper((uintptr_t)ptr);
+    memmove(&_bril_live[i + 1], &_bril_live[i], (_bril_live_len - i) * sizeof *_bril_live);
+    _bril_live[i] = (_bril_allocation){
+        (uintptr_t)ptr, (size_t)total, bytes > 0 ? bytes : 1, _bril_alloc_count++,
+    };
+    _bril_live_len++;
+    return ptr;
+}
+
+void _bril_checked_free(void *ptr, int64_t row, int64_t col) {
+    size_t i = _bril_live_upper((uintptr_t)ptr);
+    if (!i || _bril_live[i - 1].base != (uintptr_t)ptr) {
+        char msg[128];
+        _bril_describe(msg, sizeof msg,
+            "Tried to free illegal memory location index: `%s`, offset: `%" PRId64 "`. Offset must be 0.",
+            (uintptr_t)ptr);
+        _bril_fail_at(msg, row, col);
+    }
+    memmove(&_bril_live[i - 1], &_bril_live[i], (_bril_live_len - i) * sizeof *_bril_live);
+    _bril_live_len--;
+    free(ptr);
+}
+
+void _bril_check_access(void *ptr, int64_t bytes, int64_t row, int64_t col) {
+    uintptr_t p = (uintptr_t)ptr;
+    size_t i = _bril_live_upper(p);
+    if (!i || p + bytes > _bril_live[i - 1].base + _bril_live[i - 1].size) {
+        char msg[128];
+        _bril_describe(msg, sizeof msg,
+            "Uninitialized heap location `%s` and/or illegal offset `%" PRId64 "`", p);
+        _bril_fail_at(msg, row, col);
+    }
+}
+
+void _bril_div_by_zero(int64_t row, int64_t col) {
+    _bril_fail_at("Attempt to divide by 0", row, col);
+}
+
+void _bril_bad_char(int64_t value, int64_t row, int64_t col) {
+    char msg[64];
+    snprintf(msg, sizeof msg, "value %" PRId64 " cannot be converted to char", value);
+    _bril_fail_at(msg, row, col);
+}
+
+void _bril_bad_commit(int64_t row, int64_t col) {
+    _bril_fail_at("commit in non-speculative state", row, col);
+}
+
+void _bril_bad_abort(int64_t row, int64_t col) {
+    _bril_fail_at("abort in non-speculative state", row, col);
+}
+
+void _bril_check_leaks(int64_t row, int64_t col) {
+    if (_bril_live_len) {
+        _bril_fail_at("Some memory locations have not been freed by the end of execution", row, col);
+    }
+}
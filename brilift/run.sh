@@ -11,9 +11,13 @@ set -e
 
 HERE=`dirname $0`
 
+# Extra flags for Brilift itself, such as -p to count dynamic instructions,
+# can go in $BRILIFT_FLAGS.
+BFLAGS="$BRILIFT_FLAGS"
+
 if [ -n "$TARGET" ]; then
     CFLAGS="-target $TARGET"
-    BFLAGS="-t $TARGET"
+    BFLAGS="$BFLAGS -t $TARGET"
 fi
 
 tmpdir=`mktemp -d`
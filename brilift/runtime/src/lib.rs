@@ -0,0 +1,102 @@
+//! The runtime library that brilift links into executables, built by `build.rs`. It exports the
+//! same functions as `rt.c` by wrapping the JIT's runtime in `rt.rs`, using its list of functions
+//! so that none can be missed. Instead of returning to compiled code after a failure, it exits
+//! with brilirs's error code.
+
+use std::ffi::{CStr, c_char};
+use std::io::Write;
+
+#[path = "../../src/rt.rs"]
+mod rt;
+
+/// Print an error and exit like brilirs does.
+fn exit_with(msg: &str) -> ! {
+    std::io::stdout().flush().unwrap();
+    eprintln!("error: {msg}");
+    std::process::exit(2)
+}
+
+/// Exit if the last call into `rt.rs` failed.
+fn check() {
+    if let Some(msg) = rt::take_error() {
+        exit_with(&msg);
+    }
+}
+
+/// Get a command-line argument for `@main`.
+///
+/// # Safety
+/// `args` must be `argv`, and `idx` must be less than `argc`.
+unsafe fn arg<'a>(args: *const *const c_char, idx: i64) -> &'a str {
+    let arg = unsafe { CStr::from_ptr(*args.offset(idx as isize)) };
+    arg.to_str()
+        .unwrap_or_else(|_| exit_with("arguments must be valid UTF-8"))
+}
+
+/// Export each function in `rt::for_each_rt_func`, exiting if it fails.
+macro_rules! export {
+    ($($symbol:ident => $func:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)?;)*) => {$(
+        #[unsafe(no_mangle)]
+        pub extern "C" fn $symbol($($arg: $ty),*) $(-> $ret)? {
+            let result = rt::$func($($arg),*);
+            check();
+            result
+        }
+    )*};
+}
+rt::for_each_rt_func!(export);
+
+/// # Safety
+/// `args` must be `argv`, and `idx` must be less than `argc`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn _bril_parse_int(args: *const *const c_char, idx: i64) -> i64 {
+    let arg = unsafe { arg(args, idx) };
+    arg.parse()
+        .unwrap_or_else(|_| exit_with(&format!("invalid int argument `{arg}`")))
+}
+
+/// # Safety
+/// `args` must be `argv`, and `idx` must be less than `argc`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn _bril_parse_bool(args: *const *const c_char, idx: i64) -> bool {
+    match unsafe { arg(args, idx) } {
+        "true" => true,
+        "false" => false,
+        arg => exit_with(&format!("invalid bool argument `{arg}`")),
+    }
+}
+
+/// # Safety
+/// `args` must be `argv`, and `idx` must be less than `argc`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn _bril_parse_float(args: *const *const c_char, idx: i64) -> f64 {
+    let arg = unsafe { arg(args, idx) };
+    arg.parse()
+        .unwrap_or_else(|_| exit_with(&format!("invalid float argument `{arg}`")))
+}
+
+/// # Safety
+/// `args` must be `argv`, and `idx` must be less than `argc`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn _bril_parse_char(args: *const *const c_char, idx: i64) -> u32 {
+    let arg = unsafe { arg(args, idx) };
+    let mut chars = arg.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => c.into(),
+        _ => exit_with(&format!("invalid char argument `{arg}`")),
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _bril_print_dyn_inst(count: i64) {
+    // Like `brilirs -p`, report the count once all the output is done.
+    std::io::stdout().flush().unwrap();
+    eprintln!("total_dyn_inst: {count}");
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _bril_check_leaks(row: i64, col: i64) {
+    if let Some(msg) = rt::check_leaks(row, col) {
+        exit_with(&msg);
+    }
+}
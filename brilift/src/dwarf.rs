@@ -0,0 +1,241 @@
+//! DWARF debug info for AOT-compiled code, so that debuggers and profilers can map machine code
+//! back to lines in the Bril source.
+
+use cranelift_module::FuncId;
+use gimli::write::{
+    Address, AttributeValue, DwarfUnit, EndianVec, LineProgram, LineString, Range, RangeList,
+    RelocateWriter, Relocation, RelocationTarget, Sections, Writer,
+};
+use gimli::{Encoding, Format, LineEncoding, RunTimeEndian};
+use object::write::{Object, StandardSegment, SymbolId};
+use object::{BinaryFormat, RelocationEncoding, RelocationFlags, RelocationKind, SectionKind};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A source line for the machine code starting at `offset` in a function.
+pub struct Row {
+    pub offset: u32,
+    pub line: u64,
+    pub column: u64,
+}
+
+/// The line table for one compiled function.
+pub struct FuncLines {
+    pub id: FuncId,
+    /// The Bril name of the function.
+    pub name: String,
+    /// The line where the function is declared, if known.
+    pub line: Option<u64>,
+    /// The size of the function's machine code.
+    pub size: u32,
+    pub rows: Vec<Row>,
+}
+
+/// The line tables for all the functions compiled from one source file.
+pub struct DebugInfo {
+    source: String,
+    funcs: Vec<FuncLines>,
+}
+
+/// A section of DWARF data, along with the relocations it needs once it is in the object file.
+#[derive(Clone)]
+struct DwarfSection {
+    data: EndianVec<RunTimeEndian>,
+    relocs: Vec<Relocation>,
+}
+
+impl RelocateWriter for DwarfSection {
+    type Writer = EndianVec<RunTimeEndian>;
+
+    fn writer(&self) -> &Self::Writer {
+        &self.data
+    }
+
+    fn writer_mut(&mut self) -> &mut Self::Writer {
+        &mut self.data
+    }
+
+    fn relocate(&mut self, relocation: Relocation) {
+        self.relocs.push(relocation);
+    }
+}
+
+impl DebugInfo {
+    /// Start collecting debug info for the Bril program in the file `source`.
+    pub fn new(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            funcs: Vec::new(),
+        }
+    }
+
+    pub fn add_func(&mut self, func: FuncLines) {
+        self.funcs.push(func);
+    }
+
+    /// Add the debug sections to an object file. `symbol` gives the symbol for each function.
+    pub fn write(
+        &self,
+        obj: &mut Object,
+        address_size: u8,
+        endian: RunTimeEndian,
+        symbol: impl Fn(FuncId) -> SymbolId,
+    ) -> gimli::write::Result<()> {
+        let encoding = Encoding {
+            format: Format::Dwarf32,
+            version: 4,
+            address_size,
+        };
+        let mut dwarf = DwarfUnit::new(encoding);
+
+        // Describe the source file.
+        let path = Path::new(&self.source);
+        let dir = path
+            .parent()
+            .map_or_else(String::new, |d| d.display().to_string());
+        let file = path
+            .file_name()
+            .map_or_else(|| self.source.clone(), |f| f.to_string_lossy().into_owned());
+        let dir_string = LineString::new(dir.as_bytes(), encoding, &mut dwarf.line_strings);
+        let file_string = LineString::new(file.as_bytes(), encoding, &mut dwarf.line_strings);
+        dwarf.unit.line_program = LineProgram::new(
+            encoding,
+            LineEncoding::default(),
+            dir_string,
+            None,
+            file_string.clone(),
+            None,
+        );
+        let dir_id = dwarf.unit.line_program.default_directory();
+        let file_id = dwarf.unit.line_program.add_file(file_string, dir_id, None);
+
+        // Generate a line sequence and a subprogram for each function. Addresses are relative to
+        // the function's symbol, which is the `i`th entry of `self.funcs`.
+        let root = dwarf.unit.root();
+        let mut ranges = Vec::new();
+        for (i, func) in self.funcs.iter().enumerate() {
+            let start = Address::Symbol {
+                symbol: i,
+                addend: 0,
+            };
+
+            let program = &mut dwarf.unit.line_program;
+            program.begin_sequence(Some(start));
+            for row in &func.rows {
+                program.row().address_offset = row.offset.into();
+                program.row().file = file_id;
+                program.row().line = row.line;
+                program.row().column = row.column;
+                program.generate_row();
+            }
+            program.end_sequence(func.size.into());
+
+            let subprogram = dwarf.unit.add(root, gimli::DW_TAG_subprogram);
+            let entry = dwarf.unit.get_mut(subprogram);
+            entry.set(
+                gimli::DW_AT_name,
+                AttributeValue::String(func.name.as_bytes().to_vec()),
+            );
+            entry.set(gimli::DW_AT_low_pc, AttributeValue::Address(start));
+            entry.set(
+                gimli::DW_AT_high_pc,
+                AttributeValue::Udata(func.size.into()),
+            );
+            if let Some(line) = func.line {
+                entry.set(
+                    gimli::DW_AT_decl_file,
+                    AttributeValue::FileIndex(Some(file_id)),
+                );
+                entry.set(gimli::DW_AT_decl_line, AttributeValue::Udata(line));
+            }
+
+            ranges.push(Range::StartLength {
+                begin: start,
+                length: func.size.into(),
+            });
+        }
+
+        // Describe the compilation unit.
+        let range_list = dwarf.unit.ranges.add(RangeList(ranges));
+        let entry = dwarf.unit.get_mut(root);
+        entry.set(
+            gimli::DW_AT_producer,
+            AttributeValue::String(b"brilift".to_vec()),
+        );
+        entry.set(
+            gimli::DW_AT_name,
+            AttributeValue::String(self.source.as_bytes().to_vec()),
+        );
+        entry.set(
+            gimli::DW_AT_comp_dir,
+            AttributeValue::String(dir.into_bytes()),
+        );
+        entry.set(
+            gimli::DW_AT_low_pc,
+            AttributeValue::Address(Address::Constant(0)),
+        );
+        entry.set(
+            gimli::DW_AT_ranges,
+            AttributeValue::RangeListRef(range_list),
+        );
+
+        let mut sections = Sections::new(DwarfSection {
+            data: EndianVec::new(endian),
+            relocs: Vec::new(),
+        });
+        dwarf.write(&mut sections)?;
+
+        // Add each section to the object, and then the relocations, which may refer to any section.
+        // Mach-O has its own section names, and its tools expect references between debug sections
+        // to be plain offsets instead.
+        let macho = obj.format() == BinaryFormat::MachO;
+        let in_place =
+            |reloc: &Relocation| macho && matches!(reloc.target, RelocationTarget::Section(_));
+        let mut section_ids = HashMap::new();
+        sections.for_each(|id, section| -> gimli::write::Result<()> {
+            if section.data.slice().is_empty() {
+                return Ok(());
+            }
+            let mut data = section.data.clone();
+            for reloc in section.relocs.iter().filter(|r| in_place(r)) {
+                data.write_udata_at(reloc.offset, reloc.addend as u64, reloc.size)?;
+            }
+            let name = if macho {
+                id.name().replacen('.', "__", 1)
+            } else {
+                id.name().to_string()
+            };
+            let section_id = obj.add_section(
+                obj.segment_name(StandardSegment::Debug).to_vec(),
+                name.into_bytes(),
+                SectionKind::Debug,
+            );
+            obj.set_section_data(section_id, data.into_vec(), 1);
+            section_ids.insert(id, section_id);
+            Ok(())
+        })?;
+        sections.for_each(|id, section| -> gimli::write::Result<()> {
+            for reloc in section.relocs.iter().filter(|r| !in_place(r)) {
+                let target = match reloc.target {
+                    RelocationTarget::Symbol(i) => symbol(self.funcs[i].id),
+                    RelocationTarget::Section(target) => obj.section_symbol(section_ids[&target]),
+                };
+                obj.add_relocation(
+                    section_ids[&id],
+                    object::write::Relocation {
+                        offset: reloc.offset as u64,
+                        symbol: target,
+                        addend: reloc.addend,
+                        flags: RelocationFlags::Generic {
+                            kind: RelocationKind::Absolute,
+                            encoding: RelocationEncoding::Generic,
+                            size: reloc.size * 8,
+                        },
+                    },
+                )
+                .map_err(|_| gimli::write::Error::InvalidAddress)?;
+            }
+            Ok(())
+        })
+    }
+}
@@ -1,78 +1,209 @@
+mod dwarf;
 mod rt;
 pub mod translator;
 
-use crate::translator::{Translator, find_func};
+// Re-exported for library users of `Translator<JITModule>`.
+pub use cranelift_jit::JITModule;
+pub use cranelift_module::FuncId;
+
+use crate::translator::{Translator, check_supported, find_func};
 use bril_rs as bril;
 use bril_rs::Program;
-use cranelift_jit::JITModule;
 use cranelift_object::ObjectModule;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+/// An error from just-in-time compiling and running a Bril program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JitError {
+    /// The program uses something that brilift can not compile.
+    Unsupported(String),
+    /// The arguments do not match the parameters of `@main`.
+    Args(String),
+    /// The program failed while running (e.g., an allocation failed).
+    Runtime(String),
+}
+
+impl fmt::Display for JitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported(msg) | Self::Args(msg) | Self::Runtime(msg) => write!(f, "{msg}"),
+        }
+    }
+}
 
-/// Compile a program ahead of time to an object file.
+impl std::error::Error for JitError {}
+
+/// Compile a program ahead of time to an object file. Use `link` to turn it into an executable.
+/// Returns an error if the program uses something that brilift can not compile.
 ///
 /// * `program` - the Bril program to compile
 /// * `target` - the target triple, or None to target the host
 /// * `output` - the filename where we should write the object file
 /// * `opt_level` - a Cranelift optimization level
 /// * `dump_ir` - optionally emit the Cranelift IR to stdout
+/// * `profile` - count dynamic instructions and print the total to stderr on exit
+/// * `checked` - report the runtime errors that brilirs reports instead of crashing
+/// * `debug_source` - optionally emit DWARF line info for the program, which is in this file
+#[expect(clippy::too_many_arguments)]
 pub fn compile(
     program: &Program,
     target: Option<String>,
     output: &str,
     opt_level: &str,
     dump_ir: bool,
-) {
+    profile: bool,
+    checked: bool,
+    debug_source: Option<&str>,
+) -> Result<(), String> {
+    check_supported(program)?;
+
     // Compile.
     let mut trans = Translator::<ObjectModule>::new(target, opt_level);
+    if profile {
+        trans.enable_profiling();
+    }
+    if checked {
+        trans.enable_checks();
+    }
+    if let Some(source) = debug_source {
+        trans.enable_debug_info(source);
+    }
     trans.compile_prog(program, dump_ir);
 
     // Add a C-style `main` wrapper.
     let main = find_func(&program.functions, "main");
-    trans.add_c_main(&main.args, dump_ir);
+    trans.add_c_main(main, dump_ir);
 
     // Write object file.
     trans.emit(output);
+    Ok(())
 }
 
 /// Just-in-time compile and execute a Bril program.
 ///
+/// Returns once `@main` returns, or with an error if the program fails while running.
+///
 /// * `program` - the Bril program to compile
 /// * `args` - the arguments to pass to the `@main` function
 /// * `dump_ir` - optionally emit the Cranelift IR to stdout
-pub fn jit_run(program: &Program, args: Vec<String>, dump_ir: bool) {
-    // Compile.
-    let mut trans = Translator::<JITModule>::new();
-    trans.compile_prog(program, dump_ir);
-
-    // Add a JIT wrapper for `main`.
+/// * `profile` - count dynamic instructions and print the total to stderr
+/// * `checked` - report the runtime errors that brilirs reports instead of crashing
+pub fn jit_run(
+    program: &Program,
+    args: Vec<String>,
+    dump_ir: bool,
+    profile: bool,
+    checked: bool,
+) -> Result<(), JitError> {
+    check_supported(program).map_err(JitError::Unsupported)?;
     let main = find_func(&program.functions, "main");
-    let entry_id = trans.add_mem_wrapper("main", &main.args, dump_ir);
 
     // Parse CLI arguments.
     if main.args.len() != args.len() {
-        panic!(
+        return Err(JitError::Args(format!(
             "@main expects {} arguments; got {}",
             main.args.len(),
             args.len()
-        );
+        )));
     }
-    let main_args: Vec<bril::Literal> = main
+    let main_args = main
         .args
         .iter()
         .zip(args)
-        .map(|(arg, val_str)| match arg.arg_type {
-            bril::Type::Int => bril::Literal::Int(val_str.parse().unwrap()),
-            bril::Type::Bool => bril::Literal::Bool(val_str == "true"),
-            bril::Type::Float => bril::Literal::Float(val_str.parse().unwrap()),
-            bril::Type::Char => bril::Literal::Char(val_str.parse().unwrap()),
-            bril::Type::Pointer(_) => unimplemented!("pointers not supported as main args"),
+        .map(|(arg, val_str)| {
+            let lit = match arg.arg_type {
+                bril::Type::Int => val_str.parse().ok().map(bril::Literal::Int),
+                bril::Type::Bool => match val_str.as_str() {
+                    "true" => Some(bril::Literal::Bool(true)),
+                    "false" => Some(bril::Literal::Bool(false)),
+                    _ => None,
+                },
+                bril::Type::Float => val_str.parse().ok().map(bril::Literal::Float),
+                bril::Type::Char => val_str.parse().ok().map(bril::Literal::Char),
+                bril::Type::Pointer(_) | bril::Type::Any => {
+                    unreachable!("rejected by `check_supported`")
+                }
+            };
+            lit.ok_or_else(|| {
+                JitError::Args(format!(
+                    "invalid {} argument `{val_str}` for `{}`",
+                    arg.arg_type, arg.name
+                ))
+            })
         })
-        .collect();
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Compile.
+    let mut trans = Translator::<JITModule>::new();
+    if profile {
+        trans.enable_profiling();
+    }
+    if checked {
+        trans.enable_checks();
+    }
+    trans.compile_prog(program, dump_ir);
+
+    // Add a JIT wrapper for `main`.
+    let entry_id = trans.add_mem_wrapper("main", &main.args, dump_ir);
 
     // Invoke the main function.
-    unsafe { trans.run(entry_id, &main_args) };
+    unsafe { trans.run(entry_id, &main_args) }.map_err(JitError::Runtime)?;
+    if checked {
+        trans.check_leaks(main).map_err(JitError::Runtime)?;
+    }
+    if let Some(count) = trans.dyn_inst_count() {
+        eprintln!("total_dyn_inst: {count}");
+    }
+    Ok(())
 }
 
 /// The C runtime library for Rust library users.
 pub fn c_runtime() -> &'static str {
     include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/rt.c"))
 }
+
+/// The Rust runtime library, built as a static library for the host by `build.rs`.
+pub fn rust_runtime() -> &'static [u8] {
+    include_bytes!(concat!(env!("OUT_DIR"), "/libbrilift_rt.a"))
+}
+
+/// The system libraries that the Rust runtime library needs, as linker arguments.
+const NATIVE_LIBS: &str = include_str!(concat!(env!("OUT_DIR"), "/native-static-libs.txt"));
+
+/// Link an object file from `compile` with the Rust runtime library to produce an executable.
+///
+/// This uses the system C compiler (or `$CC`) as the linker. The runtime is built for the host,
+/// so cross-compiled objects need to be linked with `rt.c` instead.
+///
+/// * `object` - the object file to link
+/// * `output` - the filename where we should write the executable
+/// * `link_args` - extra arguments for the linker
+pub fn link(object: &Path, output: &str, link_args: &[String]) -> Result<(), String> {
+    let mut runtime = tempfile::Builder::new()
+        .prefix("brilift-rt-")
+        .suffix(".a")
+        .tempfile()
+        .map_err(|e| format!("could not create a temporary file: {e}"))?;
+    runtime
+        .write_all(rust_runtime())
+        .map_err(|e| format!("could not write {}: {e}", runtime.path().display()))?;
+
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&cc)
+        .arg(object)
+        .arg(runtime.path())
+        .args(NATIVE_LIBS.split_whitespace())
+        .args(link_args)
+        .arg("-o")
+        .arg(output)
+        .status();
+
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(_) => Err(format!("{cc} could not link {output}")),
+        Err(e) => Err(format!("could not run {cc}: {e}")),
+    }
+}
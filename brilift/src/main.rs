@@ -1,28 +1,74 @@
 use argh::FromArgs;
 use bril_rs as bril;
-use brilift::{compile, jit_run};
+use brilift::{JitError, compile, jit_run, link};
+use std::fs::File;
+use std::io::Read;
 use std::str::FromStr;
 
 #[derive(FromArgs)]
 #[argh(description = "Bril compiler")]
 struct BriliftArgs {
-    #[argh(switch, short = 'j', description = "JIT and run (doesn't work)")]
+    #[argh(switch, short = 'j', description = "JIT and run")]
     jit: bool,
 
     #[argh(option, short = 't', description = "target triple")]
     target: Option<String>,
 
+    #[argh(
+        option,
+        short = 'f',
+        description = "read the program from a file instead of stdin"
+    )]
+    file: Option<String>,
+
+    #[argh(switch, description = "the program is Bril text instead of JSON")]
+    text: bool,
+
+    #[argh(
+        switch,
+        short = 'e',
+        description = "link an executable with the Rust runtime instead of emitting an object file"
+    )]
+    exe: bool,
+
     #[argh(
         option,
         short = 'o',
-        description = "output object file",
-        default = "String::from(\"bril.o\")"
+        description = "output file (default: bril.o, or bril with -e)"
+    )]
+    output: Option<String>,
+
+    #[argh(
+        option,
+        short = 'l',
+        description = "extra argument for the linker with -e (repeatable)"
     )]
-    output: String,
+    link_arg: Vec<String>,
 
     #[argh(switch, short = 'd', description = "dump CLIF IR")]
     dump_ir: bool,
 
+    #[argh(
+        switch,
+        short = 'p',
+        description = "count dynamic instructions and print total_dyn_inst to stderr"
+    )]
+    profile: bool,
+
+    #[argh(
+        switch,
+        short = 'c',
+        description = "check for division by zero, bad chars, bad memory accesses, and leaks"
+    )]
+    checked: bool,
+
+    #[argh(
+        switch,
+        short = 'g',
+        description = "emit DWARF line info from the program's source positions (AOT only)"
+    )]
+    debug_info: bool,
+
     #[argh(switch, short = 'v', description = "verbose logging")]
     verbose: bool,
 
@@ -69,6 +115,26 @@ impl FromStr for OptLevel {
     }
 }
 
+/// Print an error and exit without running the program.
+fn fail(msg: impl std::fmt::Display) -> ! {
+    eprintln!("error: {msg}");
+    std::process::exit(1)
+}
+
+/// Load the Bril program from a file or stdin, in either JSON or text form.
+fn load_program(file: Option<&String>, text: bool) -> bril::Program {
+    let input: Box<dyn Read> = match file {
+        Some(path) => Box::new(File::open(path).unwrap_or_else(|e| fail(format!("{path}: {e}")))),
+        None => Box::new(std::io::stdin()),
+    };
+    if text {
+        let prog = bril2json::parse_abstract_program_from_read(input, true, false, file.cloned());
+        bril::Program::try_from_all(prog).unwrap_or_else(|e| fail(e))
+    } else {
+        bril::load_program_from_read(input)
+    }
+}
+
 fn main() {
     let args: BriliftArgs = argh::from_env();
 
@@ -85,18 +151,75 @@ fn main() {
     )
     .unwrap();
 
-    // Load the Bril program from stdin.
-    let prog = bril::load_program();
+    let prog = load_program(args.file.as_ref(), args.text);
+
+    // Debuggers look for the source file by its absolute path.
+    let debug_source = args.debug_info.then(|| match &args.file {
+        Some(path) => std::fs::canonicalize(path)
+            .unwrap_or_else(|e| fail(format!("{path}: {e}")))
+            .display()
+            .to_string(),
+        None => String::from("<stdin>"),
+    });
 
     if args.jit {
-        jit_run(&prog, args.args, args.dump_ir);
+        match jit_run(&prog, args.args, args.dump_ir, args.profile, args.checked) {
+            Ok(()) => {}
+            Err(JitError::Unsupported(msg)) => fail(msg),
+            // Runtime errors exit with 2, like brilirs.
+            Err(e) => {
+                eprintln!("error: {e}");
+                std::process::exit(2);
+            }
+        }
+    } else if args.exe {
+        // The Rust runtime is only built for the host, so other targets have to link with `rt.c`.
+        if let Some(target) = &args.target {
+            let host = cranelift_native::builder().map(|b| b.triple().clone());
+            let triple = cranelift_codegen::isa::lookup_by_name(target).map(|b| b.triple().clone());
+            if let (Ok(host), Ok(triple)) = (host, triple)
+                && host != triple
+            {
+                fail(format!(
+                    "-e can only link executables for the host, not {target} (emit an object \
+                     file and link it with rt.c instead)"
+                ));
+            }
+        }
+
+        // Compile to a temporary object file and link it.
+        let output = args.output.unwrap_or_else(|| String::from("bril"));
+        let object = tempfile::Builder::new()
+            .prefix("brilift-")
+            .suffix(".o")
+            .tempfile()
+            .unwrap_or_else(|e| fail(format!("could not create a temporary file: {e}")));
+        compile(
+            &prog,
+            args.target,
+            object.path().to_str().unwrap(),
+            args.opt_level.to_str(),
+            args.dump_ir,
+            args.profile,
+            args.checked,
+            debug_source.as_deref(),
+        )
+        .unwrap_or_else(|e| fail(e));
+        let res = link(object.path(), &output, &args.link_arg);
+        // Delete the object file before `fail` exits.
+        drop(object);
+        res.unwrap_or_else(|e| fail(e));
     } else {
         compile(
             &prog,
-            args.target.clone(),
-            &args.output,
+            args.target,
+            &args.output.unwrap_or_else(|| String::from("bril.o")),
             args.opt_level.to_str(),
             args.dump_ir,
-        );
+            args.profile,
+            args.checked,
+            debug_source.as_deref(),
+        )
+        .unwrap_or_else(|e| fail(e));
     }
 }
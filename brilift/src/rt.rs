@@ -1,61 +1,160 @@
 use std::alloc;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Write;
 use std::mem::size_of;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set when a runtime function fails. JIT-compiled code checks this after every call that can
+/// fail and returns immediately, so the error can be reported to the caller instead of aborting.
+pub static FAILED: AtomicBool = AtomicBool::new(false);
+
+/// The message for the failure that set `FAILED`, and the row and column where it happened.
+static ERROR: Mutex<Option<(String, i64, i64)>> = Mutex::new(None);
+
+fn fail(msg: String) {
+    fail_at(msg, 0, 0);
+}
+
+/// Prefix an error message with a Bril source position, like brilirs. A row of 0 means there is
+/// no position.
+pub fn at_pos(msg: &str, row: i64, col: i64) -> String {
+    if row == 0 {
+        msg.to_string()
+    } else {
+        format!("Line {row}, Column {col}: {msg}")
+    }
+}
+
+fn fail_at(msg: String, row: i64, col: i64) {
+    *ERROR.lock().unwrap() = Some((msg, row, col));
+    FAILED.store(true, Ordering::SeqCst);
+}
+
+/// Get (and clear) the error from the runtime, if there was one, along with its row and column.
+pub fn take_error_at() -> Option<(String, i64, i64)> {
+    FAILED.store(false, Ordering::SeqCst);
+    ERROR.lock().unwrap().take()
+}
+
+/// Get (and clear) the error from the runtime, if there was one.
+pub fn take_error() -> Option<String> {
+    take_error_at().map(|(msg, row, col)| at_pos(&msg, row, col))
+}
+
+/// Output from compiled code, when it is being collected instead of printed.
+static CAPTURED: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+/// Collect everything that compiled code prints from now on, for `take_output`.
+#[allow(dead_code, reason = "only used by the JIT")]
+pub fn capture_output() {
+    CAPTURED.lock().unwrap().get_or_insert_with(Vec::new);
+}
+
+/// Get (and clear) the output collected since `capture_output`.
+#[allow(dead_code, reason = "only used by the JIT")]
+pub fn take_output() -> Vec<u8> {
+    CAPTURED
+        .lock()
+        .unwrap()
+        .as_mut()
+        .map(std::mem::take)
+        .unwrap_or_default()
+}
+
+fn emit(args: fmt::Arguments) {
+    match CAPTURED.lock().unwrap().as_mut() {
+        Some(buf) => buf.write_fmt(args).unwrap(),
+        None => print!("{args}"),
+    }
+}
 
 #[unsafe(no_mangle)]
 pub extern "C" fn print_int(i: i64) {
-    print!("{i}");
+    emit(format_args!("{i}"));
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn print_bool(b: bool) {
-    print!("{b}");
+    emit(format_args!("{b}"));
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn print_float(f: f64) {
     if f.is_infinite() {
         if f < 0.0 {
-            print!("-Infinity");
+            emit(format_args!("-Infinity"));
         } else {
-            print!("Infinity");
+            emit(format_args!("Infinity"));
         }
     } else if f != 0.0 && f.abs().log10() >= 10.0 {
-        print!("{}", format!("{f:.17e}").replace('e', "e+").as_str());
+        emit(format_args!(
+            "{}",
+            format!("{f:.17e}").replace('e', "e+").as_str()
+        ));
     } else if f != 0.0 && f.abs().log10() <= -10.0 {
-        print!("{f:.17e}");
+        emit(format_args!("{f:.17e}"));
     } else {
-        print!("{f:.17}");
+        emit(format_args!("{f:.17}"));
     }
 }
 
+#[unsafe(no_mangle)]
+pub extern "C" fn print_char(c: u32) {
+    // Without checked mode, `int2char` can produce invalid chars, which are printed as the
+    // replacement character instead of aborting.
+    emit(format_args!(
+        "{}",
+        char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER)
+    ));
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn print_sep() {
-    print!(" ");
+    emit(format_args!(" "));
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn print_end() {
-    println!();
+    emit(format_args!("\n"));
 }
 
 const ALIGN: usize = 8;
 const EXTRA_SIZE: usize = size_of::<usize>();
 
-#[unsafe(no_mangle)]
-pub extern "C" fn mem_alloc(count: i64, bytes: i64) -> *mut u8 {
+/// Allocate `count` elements of `bytes` bytes each, returning null if the allocation is invalid.
+fn try_alloc(count: i64, bytes: i64) -> Option<*mut u8> {
     // The logical size of the allocation.
-    let payload_size: usize = (count * bytes).try_into().unwrap();
+    let payload_size = count
+        .checked_mul(bytes)
+        .and_then(|size| usize::try_from(size).ok());
 
     // Allocate one extra word to store the size.
-    let layout = alloc::Layout::from_size_align(payload_size + EXTRA_SIZE, ALIGN).unwrap();
+    let layout = payload_size
+        .and_then(|size| alloc::Layout::from_size_align(size.checked_add(EXTRA_SIZE)?, ALIGN).ok());
+    let (Some(payload_size), Some(layout)) = (payload_size, layout) else {
+        return None;
+    };
 
     unsafe {
         let ptr = alloc::alloc(layout);
+        if ptr.is_null() {
+            return None;
+        }
         *(ptr as *mut usize) = payload_size;
-        ptr.add(EXTRA_SIZE) // Pointer to the payload.
+        Some(ptr.add(EXTRA_SIZE)) // Pointer to the payload.
     }
 }
 
+#[unsafe(no_mangle)]
+pub extern "C" fn mem_alloc(count: i64, bytes: i64) -> *mut u8 {
+    try_alloc(count, bytes).unwrap_or_else(|| {
+        fail(format!("cannot allocate `{count}` entries"));
+        std::ptr::null_mut()
+    })
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn mem_free(ptr: *mut u8) {
     // `ptr` points at the payload, which is immediately preceded by the size (which does not
@@ -68,3 +167,182 @@ pub extern "C" fn mem_free(ptr: *mut u8) {
         alloc::dealloc(base_ptr, layout);
     }
 }
+
+/// A live allocation in checked mode.
+struct Allocation {
+    /// A sequence number, used like brilirs's heap location index in error messages.
+    index: usize,
+    /// The size of the allocation in bytes.
+    size: usize,
+    /// The size of each element in bytes.
+    elem_size: usize,
+}
+
+/// The live allocations in checked mode, keyed by their addresses.
+static LIVE: Mutex<BTreeMap<usize, Allocation>> = Mutex::new(BTreeMap::new());
+
+/// The number of allocations made in checked mode.
+static ALLOC_COUNT: Mutex<usize> = Mutex::new(0);
+
+/// Describe a pointer like brilirs does: the index of the nearest allocation at or before it,
+/// and its offset in elements.
+fn describe(live: &BTreeMap<usize, Allocation>, ptr: usize) -> (String, i64) {
+    match live.range(..=ptr).next_back() {
+        Some((&base, a)) => (a.index.to_string(), ((ptr - base) / a.elem_size) as i64),
+        None => (format!("{ptr:#x}"), 0),
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn checked_alloc(count: i64, bytes: i64, row: i64, col: i64) -> *mut u8 {
+    // Like brilirs, a zero-sized allocation is not an error but can never be accessed.
+    let Some(ptr) = (count >= 0).then(|| try_alloc(count, bytes)).flatten() else {
+        fail_at(format!("cannot allocate `{count}` entries"), row, col);
+        return std::ptr::null_mut();
+    };
+    // Zero the memory so that uninitialized pointers are null, which lets an interpreter that
+    // copies this memory back (like brilirs's tiered mode) tell them apart from real pointers.
+    unsafe { std::ptr::write_bytes(ptr, 0, (count * bytes) as usize) };
+
+    let mut index = ALLOC_COUNT.lock().unwrap();
+    LIVE.lock().unwrap().insert(
+        ptr as usize,
+        Allocation {
+            index: *index,
+            size: (count * bytes) as usize,
+            elem_size: bytes.max(1) as usize,
+        },
+    );
+    *index += 1;
+    ptr
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn checked_free(ptr: *mut u8, row: i64, col: i64) {
+    let mut live = LIVE.lock().unwrap();
+    if live.remove(&(ptr as usize)).is_some() {
+        drop(live);
+        mem_free(ptr);
+    } else {
+        let (index, offset) = describe(&live, ptr as usize);
+        fail_at(
+            format!(
+                "Tried to free illegal memory location index: `{index}`, offset: `{offset}`. Offset must be 0."
+            ),
+            row,
+            col,
+        );
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn check_access(ptr: *mut u8, bytes: i64, row: i64, col: i64) {
+    let ptr = ptr as usize;
+    let live = LIVE.lock().unwrap();
+    let in_bounds = live
+        .range(..=ptr)
+        .next_back()
+        .is_some_and(|(&base, a)| ptr + bytes as usize <= base + a.size);
+    if !in_bounds {
+        let (index, offset) = describe(&live, ptr);
+        fail_at(
+            format!("Uninitialized heap location `{index}` and/or illegal offset `{offset}`"),
+            row,
+            col,
+        );
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn div_by_zero(row: i64, col: i64) {
+    fail_at("Attempt to divide by 0".to_string(), row, col);
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn bad_char(value: i64, row: i64, col: i64) {
+    fail_at(
+        format!("value {value} cannot be converted to char"),
+        row,
+        col,
+    );
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn bad_commit(row: i64, col: i64) {
+    fail_at("commit in non-speculative state".to_string(), row, col);
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn bad_abort(row: i64, col: i64) {
+    fail_at("abort in non-speculative state".to_string(), row, col);
+}
+
+/// The live allocations in checked mode, in address order: their addresses, sequence numbers,
+/// sizes in bytes, and element sizes in bytes.
+#[allow(dead_code, reason = "only used by the JIT")]
+pub fn live_allocations() -> Vec<(*mut u8, usize, usize, usize)> {
+    LIVE.lock()
+        .unwrap()
+        .iter()
+        .map(|(&ptr, a)| (ptr as *mut u8, a.index, a.size, a.elem_size))
+        .collect()
+}
+
+/// The sequence number of the live allocation that starts at `ptr` in checked mode.
+#[allow(dead_code, reason = "only used by the JIT")]
+pub fn allocation_index(ptr: *mut u8) -> Option<usize> {
+    LIVE.lock().unwrap().get(&(ptr as usize)).map(|a| a.index)
+}
+
+/// Check for (and forget about) allocations that were never freed in checked mode.
+pub fn check_leaks(row: i64, col: i64) -> Option<String> {
+    let mut live = LIVE.lock().unwrap();
+    if live.is_empty() {
+        return None;
+    }
+    live.clear();
+    Some(at_pos(
+        "Some memory locations have not been freed by the end of execution",
+        row,
+        col,
+    ))
+}
+
+/// Call the macro `$m` with every runtime function that compiled Bril instructions use, written as
+/// `symbol => function(arguments) -> result;`. The JIT links each symbol straight to its function
+/// and the static runtime in `runtime/` exports wrappers that exit on errors, so both are generated
+/// from this list. `rt.c` has to define the same symbols by hand.
+macro_rules! for_each_rt_func {
+    ($m:ident) => {
+        $m! {
+            _bril_print_int => print_int(i: i64);
+            _bril_print_bool => print_bool(b: bool);
+            _bril_print_float => print_float(f: f64);
+            _bril_print_char => print_char(c: u32);
+            _bril_print_sep => print_sep();
+            _bril_print_end => print_end();
+            _bril_alloc => mem_alloc(count: i64, bytes: i64) -> *mut u8;
+            _bril_free => mem_free(ptr: *mut u8);
+            _bril_checked_alloc => checked_alloc(count: i64, bytes: i64, row: i64, col: i64) -> *mut u8;
+            _bril_checked_free => checked_free(ptr: *mut u8, row: i64, col: i64);
+            _bril_check_access => check_access(ptr: *mut u8, bytes: i64, row: i64, col: i64);
+            _bril_div_by_zero => div_by_zero(row: i64, col: i64);
+            _bril_bad_char => bad_char(value: i64, row: i64, col: i64);
+            _bril_bad_commit => bad_commit(row: i64, col: i64);
+            _bril_bad_abort => bad_abort(row: i64, col: i64);
+        }
+    };
+}
+#[allow(unused_imports, reason = "only used by the static runtime")]
+pub(crate) use for_each_rt_func;
+
+macro_rules! symbols {
+    ($($symbol:ident => $func:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)?;)*) => {
+        /// The symbol and address of every function in `for_each_rt_func`.
+        #[allow(dead_code, reason = "only used by the JIT")]
+        pub fn symbols() -> Vec<(&'static str, *const u8)> {
+            vec![$((stringify!($symbol), $func as *const u8)),*]
+        }
+    };
+}
+for_each_rt_func!(symbols);
@@ -1,3 +1,4 @@
+use crate::dwarf;
 use crate::rt;
 use bril_rs as bril;
 use core::mem;
@@ -11,7 +12,9 @@ use cranelift_module::{Module, default_libcall_names};
 use cranelift_object::{ObjectBuilder, ObjectModule};
 use enum_map::{Enum, EnumMap, enum_map};
 use std::collections::HashMap;
+use std::fmt;
 use std::fs;
+use std::io::{self, Write};
 use std::sync::Arc;
 
 /// Runtime functions used by ordinary Bril instructions.
@@ -21,10 +24,18 @@ enum RTFunc {
     PrintInt,
     PrintBool,
     PrintFloat,
+    PrintChar,
     PrintSep,
     PrintEnd,
     Alloc,
     Free,
+    CheckedAlloc,
+    CheckedFree,
+    CheckAccess,
+    DivByZero,
+    BadChar,
+    BadCommit,
+    BadAbort,
 }
 
 /// A trap code we'll use for unreachable code. This doesn't actually give
@@ -55,6 +66,11 @@ impl RTFunc {
                 returns: vec![],
                 call_conv,
             },
+            Self::PrintChar => ir::Signature {
+                params: vec![ir::AbiParam::new(ir::types::I32)],
+                returns: vec![],
+                call_conv,
+            },
             Self::PrintSep => ir::Signature {
                 params: vec![],
                 returns: vec![],
@@ -78,6 +94,61 @@ impl RTFunc {
                 returns: vec![],
                 call_conv,
             },
+            // The checked versions also take the source position (row and column) for errors.
+            Self::CheckedAlloc => ir::Signature {
+                params: vec![
+                    ir::AbiParam::new(ir::types::I64),
+                    ir::AbiParam::new(ir::types::I64),
+                    ir::AbiParam::new(ir::types::I64),
+                    ir::AbiParam::new(ir::types::I64),
+                ],
+                returns: vec![ir::AbiParam::new(pointer_type)],
+                call_conv,
+            },
+            Self::CheckedFree => ir::Signature {
+                params: vec![
+                    ir::AbiParam::new(pointer_type),
+                    ir::AbiParam::new(ir::types::I64),
+                    ir::AbiParam::new(ir::types::I64),
+                ],
+                returns: vec![],
+                call_conv,
+            },
+            Self::CheckAccess => ir::Signature {
+                params: vec![
+                    ir::AbiParam::new(pointer_type),
+                    ir::AbiParam::new(ir::types::I64),
+                    ir::AbiParam::new(ir::types::I64),
+                    ir::AbiParam::new(ir::types::I64),
+                ],
+                returns: vec![],
+                call_conv,
+            },
+            Self::DivByZero => ir::Signature {
+                params: vec![
+                    ir::AbiParam::new(ir::types::I64),
+                    ir::AbiParam::new(ir::types::I64),
+                ],
+                returns: vec![],
+                call_conv,
+            },
+            Self::BadChar => ir::Signature {
+                params: vec![
+                    ir::AbiParam::new(ir::types::I64),
+                    ir::AbiParam::new(ir::types::I64),
+                    ir::AbiParam::new(ir::types::I64),
+                ],
+                returns: vec![],
+                call_conv,
+            },
+            Self::BadCommit | Self::BadAbort => ir::Signature {
+                params: vec![
+                    ir::AbiParam::new(ir::types::I64),
+                    ir::AbiParam::new(ir::types::I64),
+                ],
+                returns: vec![],
+                call_conv,
+            },
         }
     }
 
@@ -86,22 +157,18 @@ impl RTFunc {
             Self::PrintInt => "_bril_print_int",
             Self::PrintBool => "_bril_print_bool",
             Self::PrintFloat => "_bril_print_float",
+            Self::PrintChar => "_bril_print_char",
             Self::PrintSep => "_bril_print_sep",
             Self::PrintEnd => "_bril_print_end",
             Self::Alloc => "_bril_alloc",
             Self::Free => "_bril_free",
-        }
-    }
-
-    fn rt_impl(&self) -> *const u8 {
-        match self {
-            RTFunc::PrintInt => rt::print_int as *const u8,
-            RTFunc::PrintBool => rt::print_bool as *const u8,
-            RTFunc::PrintFloat => rt::print_float as *const u8,
-            RTFunc::PrintSep => rt::print_sep as *const u8,
-            RTFunc::PrintEnd => rt::print_end as *const u8,
-            RTFunc::Alloc => rt::mem_alloc as *const u8,
-            RTFunc::Free => rt::mem_free as *const u8,
+            Self::CheckedAlloc => "_bril_checked_alloc",
+            Self::CheckedFree => "_bril_checked_free",
+            Self::CheckAccess => "_bril_check_access",
+            Self::DivByZero => "_bril_div_by_zero",
+            Self::BadChar => "_bril_bad_char",
+            Self::BadCommit => "_bril_bad_commit",
+            Self::BadAbort => "_bril_bad_abort",
         }
     }
 }
@@ -114,6 +181,9 @@ enum RTSetupFunc {
     ParseInt,
     ParseBool,
     ParseFloat,
+    ParseChar,
+    PrintDynInst,
+    CheckLeaks,
 }
 
 impl RTSetupFunc {
@@ -147,6 +217,27 @@ impl RTSetupFunc {
                 returns: vec![ir::AbiParam::new(ir::types::F64)],
                 call_conv,
             },
+            Self::ParseChar => ir::Signature {
+                params: vec![
+                    ir::AbiParam::new(pointer_type),
+                    ir::AbiParam::new(ir::types::I64),
+                ],
+                returns: vec![ir::AbiParam::new(ir::types::I32)],
+                call_conv,
+            },
+            Self::PrintDynInst => ir::Signature {
+                params: vec![ir::AbiParam::new(ir::types::I64)],
+                returns: vec![],
+                call_conv,
+            },
+            Self::CheckLeaks => ir::Signature {
+                params: vec![
+                    ir::AbiParam::new(ir::types::I64),
+                    ir::AbiParam::new(ir::types::I64),
+                ],
+                returns: vec![],
+                call_conv,
+            },
         }
     }
 
@@ -155,6 +246,9 @@ impl RTSetupFunc {
             Self::ParseInt => "_bril_parse_int",
             Self::ParseBool => "_bril_parse_bool",
             Self::ParseFloat => "_bril_parse_float",
+            Self::ParseChar => "_bril_parse_char",
+            Self::PrintDynInst => "_bril_print_dyn_inst",
+            Self::CheckLeaks => "_bril_check_leaks",
         }
     }
 }
@@ -167,6 +261,7 @@ fn translate_type(typ: &bril::Type, pointer_type: ir::Type) -> ir::Type {
         bril::Type::Float => ir::types::F64,
         bril::Type::Char => ir::types::I32,
         bril::Type::Pointer(_) => pointer_type,
+        bril::Type::Any => unreachable!("the dynamic extension is rejected by `check_supported`"),
     }
 }
 
@@ -216,6 +311,182 @@ fn all_vars(func: &bril::Function) -> HashMap<&String, &bril::Type> {
         .collect()
 }
 
+/// Get the shadow variables of the SSA extension, which are the destinations of `get`
+/// instructions (and their types).
+fn shadow_vars(func: &bril::Function) -> HashMap<&String, &bril::Type> {
+    func.instrs
+        .iter()
+        .filter_map(|inst| match inst {
+            bril::Code::Instruction(bril::Instruction::Value {
+                dest,
+                op: bril::ValueOps::Get,
+                op_type: typ,
+                ..
+            }) => Some((dest, typ)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Count the instructions in the basic block at the start of `insts`, which ends at a label or
+/// just after a terminator. A `guard` also ends the count, because it may jump away. This matches
+/// how brilirs counts dynamic instructions.
+fn block_len(insts: &[bril::Code]) -> i64 {
+    let mut len = 0;
+    for code in insts {
+        match code {
+            bril::Code::Label { .. } => break,
+            bril::Code::Instruction(inst) => {
+                len += 1;
+                if CompileEnv::is_term(inst) || CompileEnv::is_guard(inst) {
+                    break;
+                }
+            }
+        }
+    }
+    len
+}
+
+/// Find the speculation depth before each instruction in a function body, i.e., the number of
+/// `speculate`s that have not yet been committed or aborted. Unreachable code has depth 0.
+///
+/// We compile speculation statically, so the depth must be the same along every path. If it is
+/// not, this returns the index of an instruction where the paths disagree. A `commit` or failed
+/// `guard` outside of speculation is a runtime error, so it leaves the depth at 0.
+fn spec_depths(insts: &[bril::Code]) -> Result<Vec<usize>, usize> {
+    let labels: HashMap<&String, usize> = insts
+        .iter()
+        .enumerate()
+        .filter_map(|(i, code)| match code {
+            bril::Code::Label { label, .. } => Some((label, i)),
+            bril::Code::Instruction(_) => None,
+        })
+        .collect();
+
+    let mut depths = vec![None; insts.len()];
+    let mut worklist: Vec<(usize, usize)> = vec![(0, 0)];
+    while let Some((i, depth)) = worklist.pop() {
+        if i >= insts.len() {
+            continue;
+        }
+        if let Some(old) = depths[i] {
+            if old != depth {
+                return Err(i);
+            }
+            continue;
+        }
+        depths[i] = Some(depth);
+
+        match &insts[i] {
+            bril::Code::Instruction(bril::Instruction::Effect { op, labels: l, .. }) => match op {
+                bril::EffectOps::Jump | bril::EffectOps::Branch => {
+                    worklist.extend(l.iter().map(|label| (labels[label], depth)));
+                }
+                bril::EffectOps::Return => {}
+                bril::EffectOps::Speculate => worklist.push((i + 1, depth + 1)),
+                bril::EffectOps::Commit => worklist.push((i + 1, depth.saturating_sub(1))),
+                bril::EffectOps::Guard => {
+                    worklist.push((i + 1, depth));
+                    worklist.push((labels[&l[0]], depth.saturating_sub(1)));
+                }
+                _ => worklist.push((i + 1, depth)),
+            },
+            _ => worklist.push((i + 1, depth)),
+        }
+    }
+    Ok(depths.into_iter().map(|d| d.unwrap_or(0)).collect())
+}
+
+/// Check that brilift can compile a program, returning a message saying why if it cannot.
+pub fn check_supported(prog: &bril::Program) -> Result<(), String> {
+    for func in &prog.functions {
+        let types = func
+            .args
+            .iter()
+            .map(|arg| &arg.arg_type)
+            .chain(&func.return_type)
+            .chain(func.instrs.iter().filter_map(|code| match code {
+                bril::Code::Instruction(
+                    bril::Instruction::Constant {
+                        const_type: typ, ..
+                    }
+                    | bril::Instruction::Value { op_type: typ, .. },
+                ) => Some(typ),
+                _ => None,
+            }));
+        for typ in types {
+            if is_dynamic(typ) {
+                return Err(format!(
+                    "unsupported: @{} uses the dynamic extension's type `{typ}`",
+                    func.name
+                ));
+            }
+        }
+
+        if func.name == "main"
+            && let Some(arg) = func
+                .args
+                .iter()
+                .find(|arg| matches!(arg.arg_type, bril::Type::Pointer(_)))
+        {
+            return Err(format!(
+                "unsupported: @main can not take the pointer argument `{}`",
+                arg.name
+            ));
+        }
+
+        // Printing uses the type that `all_vars` finds for each variable.
+        let var_types = all_vars(func);
+        for code in &func.instrs {
+            if let bril::Code::Instruction(bril::Instruction::Effect {
+                op: bril::EffectOps::Print,
+                args,
+                ..
+            }) = code
+                && let Some(arg) = args
+                    .iter()
+                    .find(|arg| matches!(var_types.get(arg), Some(bril::Type::Pointer(_))))
+            {
+                return Err(format!(
+                    "unsupported: can not print the pointer `{arg}` in @{}",
+                    func.name
+                ));
+            }
+        }
+
+        if let Err(i) = spec_depths(&func.instrs) {
+            let at = match &func.instrs[i] {
+                bril::Code::Label { label, .. } => format!("`.{label}`"),
+                bril::Code::Instruction(inst) => match pos_row_col(inst.get_pos().as_ref()) {
+                    (0, _) => format!("instruction {i}"),
+                    (row, col) => format!("line {row}, column {col}"),
+                },
+            };
+            return Err(format!(
+                "unsupported: the number of uncommitted `speculate`s at {at} in @{} depends on \
+                 the path taken",
+                func.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check whether a type is (or points to) the dynamic extension's `any`.
+fn is_dynamic(typ: &bril::Type) -> bool {
+    match typ {
+        bril::Type::Any => true,
+        bril::Type::Pointer(elem) => is_dynamic(elem),
+        _ => false,
+    }
+}
+
+/// The row and column of a source position for the runtime's error messages, or zeros if there
+/// is no position.
+fn pos_row_col(pos: Option<&bril::Position>) -> (i64, i64) {
+    pos.map_or((0, 0), |p| (p.pos.row as i64, p.pos.col as i64))
+}
+
 /// Emit Cranelift code to load a Bril value from memory.
 fn emit_load(
     pointer_type: ir::Type,
@@ -237,11 +508,26 @@ fn emit_store(builder: &mut FunctionBuilder, ptr: ir::Value, val: ir::Value) {
 /// An environment for translating Bril into CLIF.
 struct CompileEnv<'a> {
     vars: HashMap<&'a String, Variable>,
+    shadows: HashMap<&'a String, Variable>,
     var_types: HashMap<&'a String, &'a bril::Type>,
     rt_refs: EnumMap<RTFunc, ir::FuncRef>,
     blocks: HashMap<&'a String, ir::Block>,
     func_refs: HashMap<&'a String, ir::FuncRef>,
     pointer_type: ir::Type,
+    /// The address of the runtime's failure flag and a block that returns early, when failures
+    /// need to be propagated back to the caller (i.e., in JIT mode).
+    error_check: Option<(i64, ir::Block)>,
+    /// The dynamic instruction counter, when profiling.
+    dyn_inst: Option<ir::GlobalValue>,
+    /// Check for the runtime errors that brilirs reports.
+    checked: bool,
+    /// The speculation depth before each instruction (see `spec_depths`).
+    spec_depths: Vec<usize>,
+    /// A snapshot of every variable for each level of speculation, restored when a guard fails.
+    /// Cranelift's SSA construction makes these copies free.
+    snapshots: Vec<HashMap<&'a String, Variable>>,
+    /// Tag instructions with source locations for debug info.
+    debug_info: bool,
 }
 
 impl CompileEnv<'_> {
@@ -299,6 +585,159 @@ impl CompileEnv<'_> {
         builder.def_var(self.vars[dest], res);
     }
 
+    /// At the start of a basic block, add its length to the dynamic instruction counter.
+    fn gen_count(&self, builder: &mut FunctionBuilder, insts: &[bril::Code]) {
+        let len = block_len(insts);
+        if let Some(counter) = self.dyn_inst
+            && len > 0
+        {
+            let addr = builder.ins().global_value(self.pointer_type, counter);
+            let count = builder
+                .ins()
+                .load(ir::types::I64, ir::MemFlags::trusted(), addr, 0);
+            let count = builder.ins().iadd_imm(count, len);
+            builder.ins().store(ir::MemFlags::trusted(), count, addr, 0);
+        }
+    }
+
+    /// After a call that can fail, return immediately if the runtime reported a failure.
+    fn gen_error_check(&self, builder: &mut FunctionBuilder) {
+        if let Some((flag_addr, bail_block)) = self.error_check {
+            let addr = builder.ins().iconst(self.pointer_type, flag_addr);
+            let failed = builder
+                .ins()
+                .load(ir::types::I8, ir::MemFlags::trusted(), addr, 0);
+            let cont_block = builder.create_block();
+            builder.ins().brif(failed, bail_block, &[], cont_block, &[]);
+            builder.seal_block(cont_block);
+            builder.switch_to_block(cont_block);
+        }
+    }
+
+    /// Emit the source position of `inst` as arguments for a runtime function.
+    fn gen_pos(&self, builder: &mut FunctionBuilder, inst: &bril::Instruction) -> [ir::Value; 2] {
+        let (row, col) = pos_row_col(inst.get_pos().as_ref());
+        [
+            builder.ins().iconst(ir::types::I64, row),
+            builder.ins().iconst(ir::types::I64, col),
+        ]
+    }
+
+    /// In checked mode, make sure that `bytes` bytes at `ptr` are in a live allocation.
+    fn gen_access_check(
+        &self,
+        builder: &mut FunctionBuilder,
+        inst: &bril::Instruction,
+        ptr: ir::Value,
+        typ: &bril::Type,
+    ) {
+        if self.checked {
+            let bytes = translate_type(typ, self.pointer_type).bytes();
+            let bytes_val = builder.ins().iconst(ir::types::I64, bytes as i64);
+            let [row, col] = self.gen_pos(builder, inst);
+            builder.ins().call(
+                self.rt_refs[RTFunc::CheckAccess],
+                &[ptr, bytes_val, row, col],
+            );
+            self.gen_error_check(builder);
+        }
+    }
+
+    /// In checked mode, report an error when dividing by zero instead of trapping.
+    fn gen_div_check(
+        &self,
+        builder: &mut FunctionBuilder,
+        inst: &bril::Instruction,
+        divisor: ir::Value,
+    ) {
+        if self.checked {
+            self.gen_check(builder, divisor, |builder| {
+                let [row, col] = self.gen_pos(builder, inst);
+                builder
+                    .ins()
+                    .call(self.rt_refs[RTFunc::DivByZero], &[row, col]);
+            });
+        }
+    }
+
+    /// In checked mode, report an error when converting an int that is not a Unicode scalar value
+    /// (i.e., is negative, a surrogate, or too large) to a char.
+    fn gen_char_check(
+        &self,
+        builder: &mut FunctionBuilder,
+        inst: &bril::Instruction,
+        value: ir::Value,
+    ) {
+        if self.checked {
+            let below_surrogates = builder
+                .ins()
+                .icmp_imm(IntCC::UnsignedLessThan, value, 0xD800);
+            let above_surrogates = builder.ins().iadd_imm(value, -0xE000);
+            let above_surrogates = builder.ins().icmp_imm(
+                IntCC::UnsignedLessThan,
+                above_surrogates,
+                0x11_0000 - 0xE000,
+            );
+            let valid = builder.ins().bor(below_surrogates, above_surrogates);
+            self.gen_check(builder, valid, |builder| {
+                let [row, col] = self.gen_pos(builder, inst);
+                builder
+                    .ins()
+                    .call(self.rt_refs[RTFunc::BadChar], &[value, row, col]);
+            });
+        }
+    }
+
+    /// Continue if `ok` is nonzero. Otherwise, report an error with `fail`, which calls a runtime
+    /// function, and return early.
+    fn gen_check(
+        &self,
+        builder: &mut FunctionBuilder,
+        ok: ir::Value,
+        fail: impl FnOnce(&mut FunctionBuilder),
+    ) {
+        let fail_block = builder.create_block();
+        let cont_block = builder.create_block();
+        builder.ins().brif(ok, cont_block, &[], fail_block, &[]);
+
+        builder.seal_block(fail_block);
+        builder.switch_to_block(fail_block);
+        fail(builder);
+        match self.error_check {
+            Some((_, bail_block)) => {
+                builder.ins().jump(bail_block, &[]);
+            }
+            // The C runtime exits instead of returning.
+            None => {
+                builder.ins().trap(UNREACHABLE);
+            }
+        }
+
+        builder.seal_block(cont_block);
+        builder.switch_to_block(cont_block);
+    }
+
+    /// Fill in the block that returns early after a failure. The return value is never used, so
+    /// it is just zero.
+    fn gen_bail(&self, builder: &mut FunctionBuilder, return_type: Option<&bril::Type>) {
+        if let Some((_, bail_block)) = self.error_check {
+            builder.switch_to_block(bail_block);
+            match return_type {
+                None => builder.ins().return_(&[]),
+                Some(bril::Type::Float) => {
+                    let zero = builder.ins().f64const(0.0);
+                    builder.ins().return_(&[zero])
+                }
+                Some(typ) => {
+                    let zero = builder
+                        .ins()
+                        .iconst(translate_type(typ, self.pointer_type), 0);
+                    builder.ins().return_(&[zero])
+                }
+            };
+        }
+    }
+
     /// Implement a Bril `print` instruction in CLIF.
     fn gen_print(&self, args: &[String], builder: &mut FunctionBuilder) {
         let mut first = true;
@@ -316,8 +755,10 @@ impl CompileEnv<'_> {
                 bril::Type::Int => RTFunc::PrintInt,
                 bril::Type::Bool => RTFunc::PrintBool,
                 bril::Type::Float => RTFunc::PrintFloat,
-                bril::Type::Char => unimplemented!(),
-                bril::Type::Pointer(_) => unimplemented!(),
+                bril::Type::Char => RTFunc::PrintChar,
+                bril::Type::Pointer(_) | bril::Type::Any => {
+                    unreachable!("rejected by `check_supported`")
+                }
             };
             let print_ref = self.rt_refs[print_func];
             builder.ins().call(print_ref, &[arg_val]);
@@ -363,6 +804,9 @@ impl CompileEnv<'_> {
                 builder.ins().iconst(ir::types::I32, val as i64)
             }
             bril::Type::Pointer(_) => panic!("pointer literals not allowed"),
+            bril::Type::Any => {
+                unreachable!("the dynamic extension is rejected by `check_supported`")
+            }
         }
     }
 
@@ -391,6 +835,12 @@ impl CompileEnv<'_> {
             bril::ValueOps::Eq => IntCC::Equal,
             bril::ValueOps::Ge => IntCC::SignedGreaterThanOrEqual,
             bril::ValueOps::Gt => IntCC::SignedGreaterThan,
+            // Chars are compared by their (unsigned) code points.
+            bril::ValueOps::Clt => IntCC::UnsignedLessThan,
+            bril::ValueOps::Cle => IntCC::UnsignedLessThanOrEqual,
+            bril::ValueOps::Ceq => IntCC::Equal,
+            bril::ValueOps::Cge => IntCC::UnsignedGreaterThanOrEqual,
+            bril::ValueOps::Cgt => IntCC::UnsignedGreaterThan,
             _ => panic!("not a comparison opcode: {op}"),
         }
     }
@@ -408,7 +858,12 @@ impl CompileEnv<'_> {
     }
 
     /// Compile one Bril instruction into CLIF.
-    fn compile_inst(&self, inst: &bril::Instruction, builder: &mut FunctionBuilder) {
+    fn compile_inst(
+        &self,
+        inst: &bril::Instruction,
+        spec_depth: usize,
+        builder: &mut FunctionBuilder,
+    ) {
         match inst {
             bril::Instruction::Constant {
                 dest,
@@ -444,6 +899,7 @@ impl CompileEnv<'_> {
                         .map(|arg| builder.use_var(self.vars[arg]))
                         .collect();
                     builder.ins().call(func_ref, &arg_vals);
+                    self.gen_error_check(builder);
                 }
                 bril::EffectOps::Return => {
                     if !args.is_empty() {
@@ -456,18 +912,88 @@ impl CompileEnv<'_> {
                 bril::EffectOps::Nop => {}
                 bril::EffectOps::Store => {
                     let ptr_arg = builder.use_var(self.vars[&args[0]]);
+                    self.gen_access_check(builder, inst, ptr_arg, self.var_types[&args[1]]);
                     let val_arg = builder.use_var(self.vars[&args[1]]);
                     emit_store(builder, ptr_arg, val_arg);
                 }
                 bril::EffectOps::Free => {
                     let ptr_arg = builder.use_var(self.vars[&args[0]]);
-                    builder.ins().call(self.rt_refs[RTFunc::Free], &[ptr_arg]);
+                    if self.checked {
+                        let [row, col] = self.gen_pos(builder, inst);
+                        builder
+                            .ins()
+                            .call(self.rt_refs[RTFunc::CheckedFree], &[ptr_arg, row, col]);
+                        self.gen_error_check(builder);
+                    } else {
+                        builder.ins().call(self.rt_refs[RTFunc::Free], &[ptr_arg]);
+                    }
+                }
+
+                // SSA extension.
+                bril::EffectOps::Set => {
+                    // A shadow variable that is never read by a `get` does not need to be written.
+                    if let Some(shadow) = self.shadows.get(&args[0]) {
+                        let val = builder.use_var(self.vars[&args[1]]);
+                        builder.def_var(*shadow, val);
+                    }
+                }
+
+                // Speculation extension.
+                bril::EffectOps::Speculate => {
+                    for (name, var) in &self.vars {
+                        let val = builder.use_var(*var);
+                        builder.def_var(self.snapshots[spec_depth][name], val);
+                    }
+                }
+                bril::EffectOps::Commit => {
+                    if spec_depth == 0 && self.checked {
+                        let never = builder.ins().iconst(ir::types::I8, 0);
+                        self.gen_check(builder, never, |builder| {
+                            let [row, col] = self.gen_pos(builder, inst);
+                            builder
+                                .ins()
+                                .call(self.rt_refs[RTFunc::BadCommit], &[row, col]);
+                        });
+                    }
                 }
-                bril::EffectOps::Speculate
-                | bril::EffectOps::Commit
-                | bril::EffectOps::Guard
-                | bril::EffectOps::Set => {
-                    unimplemented!()
+                bril::EffectOps::Guard => {
+                    let cond = builder.use_var(self.vars[&args[0]]);
+                    match spec_depth.checked_sub(1) {
+                        // Roll back to the snapshot from the innermost `speculate`.
+                        Some(depth) => {
+                            let abort_block = builder.create_block();
+                            let cont_block = builder.create_block();
+                            builder.ins().brif(cond, cont_block, &[], abort_block, &[]);
+
+                            builder.seal_block(abort_block);
+                            builder.switch_to_block(abort_block);
+                            for (name, var) in &self.vars {
+                                let val = builder.use_var(self.snapshots[depth][name]);
+                                builder.def_var(*var, val);
+                            }
+                            builder.ins().jump(self.blocks[&labels[0]], &[]);
+
+                            builder.seal_block(cont_block);
+                            builder.switch_to_block(cont_block);
+                        }
+                        // There is nothing to abort outside of speculation.
+                        None if self.checked => {
+                            self.gen_check(builder, cond, |builder| {
+                                let [row, col] = self.gen_pos(builder, inst);
+                                builder
+                                    .ins()
+                                    .call(self.rt_refs[RTFunc::BadAbort], &[row, col]);
+                            });
+                        }
+                        None => {
+                            let cont_block = builder.create_block();
+                            builder
+                                .ins()
+                                .brif(cond, cont_block, &[], self.blocks[&labels[0]], &[]);
+                            builder.seal_block(cont_block);
+                            builder.switch_to_block(cont_block);
+                        }
+                    }
                 }
             },
             bril::Instruction::Value {
@@ -482,11 +1008,15 @@ impl CompileEnv<'_> {
                 bril::ValueOps::Add
                 | bril::ValueOps::Sub
                 | bril::ValueOps::Mul
-                | bril::ValueOps::Div
                 | bril::ValueOps::And
                 | bril::ValueOps::Or => {
                     self.gen_binary(builder, args, dest, op_type, Self::translate_op(*op));
                 }
+                bril::ValueOps::Div => {
+                    let divisor = builder.use_var(self.vars[&args[1]]);
+                    self.gen_div_check(builder, inst, divisor);
+                    self.gen_binary(builder, args, dest, op_type, Self::translate_op(*op));
+                }
                 bril::ValueOps::Lt
                 | bril::ValueOps::Le
                 | bril::ValueOps::Eq
@@ -512,6 +1042,7 @@ impl CompileEnv<'_> {
                     let inst = builder.ins().call(func_ref, &arg_vals);
                     let res = builder.inst_results(inst)[0];
                     builder.def_var(self.vars[dest], res);
+                    self.gen_error_check(builder);
                 }
                 bril::ValueOps::Id => {
                     let arg = builder.use_var(self.vars[&args[0]]);
@@ -543,14 +1074,24 @@ impl CompileEnv<'_> {
                     let bytes_val = builder.ins().iconst(ir::types::I64, elem_bytes as i64);
 
                     // Call the allocate function.
-                    let inst = builder
-                        .ins()
-                        .call(self.rt_refs[RTFunc::Alloc], &[count_val, bytes_val]);
-                    let res = builder.inst_results(inst)[0];
+                    let call = if self.checked {
+                        let [row, col] = self.gen_pos(builder, inst);
+                        builder.ins().call(
+                            self.rt_refs[RTFunc::CheckedAlloc],
+                            &[count_val, bytes_val, row, col],
+                        )
+                    } else {
+                        builder
+                            .ins()
+                            .call(self.rt_refs[RTFunc::Alloc], &[count_val, bytes_val])
+                    };
+                    let res = builder.inst_results(call)[0];
                     builder.def_var(self.vars[dest], res);
+                    self.gen_error_check(builder);
                 }
                 bril::ValueOps::Load => {
                     let ptr = builder.use_var(self.vars[&args[0]]);
+                    self.gen_access_check(builder, inst, ptr, op_type);
                     let val = emit_load(self.pointer_type, builder, op_type, ptr);
                     builder.def_var(self.vars[dest], val);
                 }
@@ -564,15 +1105,52 @@ impl CompileEnv<'_> {
                     let res = builder.ins().iadd(orig_ptr, offset_val);
                     builder.def_var(self.vars[dest], res);
                 }
-                bril::ValueOps::Get
-                | bril::ValueOps::Undef
-                | bril::ValueOps::Ceq
+
+                // SSA extension.
+                bril::ValueOps::Get => {
+                    let val = builder.use_var(self.shadows[dest]);
+                    builder.def_var(self.vars[dest], val);
+                }
+                bril::ValueOps::Undef => {
+                    // Reading an undefined variable is undefined, so we leave it alone. (Cranelift
+                    // gives it a zero value.)
+                }
+
+                // Char extension.
+                bril::ValueOps::Ceq
                 | bril::ValueOps::Clt
                 | bril::ValueOps::Cgt
                 | bril::ValueOps::Cle
-                | bril::ValueOps::Cge
-                | bril::ValueOps::Char2int
-                | bril::ValueOps::Int2char => unimplemented!(),
+                | bril::ValueOps::Cge => {
+                    self.gen_icmp(builder, args, dest, Self::translate_intcc(*op))
+                }
+                bril::ValueOps::Char2int => {
+                    let arg = builder.use_var(self.vars[&args[0]]);
+                    let res = builder.ins().uextend(ir::types::I64, arg);
+                    builder.def_var(self.vars[dest], res);
+                }
+                bril::ValueOps::Int2char => {
+                    let arg = builder.use_var(self.vars[&args[0]]);
+                    self.gen_char_check(builder, inst, arg);
+                    let res = builder.ins().ireduce(ir::types::I32, arg);
+                    builder.def_var(self.vars[dest], res);
+                }
+
+                // Bitcast extension.
+                bril::ValueOps::Float2Bits => {
+                    let arg = builder.use_var(self.vars[&args[0]]);
+                    let res = builder
+                        .ins()
+                        .bitcast(ir::types::I64, ir::MemFlags::new(), arg);
+                    builder.def_var(self.vars[dest], res);
+                }
+                bril::ValueOps::Bits2Float => {
+                    let arg = builder.use_var(self.vars[&args[0]]);
+                    let res = builder
+                        .ins()
+                        .bitcast(ir::types::F64, ir::MemFlags::new(), arg);
+                    builder.def_var(self.vars[dest], res);
+                }
             },
         }
     }
@@ -596,6 +1174,16 @@ impl CompileEnv<'_> {
         }
     }
 
+    fn is_guard(inst: &bril::Instruction) -> bool {
+        matches!(
+            inst,
+            bril::Instruction::Effect {
+                op: bril::EffectOps::Guard,
+                ..
+            }
+        )
+    }
+
     /// Emit the body of a Bril function into a CLIF function.
     fn compile_body(
         &self,
@@ -604,21 +1192,35 @@ impl CompileEnv<'_> {
         return_type: Option<&bril::Type>,
     ) {
         let mut terminated = false; // Entry block is open.
-        for code in insts {
+        self.gen_count(builder, insts);
+        for (i, code) in insts.iter().enumerate() {
             match code {
                 bril::Code::Instruction(inst) => {
                     // If a normal instruction immediately follows a terminator, we need a new (anonymous) block.
                     if terminated {
                         let block = builder.create_block();
                         builder.switch_to_block(block);
+                        self.gen_count(builder, &insts[i..]);
                         terminated = false;
                     }
 
+                    // The source location is the index of the instruction, which `finish_func`
+                    // turns back into a position.
+                    if self.debug_info {
+                        builder.set_srcloc(match inst.get_pos() {
+                            Some(_) => ir::SourceLoc::new(i as u32),
+                            None => ir::SourceLoc::default(),
+                        });
+                    }
+
                     // Compile one instruction.
-                    self.compile_inst(inst, builder);
+                    self.compile_inst(inst, self.spec_depths[i], builder);
 
                     if Self::is_term(inst) {
                         terminated = true;
+                    } else if Self::is_guard(inst) {
+                        // Count the rest of the block, which runs only if the guard passes.
+                        self.gen_count(builder, &insts[i + 1..]);
                     }
                 }
                 bril::Code::Label { label, .. } => {
@@ -632,6 +1234,7 @@ impl CompileEnv<'_> {
                     terminated = false;
 
                     builder.switch_to_block(new_block);
+                    self.gen_count(builder, &insts[i + 1..]);
                 }
             }
         }
@@ -646,6 +1249,8 @@ impl CompileEnv<'_> {
                 builder.ins().trap(UNREACHABLE);
             }
         }
+
+        self.gen_bail(builder, return_type);
     }
 }
 
@@ -655,6 +1260,14 @@ pub struct Translator<M: Module> {
     module: M,
     context: cranelift_codegen::Context,
     funcs: HashMap<String, cranelift_module::FuncId>,
+    /// The address of the runtime's failure flag, if compiled code should check it.
+    error_flag: Option<i64>,
+    /// The dynamic instruction counter, when profiling.
+    dyn_inst: Option<cranelift_module::DataId>,
+    /// Check for the runtime errors that brilirs reports.
+    checked: bool,
+    /// Line tables for the compiled functions, when emitting debug info.
+    debug_info: Option<dwarf::DebugInfo>,
 }
 
 impl<M: Module> Translator<M> {
@@ -672,6 +1285,34 @@ impl<M: Module> Translator<M> {
         }
     }
 
+    /// Count the dynamic instructions executed by the program, like `brilirs -p` does. This must
+    /// be called before compiling any functions.
+    pub fn enable_profiling(&mut self) {
+        let id = self
+            .module
+            .declare_data(
+                "_bril_dyn_inst",
+                cranelift_module::Linkage::Local,
+                true,
+                false,
+            )
+            .unwrap();
+        let mut desc = cranelift_module::DataDescription::new();
+        desc.define_zeroinit(8);
+        self.module.define_data(id, &desc).unwrap();
+        self.dyn_inst = Some(id);
+    }
+
+    /// Check for the runtime errors that brilirs reports: division by zero, converting ints that
+    /// are not Unicode scalar values to chars, `commit`s and failed `guard`s outside of
+    /// speculation, out-of-bounds or
+    /// use-after-free memory accesses, bad frees, and memory leaks. Errors are reported with the
+    /// Bril source position, if the program has positions. This must be called before compiling
+    /// any functions.
+    pub fn enable_checks(&mut self) {
+        self.checked = true;
+    }
+
     fn declare_func(&mut self, func: &bril::Function) -> cranelift_module::FuncId {
         // The Bril `main` function gets a different internal name, and we call it from a new
         // proper main function that gets argv/argc.
@@ -693,7 +1334,12 @@ impl<M: Module> Translator<M> {
             ir::Function::with_name_signature(ir::UserFuncName::user(0, func_id.as_u32()), sig);
     }
 
-    fn finish_func(&mut self, func_id: cranelift_module::FuncId, dump: bool) {
+    fn finish_func(
+        &mut self,
+        func: &bril::Function,
+        func_id: cranelift_module::FuncId,
+        dump: bool,
+    ) {
         // Print the IR, if requested.
         if dump {
             println!("{}", self.context.func.display());
@@ -703,6 +1349,33 @@ impl<M: Module> Translator<M> {
         self.module
             .define_function(func_id, &mut self.context)
             .unwrap();
+
+        // Map the machine code back to source positions.
+        if let Some(debug_info) = &mut self.debug_info {
+            let code = self.context.compiled_code().unwrap();
+            let rows = code
+                .buffer
+                .get_srclocs_sorted()
+                .iter()
+                .filter(|srcloc| !srcloc.loc.is_default())
+                .filter_map(|srcloc| match &func.instrs[srcloc.loc.bits() as usize] {
+                    bril::Code::Instruction(inst) => inst.get_pos().map(|pos| dwarf::Row {
+                        offset: srcloc.start,
+                        line: pos.pos.row,
+                        column: pos.pos.col,
+                    }),
+                    bril::Code::Label { .. } => None,
+                })
+                .collect();
+            debug_info.add_func(dwarf::FuncLines {
+                id: func_id,
+                name: func.name.clone(),
+                line: func.pos.as_ref().map(|pos| pos.pos.row),
+                size: code.buffer.total_size(),
+                rows,
+            });
+        }
+
         self.context.clear();
     }
 
@@ -725,6 +1398,14 @@ impl<M: Module> Translator<M> {
                 (*name, var)
             })
             .collect();
+        let shadows: HashMap<&String, Variable> = shadow_vars(func)
+            .into_iter()
+            .map(|(name, typ)| {
+                let var =
+                    builder.declare_var(translate_type(typ, self.module.isa().pointer_type()));
+                (name, var)
+            })
+            .collect();
 
         // Create blocks for every label.
         let blocks: HashMap<&String, ir::Block> = func
@@ -747,13 +1428,53 @@ impl<M: Module> Translator<M> {
             .map(|(name, id)| (name, self.module.declare_func_in_func(*id, builder.func)))
             .collect();
 
+        // Snapshot variables for each level of speculation.
+        let spec_depths = spec_depths(&func.instrs)
+            .expect("speculation depth differs between paths (see `check_supported`)");
+        let spec_levels = func
+            .instrs
+            .iter()
+            .zip(&spec_depths)
+            .filter_map(|(code, depth)| match code {
+                bril::Code::Instruction(bril::Instruction::Effect {
+                    op: bril::EffectOps::Speculate,
+                    ..
+                }) => Some(depth + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        let snapshots: Vec<HashMap<&String, Variable>> = (0..spec_levels)
+            .map(|_| {
+                var_types
+                    .iter()
+                    .map(|(name, typ)| {
+                        let var = builder
+                            .declare_var(translate_type(typ, self.module.isa().pointer_type()));
+                        (*name, var)
+                    })
+                    .collect()
+            })
+            .collect();
+
         let env = CompileEnv {
             vars,
+            shadows,
             var_types,
             rt_refs,
             blocks,
             func_refs,
             pointer_type: self.module.isa().pointer_type(),
+            error_check: self
+                .error_flag
+                .map(|flag_addr| (flag_addr, builder.create_block())),
+            dyn_inst: self
+                .dyn_inst
+                .map(|id| self.module.declare_data_in_func(id, builder.func)),
+            checked: self.checked,
+            spec_depths,
+            snapshots,
+            debug_info: self.debug_info.is_some(),
         };
 
         // Define variables for function arguments in the entry block.
@@ -774,7 +1495,9 @@ impl<M: Module> Translator<M> {
 
     /// Generate a C-style `main` function that parses command-line arguments and then calls the
     /// Bril `main` function.
-    pub fn add_c_main(&mut self, args: &[bril::Argument], dump: bool) -> cranelift_module::FuncId {
+    pub fn add_c_main(&mut self, main: &bril::Function, dump: bool) -> cranelift_module::FuncId {
+        let args = &main.args;
+
         // Declare `main` with argc/argv parameters.
         let pointer_type = self.module.isa().pointer_type();
         let sig = ir::Signature {
@@ -829,8 +1552,10 @@ impl<M: Module> Translator<M> {
                     bril::Type::Int => RTSetupFunc::ParseInt,
                     bril::Type::Bool => RTSetupFunc::ParseBool,
                     bril::Type::Float => RTSetupFunc::ParseFloat,
-                    bril::Type::Char => unimplemented!(),
-                    bril::Type::Pointer(_) => unimplemented!("can't print pointers"),
+                    bril::Type::Char => RTSetupFunc::ParseChar,
+                    bril::Type::Pointer(_) | bril::Type::Any => {
+                        unreachable!("rejected by `check_supported`")
+                    }
                 }];
                 let idx_arg = builder.ins().iconst(ir::types::I64, (i + 1) as i64); // skip argv[0]
                 let inst = builder.ins().call(parse_ref, &[argv_arg, idx_arg]);
@@ -843,6 +1568,28 @@ impl<M: Module> Translator<M> {
         let real_main_ref = self.module.declare_func_in_func(real_main_id, builder.func);
         builder.ins().call(real_main_ref, &arg_vals);
 
+        // Check for leaks, reporting them at the position of `@main` like brilirs.
+        if self.checked {
+            let (row, col) = pos_row_col(main.pos.as_ref());
+            let row = builder.ins().iconst(ir::types::I64, row);
+            let col = builder.ins().iconst(ir::types::I64, col);
+            builder
+                .ins()
+                .call(rt_setup_refs[RTSetupFunc::CheckLeaks], &[row, col]);
+        }
+
+        // Report the dynamic instruction count.
+        if let Some(id) = self.dyn_inst {
+            let counter = self.module.declare_data_in_func(id, builder.func);
+            let addr = builder.ins().global_value(pointer_type, counter);
+            let count = builder
+                .ins()
+                .load(ir::types::I64, ir::MemFlags::trusted(), addr, 0);
+            builder
+                .ins()
+                .call(rt_setup_refs[RTSetupFunc::PrintDynInst], &[count]);
+        }
+
         // Return 0 from `main`.
         let zero = builder.ins().iconst(self.module.isa().pointer_type(), 0);
         builder.ins().return_(&[zero]);
@@ -861,8 +1608,8 @@ impl<M: Module> Translator<M> {
     }
 
     /// Add a function that wraps a Bril function to invoke it with arguments that come from
-    /// memory. The new function takes a single pointer as an argument, which points to an array of
-    /// pointers to the arguments.
+    /// memory. The new function takes two pointers as arguments. The first points to an array of
+    /// pointers to the arguments, and the second is where the return value (if any) goes.
     pub fn add_mem_wrapper(
         &mut self,
         name: &str,
@@ -872,11 +1619,14 @@ impl<M: Module> Translator<M> {
         // Declare wrapper function.
         let pointer_type = self.module.isa().pointer_type();
         let sig = ir::Signature {
-            params: vec![ir::AbiParam::new(pointer_type)],
+            params: vec![
+                ir::AbiParam::new(pointer_type),
+                ir::AbiParam::new(pointer_type),
+            ],
             returns: vec![],
             call_conv: self.module.isa().default_call_conv(),
         };
-        let wrapped_name = format!("{name}_wrapper");
+        let wrapped_name = format!("__bril_{name}_wrapper");
         let wrapper_id = self
             .module
             .declare_function(&wrapped_name, cranelift_module::Linkage::Export, &sig)
@@ -911,10 +1661,14 @@ impl<M: Module> Translator<M> {
             })
             .collect();
 
-        // Call the "real" main function.
+        // Call the "real" function and store its return value.
         let real_func_id = self.funcs[name];
         let real_func_ref = self.module.declare_func_in_func(real_func_id, builder.func);
-        builder.ins().call(real_func_ref, &arg_vals);
+        let inst = builder.ins().call(real_func_ref, &arg_vals);
+        if let Some(&ret_val) = builder.inst_results(inst).first() {
+            let ret_ptr = builder.block_params(block)[1];
+            emit_store(&mut builder, ret_ptr, ret_val);
+        }
 
         builder.ins().return_(&[]);
 
@@ -930,6 +1684,10 @@ impl<M: Module> Translator<M> {
         wrapper_id
     }
 
+    /// Compile every function in a program.
+    ///
+    /// # Panics
+    /// Panics if the program is not one that `check_supported` accepts.
     pub fn compile_prog(&mut self, prog: &bril::Program, dump: bool) {
         // Declare all functions.
         for func in &prog.functions {
@@ -942,7 +1700,7 @@ impl<M: Module> Translator<M> {
             let id = self.funcs[&func.name];
             self.enter_func(func, id);
             self.compile_func(func);
-            self.finish_func(id, dump);
+            self.finish_func(func, id, dump);
         }
     }
 }
@@ -983,16 +1741,90 @@ impl Translator<ObjectModule> {
             module,
             context: cranelift_codegen::Context::new(),
             funcs: HashMap::new(),
+            // The C runtime exits on failure, so there's nothing to check.
+            error_flag: None,
+            dyn_inst: None,
+            checked: false,
+            debug_info: None,
         }
     }
 
+    /// Emit DWARF line info that maps the compiled code back to positions in the Bril program,
+    /// which is in the file `source`. This must be called before compiling any functions.
+    pub fn enable_debug_info(&mut self, source: &str) {
+        self.debug_info = Some(dwarf::DebugInfo::new(source));
+    }
+
     pub fn emit(self, output: &str) {
-        let prod = self.module.finish();
+        let address_size = self.module.isa().pointer_bytes();
+        let endian = match self.module.isa().endianness() {
+            ir::Endianness::Little => gimli::RunTimeEndian::Little,
+            ir::Endianness::Big => gimli::RunTimeEndian::Big,
+        };
+        let mut prod = self.module.finish();
+        if let Some(debug_info) = &self.debug_info {
+            debug_info
+                .write(&mut prod.object, address_size, endian, |id| {
+                    prod.functions[id].unwrap().0
+                })
+                .expect("failed to write debug info");
+        }
         let objdata = prod.emit().expect("emission failed");
         fs::write(output, objdata).expect("failed to write .o file");
     }
 }
 
+/// A Bril value as compiled code represents it, for passing values to and from JIT-compiled
+/// functions. Pointers point to memory from the runtime's allocator.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub union RawValue {
+    pub int: i64,
+    pub bool: bool,
+    pub float: f64,
+    pub char: u32,
+    pub ptr: *mut u8,
+}
+
+impl From<&bril::Literal> for RawValue {
+    fn from(lit: &bril::Literal) -> Self {
+        match *lit {
+            bril::Literal::Int(int) => Self { int },
+            bril::Literal::Bool(bool) => Self { bool },
+            bril::Literal::Float(float) => Self { float },
+            bril::Literal::Char(c) => Self { char: c.into() },
+        }
+    }
+}
+
+/// A block of memory allocated by the runtime in checked mode.
+#[derive(Debug, Clone, Copy)]
+pub struct Allocation {
+    pub ptr: *mut u8,
+    /// The runtime's sequence number for the allocation. Unlike `ptr`, this is never reused
+    /// after the allocation is freed.
+    pub index: usize,
+    /// The size of the allocation in bytes.
+    pub size: usize,
+    /// The size of each element in bytes.
+    pub elem_size: usize,
+}
+
+/// A failure reported by the runtime while running JIT-compiled code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeError {
+    pub message: String,
+    /// The row and column of the Bril instruction that failed, in checked mode.
+    pub pos: Option<(i64, i64)>,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (row, col) = self.pos.unwrap_or((0, 0));
+        write!(f, "{}", rt::at_pos(&self.message, row, col))
+    }
+}
+
 /// A JIT compiler.
 impl Translator<JITModule> {
     // `cranelift_jit` does not yet support PIC on AArch64:
@@ -1023,12 +1855,7 @@ impl Translator<JITModule> {
         let mut builder = Self::jit_builder();
 
         // Provide runtime functions.
-        enum_map! {
-            rtfunc => {
-                let f: RTFunc = rtfunc;
-                builder.symbol(f.name(), f.rt_impl());
-            }
-        };
+        builder.symbols(rt::symbols());
 
         let mut module = JITModule::new(builder);
 
@@ -1037,6 +1864,10 @@ impl Translator<JITModule> {
             context: module.make_context(),
             module,
             funcs: HashMap::new(),
+            error_flag: Some(&rt::FAILED as *const _ as i64),
+            dyn_inst: None,
+            checked: false,
+            debug_info: None,
         }
     }
 
@@ -1049,24 +1880,122 @@ impl Translator<JITModule> {
         self.module.get_finalized_function(func_id)
     }
 
-    fn val_ptrs(vals: &[bril::Literal]) -> Vec<*const u8> {
-        vals.iter()
-            .map(|lit| match lit {
-                bril::Literal::Int(i) => i as *const i64 as *const u8,
-                bril::Literal::Bool(b) => b as *const bool as *const u8,
-                bril::Literal::Float(f) => f as *const f64 as *const u8,
-                bril::Literal::Char(c) => c as *const char as *const u8,
+    /// Get the number of dynamic instructions executed so far, if profiling is enabled.
+    pub fn dyn_inst_count(&self) -> Option<i64> {
+        self.dyn_inst.map(|id| {
+            let (ptr, _) = self.module.get_finalized_data(id);
+            unsafe { *(ptr as *const i64) }
+        })
+    }
+
+    /// In checked mode, report an error if any memory is still allocated, at the position of the
+    /// `main` function like brilirs.
+    pub fn check_leaks(&self, main: &bril::Function) -> Result<(), String> {
+        if !self.checked {
+            return Ok(());
+        }
+        let (row, col) = pos_row_col(main.pos.as_ref());
+        rt::check_leaks(row, col).map_or(Ok(()), Err)
+    }
+
+    /// Collect everything that compiled code prints from now on, instead of printing it to
+    /// stdout. Use `take_output` to get it.
+    pub fn capture_output(&mut self) {
+        rt::capture_output();
+    }
+
+    /// Get (and clear) the output that compiled code has printed since `capture_output`.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        rt::take_output()
+    }
+
+    /// The number of bytes that compiled code uses to store a value of a Bril type in memory.
+    pub fn value_size(&self, typ: &bril::Type) -> usize {
+        translate_type(typ, self.module.isa().pointer_type()).bytes() as usize
+    }
+
+    /// In checked mode, allocate memory for `count` values of type `elem` that compiled code can
+    /// access and free. Returns the error message if the allocation fails.
+    pub fn alloc(&mut self, count: usize, elem: &bril::Type) -> Result<Allocation, String> {
+        assert!(self.checked, "alloc requires checked mode");
+        let bytes = self.value_size(elem);
+        let ptr = rt::checked_alloc(count as i64, bytes as i64, 0, 0);
+        match rt::take_error() {
+            Some(msg) => Err(msg),
+            None => Ok(Allocation {
+                ptr,
+                index: rt::allocation_index(ptr).unwrap(),
+                size: count * bytes,
+                elem_size: bytes,
+            }),
+        }
+    }
+
+    /// In checked mode, get all the memory that is currently allocated, in address order.
+    pub fn allocations(&self) -> Vec<Allocation> {
+        assert!(self.checked, "allocations requires checked mode");
+        rt::live_allocations()
+            .into_iter()
+            .map(|(ptr, index, size, elem_size)| Allocation {
+                ptr,
+                index,
+                size,
+                elem_size,
             })
             .collect()
     }
 
-    /// Run a JITted wrapper function.
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn run(&mut self, func_id: cranelift_module::FuncId, args: &[bril::Literal]) {
+    /// In checked mode, free memory from `alloc` or from compiled code.
+    ///
+    /// # Safety
+    /// `ptr` must be the start of an allocation in `allocations`, which must not be used again.
+    pub unsafe fn free(&mut self, ptr: *mut u8) {
+        assert!(self.checked, "free requires checked mode");
+        rt::checked_free(ptr, 0, 0);
+    }
+
+    /// Call a JITted wrapper function, passing it `args` and returning the Bril function's
+    /// return value (which is meaningless if it does not return anything).
+    ///
+    /// # Safety
+    /// `args` must match the parameters of the wrapped function, and any pointers among them must
+    /// point to memory that compiled code can use.
+    pub unsafe fn call(
+        &mut self,
+        func_id: cranelift_module::FuncId,
+        args: &[RawValue],
+    ) -> Result<RawValue, RuntimeError> {
         let func_ptr = self.get_func_ptr(func_id);
-        let arg_ptrs = Self::val_ptrs(args);
-        let func = unsafe { mem::transmute::<*const u8, fn(*const *const u8) -> ()>(func_ptr) };
-        func(arg_ptrs.as_ptr());
+        let arg_ptrs: Vec<*const RawValue> = args.iter().map(|arg| arg as *const _).collect();
+        let mut ret = RawValue { int: 0 };
+        let func = unsafe {
+            mem::transmute::<*const u8, fn(*const *const RawValue, *mut RawValue) -> ()>(func_ptr)
+        };
+        func(arg_ptrs.as_ptr(), &mut ret);
+
+        match rt::take_error_at() {
+            Some((message, row, col)) => Err(RuntimeError {
+                message,
+                pos: (row != 0).then_some((row, col)),
+            }),
+            None => Ok(ret),
+        }
+    }
+
+    /// Run a JITted wrapper function. Returns the error message if the runtime reported a
+    /// failure.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn run(
+        &mut self,
+        func_id: cranelift_module::FuncId,
+        args: &[bril::Literal],
+    ) -> Result<(), String> {
+        let args: Vec<RawValue> = args.iter().map(RawValue::from).collect();
+        let result = unsafe { self.call(func_id, &args) };
+
+        // Flush everything the program printed, like the C runtime does when it exits.
+        io::stdout().flush().unwrap();
+        result.map(|_| ()).map_err(|e| e.to_string())
     }
 }
 
@@ -1079,3 +2008,31 @@ impl Default for Translator<JITModule> {
 pub fn find_func<'a>(funcs: &'a [bril::Function], name: &str) -> &'a bril::Function {
     funcs.iter().find(|f| f.name == name).unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether the C source `src` defines or declares the function `name`.
+    fn defines(src: &str, name: &str) -> bool {
+        src.match_indices(&format!("{name}("))
+            .any(|(i, _)| !src[..i].ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_'))
+    }
+
+    /// The compiler, the JIT's runtime, and `rt.c` all have to agree on the runtime functions.
+    #[test]
+    fn runtimes_define_every_function() {
+        let symbols: Vec<_> = rt::symbols().into_iter().map(|(name, _)| name).collect();
+        let rt_c = crate::c_runtime();
+        for i in 0..RTFunc::LENGTH {
+            let name = RTFunc::from_usize(i).name();
+            assert!(symbols.contains(&name), "{name} is missing from rt.rs");
+            assert!(defines(rt_c, name), "{name} is missing from rt.c");
+        }
+        assert_eq!(symbols.len(), RTFunc::LENGTH, "rt.rs has unused functions");
+        for i in 0..RTSetupFunc::LENGTH {
+            let name = RTSetupFunc::from_usize(i).name();
+            assert!(defines(rt_c, name), "{name} is missing from rt.c");
+        }
+    }
+}
@@ -10,6 +10,9 @@ pub struct BBProgram {
   pub(crate) index_of_main: Option<FuncIndex>,
   pub(crate) func_index: Vec<BBFunction>,
   pub(crate) max_frame_size: usize,
+  /// The original program, for compiling hot functions
+  #[cfg(feature = "jit")]
+  pub(crate) program: Program,
 }
 
 impl TryFrom<Program> for BBProgram {
@@ -30,6 +33,8 @@ impl BBProgram {
   /// or 2^16 variables in a function.
   pub fn new(prog: Program) -> Result<Self, InterpError> {
     let num_funcs = prog.functions.len();
+    #[cfg(feature = "jit")]
+    let program = prog.clone();
 
     let func_map: FxHashMap<String, FuncIndex> = prog
       .functions
@@ -51,6 +56,8 @@ impl BBProgram {
       index_of_main: func_map.get("main").copied(),
       func_index,
       max_frame_size,
+      #[cfg(feature = "jit")]
+      program,
     };
     if func_map.len() == num_funcs {
       Ok(bb)
@@ -101,6 +108,8 @@ pub(crate) struct BBFunction {
   pub(crate) num_of_vars: usize,
   pub(crate) args_as_nums: Vec<VarIndex>,
   pub(crate) pos: Option<Position>,
+  #[cfg(feature = "jit")]
+  pub(crate) index: FuncIndex,
 }
 
 impl BBFunction {
@@ -308,6 +317,8 @@ impl BBFunction {
       args_as_nums,
       num_of_vars: num_var_map.len(),
       pos: func.pos,
+      #[cfg(feature = "jit")]
+      index: func_map[&func.name],
     })
   }
 
@@ -20,6 +20,18 @@ pub struct Cli {
   #[arg(short, long, action)]
   pub text: bool,
 
+  /// Compile functions to native code once they have been called or looped this many times
+  #[cfg(feature = "jit")]
+  #[arg(
+    short,
+    long,
+    value_name = "THRESHOLD",
+    num_args = 0..=1,
+    require_equals = true,
+    default_missing_value = "1000"
+  )]
+  pub jit: Option<usize>,
+
   /// Arguments for the main function
   #[arg(action)]
   pub args: Vec<String>,
@@ -57,8 +57,11 @@ pub enum InterpError {
   BadAsmtType(bril_rs::Type, bril_rs::Type), // (expected, actual). For when the LHS type of an instruction is bad
   #[error("There has been an io error: `{0:?}`")]
   IoError(#[from] std::io::Error),
-  #[error("value ${0} cannot be converted to char")]
+  #[error("value {0} cannot be converted to char")]
   ToCharError(i64),
+  #[cfg(feature = "jit")]
+  #[error("{0}")]
+  NativeError(String), // An error reported by code compiled with brilift
   #[error(
     "You probably shouldn't see this error, this is here to handle conversions between InterpError and PositionalError"
   )]
@@ -156,7 +159,7 @@ impl From<InterpError> for PositionalInterpError {
 }
 
 impl From<PositionalConversionError> for PositionalInterpError {
-  fn from(PositionalConversionError { e, pos }: PositionalConversionError) -> Self {
+  fn from(PositionalConversionError { e, pos, .. }: PositionalConversionError) -> Self {
     Self {
       e: Box::new(e),
       pos,
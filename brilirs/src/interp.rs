@@ -8,6 +8,9 @@ use std::cmp::max;
 use std::collections::HashMap;
 use std::fmt;
 
+#[cfg(feature = "jit")]
+mod jit;
+
 // The Environment is the data structure used to represent the stack of the program.
 // The values of all variables are store here. Each variable is represented as a number so
 // each value can be store at the index of that number.
@@ -382,6 +385,8 @@ fn execute<'a, T: std::io::Write>(
   let mut curr_block_idx = LabelIndex(0);
 
   loop {
+    #[cfg(feature = "jit")]
+    let prev_block_idx = curr_block_idx;
     let curr_block = &func.blocks[curr_block_idx.0 as usize];
     let curr_instrs = &curr_block.flat_instrs;
 
@@ -591,6 +596,12 @@ fn execute<'a, T: std::io::Write>(
           state.env.set(op.dest, Value::Pointer(ptr));
         }
         FlatIR::MultiArityCall { func, dest, args } => {
+          #[cfg(feature = "jit")]
+          if let jit::Call::Returned(result) = jit::call(state, *func, args)? {
+            state.env.set(*dest, result.unwrap());
+            continue;
+          }
+
           let callee_func = state.prog.get(*func).unwrap();
 
           make_func_args(callee_func, args, &mut state.env);
@@ -602,6 +613,11 @@ fn execute<'a, T: std::io::Write>(
           state.env.set(*dest, result);
         }
         FlatIR::TailCall { func, args } | FlatIR::TailCallVoid { func, args } => {
+          #[cfg(feature = "jit")]
+          if let jit::Call::Returned(result) = jit::call(state, *func, args)? {
+            return Ok(result);
+          }
+
           let callee_func = state.prog.get(*func).unwrap();
           make_tail_call_args(callee_func, args, &mut state.env, &mut state.arg_scratch);
           return execute(state, callee_func);
@@ -668,6 +684,11 @@ fn execute<'a, T: std::io::Write>(
           return Ok(None);
         }
         FlatIR::EffectfulCall { func, args } => {
+          #[cfg(feature = "jit")]
+          if let jit::Call::Returned(_) = jit::call(state, *func, args)? {
+            continue;
+          }
+
           let callee_func = state.prog.get(*func).unwrap();
 
           make_func_args(callee_func, args, &mut state.env);
@@ -741,6 +762,14 @@ fn execute<'a, T: std::io::Write>(
       }
       BlockExit::Branched => {} // Branch/CmpBranch already set curr_block_idx
     }
+
+    // Jumping backwards means we are in a loop, which makes the function hotter
+    #[cfg(feature = "jit")]
+    if curr_block_idx.0 <= prev_block_idx.0
+      && let Some(tiering) = &mut state.tiering
+    {
+      tiering.back_edge(func.index);
+    }
   }
 }
 
@@ -818,6 +847,8 @@ struct State<'a, T: std::io::Write> {
   out: T,
   instruction_count: usize,
   arg_scratch: Vec<Value>,
+  #[cfg(feature = "jit")]
+  tiering: Option<jit::Tiering<'a>>,
 }
 
 impl<'a, T: std::io::Write> State<'a, T> {
@@ -829,6 +860,8 @@ impl<'a, T: std::io::Write> State<'a, T> {
       out,
       instruction_count: 0,
       arg_scratch: Vec::with_capacity(prog.max_frame_size),
+      #[cfg(feature = "jit")]
+      tiering: None,
     }
   }
 }
@@ -845,21 +878,59 @@ pub fn execute_main<T: std::io::Write, U: std::io::Write>(
   out: T,
   input_args: &[String],
   profiling: bool,
-  mut profiling_out: U,
+  profiling_out: U,
 ) -> Result<(), PositionalInterpError> {
+  let (main_func, env) = setup_main(prog, input_args)?;
+  let state = State::new(prog, env, Heap::default(), out);
+  run_main(state, main_func, profiling, profiling_out)
+}
+
+/// Like [`execute_main`], but with tiered execution.
+///
+/// Once a function has been called or has looped ```threshold``` times, it is compiled to native code with brilift and later calls to it run the native code instead. Programs that brilift cannot compile are only interpreted.
+/// # Panics
+/// This should not panic with normal use except if there is a bug or if you are using an unimplemented feature
+/// # Errors
+/// Will error on malformed `BBProgram`, like if the original Bril program was not well-formed
+#[cfg(feature = "jit")]
+pub fn execute_main_tiered<T: std::io::Write, U: std::io::Write>(
+  prog: &BBProgram,
+  out: T,
+  input_args: &[String],
+  profiling: bool,
+  profiling_out: U,
+  threshold: usize,
+) -> Result<(), PositionalInterpError> {
+  let (main_func, env) = setup_main(prog, input_args)?;
+  let mut state = State::new(prog, env, Heap::default(), out);
+  state.tiering = jit::Tiering::new(&prog.program, threshold, profiling);
+  run_main(state, main_func, profiling, profiling_out)
+}
+
+// Finds the main function and sets up its frame with the command line arguments
+fn setup_main<'a>(
+  prog: &'a BBProgram,
+  input_args: &[String],
+) -> Result<(&'a BBFunction, Environment), PositionalInterpError> {
   let main_func = prog
     .index_of_main
     .map(|i| prog.get(i).unwrap())
     .ok_or(InterpError::NoMainFunction)?;
 
-  let mut env = Environment::new(main_func.num_of_vars, prog.max_frame_size);
-  let heap = Heap::default();
+  let env = Environment::new(main_func.num_of_vars, prog.max_frame_size);
 
-  env = parse_args(env, &main_func.args, &main_func.args_as_nums, input_args)
+  let env = parse_args(env, &main_func.args, &main_func.args_as_nums, input_args)
     .map_err(|e| e.add_pos(main_func.pos.clone()))?;
 
-  let mut state = State::new(prog, env, heap, out);
+  Ok((main_func, env))
+}
 
+fn run_main<'a, T: std::io::Write, U: std::io::Write>(
+  mut state: State<'a, T>,
+  main_func: &'a BBFunction,
+  profiling: bool,
+  mut profiling_out: U,
+) -> Result<(), PositionalInterpError> {
   execute(&mut state, main_func)?;
 
   if !state.heap.is_empty() {
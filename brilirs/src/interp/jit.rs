@@ -0,0 +1,421 @@
+// Tiered execution: functions that get hot in the interpreter are compiled to native code with
+// brilift's JIT, and later calls to them run the native code instead.
+//
+// Native code has its own memory, so each native call copies everything it can reach in the
+// interpreter's heap into native memory, and copies it all back (along with any new allocations)
+// when it returns. Native memory only lives for the duration of one call.
+
+use super::{Heap, Pointer, State, Value};
+use crate::error::{InterpError, PositionalInterpError};
+use crate::ir::{FuncIndex, VarIndex};
+use bril_rs::{ColRow, Position, Program, Type};
+use brilift::translator::{Allocation, RawValue, Translator, check_supported};
+use brilift::{FuncId, JITModule};
+use std::collections::{BTreeMap, HashMap};
+
+// How far along a function is in tiering up
+enum Tier {
+  // Interpreted, with the number of calls and loop back-edges so far
+  Cold(usize),
+  // Compiled, with the brilift wrapper that calls it
+  Native(FuncId),
+}
+
+pub(super) struct Tiering<'a> {
+  program: &'a Program,
+  threshold: usize,
+  profiling: bool,
+  tiers: Vec<Tier>,
+  // brilift compiles the whole program when the first function gets hot
+  jit: Option<Translator<JITModule>>,
+  // The JIT's dynamic instruction count when native code last returned
+  dyn_inst: i64,
+}
+
+impl<'a> Tiering<'a> {
+  // Returns None if brilift cannot compile `program`, which then only gets interpreted
+  pub(super) fn new(program: &'a Program, threshold: usize, profiling: bool) -> Option<Self> {
+    check_supported(program).is_ok().then(|| Self {
+      program,
+      threshold,
+      profiling,
+      tiers: program.functions.iter().map(|_| Tier::Cold(0)).collect(),
+      jit: None,
+      dyn_inst: 0,
+    })
+  }
+
+  pub(super) fn back_edge(&mut self, func: FuncIndex) {
+    if let Tier::Cold(count) = &mut self.tiers[func.0 as usize] {
+      *count += 1;
+    }
+  }
+
+  // Counts a call to `func`, and returns its native wrapper once it is hot
+  fn tier_up(&mut self, func: FuncIndex) -> Option<FuncId> {
+    let count = match &mut self.tiers[func.0 as usize] {
+      Tier::Native(id) => return Some(*id),
+      Tier::Cold(count) => {
+        *count += 1;
+        *count
+      }
+    };
+    if count < self.threshold {
+      return None;
+    }
+
+    let jit = self.jit.get_or_insert_with(|| {
+      let mut jit = Translator::<JITModule>::new();
+      jit.enable_checks();
+      if self.profiling {
+        jit.enable_profiling();
+      }
+      jit.capture_output();
+      jit.compile_prog(self.program, false);
+      jit
+    });
+    let bril_func = &self.program.functions[func.0 as usize];
+    let id = jit.add_mem_wrapper(&bril_func.name, &bril_func.args, false);
+    self.tiers[func.0 as usize] = Tier::Native(id);
+    Some(id)
+  }
+}
+
+// The outcome of trying to call a function in native code
+pub(super) enum Call {
+  Interpret,
+  Returned(Option<Value>),
+}
+
+// Calls `func` with `args` from the current frame in native code, if it is hot
+pub(super) fn call<T: std::io::Write>(
+  state: &mut State<'_, T>,
+  func: FuncIndex,
+  args: &[VarIndex],
+) -> Result<Call, PositionalInterpError> {
+  let Some(tiering) = &mut state.tiering else {
+    return Ok(Call::Interpret);
+  };
+  let Some(id) = tiering.tier_up(func) else {
+    return Ok(Call::Interpret);
+  };
+  let bril_func = &tiering.program.functions[func.0 as usize];
+  let jit = tiering.jit.as_mut().unwrap();
+
+  let mut marshaler = Marshaler::new(jit, &mut state.heap);
+  let native_args: Option<Vec<RawValue>> = args
+    .iter()
+    .zip(&bril_func.args)
+    .map(|(arg, param)| marshaler.native_value(*state.env.get(*arg), &param.arg_type))
+    .collect();
+  // Memory that the interpreter has already freed (or that has the wrong type) cannot be copied,
+  // so the interpreter gets to report the error.
+  let Some(native_args) = native_args.filter(|_| marshaler.copy_in()) else {
+    marshaler.discard();
+    return Ok(Call::Interpret);
+  };
+
+  let result = unsafe { marshaler.jit.call(id, &native_args) };
+
+  // Catch up on what native code printed and counted.
+  let output = marshaler.jit.take_output();
+  if let Some(count) = marshaler.jit.dyn_inst_count() {
+    state.instruction_count += usize::try_from(count - tiering.dyn_inst).unwrap();
+    tiering.dyn_inst = count;
+  }
+  state
+    .out
+    .write_all(&output)
+    .map_err(|e| InterpError::IoError(e).add_pos(None))?;
+
+  match result {
+    Ok(ret) => marshaler
+      .copy_out(ret, bril_func.return_type.as_ref())
+      .map(Call::Returned)
+      .map_err(|e| e.add_pos(bril_func.pos.clone())),
+    Err(e) => {
+      marshaler.discard();
+      let src = bril_func.pos.as_ref().and_then(|p| p.src.clone());
+      Err(
+        InterpError::NativeError(e.message).add_pos(e.pos.map(|(row, col)| Position {
+          pos: ColRow {
+            row: row.try_into().unwrap(),
+            col: col.try_into().unwrap(),
+          },
+          pos_end: None,
+          src,
+        })),
+      )
+    }
+  }
+}
+
+// Copies values between the interpreter's heap and native memory for one native call
+struct Marshaler<'j, 'h> {
+  jit: &'j mut Translator<JITModule>,
+  heap: &'h mut Heap,
+  // The native copy of each heap entry, by index, with its element type
+  native: HashMap<u16, (Allocation, Type)>,
+  // The heap entry for each native allocation, by the runtime's sequence number. Addresses can't
+  // be used for this, since native code can free an allocation and get its address back from
+  // another one.
+  entries: HashMap<usize, u16>,
+  // Heap entries whose contents still need to be copied to or from native memory
+  pending: Vec<u16>,
+}
+
+impl<'j, 'h> Marshaler<'j, 'h> {
+  fn new(jit: &'j mut Translator<JITModule>, heap: &'h mut Heap) -> Self {
+    Self {
+      jit,
+      heap,
+      native: HashMap::new(),
+      entries: HashMap::new(),
+      pending: Vec::new(),
+    }
+  }
+
+  // Converts a value for native code. Heap entries that pointers refer to get native memory,
+  // and are copied by `copy_in`.
+  fn native_value(&mut self, val: Value, ty: &Type) -> Option<RawValue> {
+    Some(match val {
+      Value::Int(i) => RawValue { int: i },
+      Value::Bool(b) => RawValue { bool: b },
+      Value::Float(f) => RawValue { float: f },
+      Value::Char(c) => RawValue { char: c.into() },
+      Value::Pointer(ptr) => {
+        let Type::Pointer(elem) = ty else {
+          unreachable!()
+        };
+        RawValue {
+          ptr: self.native_ptr(ptr, elem)?,
+        }
+      }
+      // Native code has no notion of uninitialized values
+      Value::Uninitialized => RawValue { int: 0 },
+    })
+  }
+
+  fn native_ptr(&mut self, ptr: Pointer, elem: &Type) -> Option<*mut u8> {
+    let base = match self.native.get(&ptr.index) {
+      Some((allocation, ty)) if ty == elem => allocation.ptr,
+      Some(_) => return None,
+      None => {
+        let entry = &self.heap.memory[ptr.index as usize];
+        if entry.generation != ptr.generation {
+          return None;
+        }
+        let allocation = self.jit.alloc(entry.data.len(), elem).ok()?;
+        self.native.insert(ptr.index, (allocation, elem.clone()));
+        self.entries.insert(allocation.index, ptr.index);
+        self.pending.push(ptr.index);
+        allocation.ptr
+      }
+    };
+    let offset = isize::try_from(ptr.offset).unwrap() * isize::try_from(self.size(elem)).unwrap();
+    Some(base.wrapping_offset(offset))
+  }
+
+  // Copies the heap entries that native code can reach into native memory
+  fn copy_in(&mut self) -> bool {
+    while let Some(index) = self.pending.pop() {
+      let (allocation, elem) = self.native[&index].clone();
+      let base = allocation.ptr;
+      for i in 0..self.heap.memory[index as usize].data.len() {
+        let Some(val) = self.native_value(self.heap.memory[index as usize].data[i], &elem) else {
+          return false;
+        };
+        unsafe { write(base.wrapping_add(i * self.size(&elem)), val, &elem) };
+      }
+    }
+    true
+  }
+
+  // Copies native memory back into the heap after a call that returned `ret`, and frees it.
+  // Memory that native code allocated becomes new heap entries, and memory that it freed is
+  // freed in the heap.
+  fn copy_out(
+    mut self,
+    ret: RawValue,
+    ret_type: Option<&Type>,
+  ) -> Result<Option<Value>, InterpError> {
+    let allocations: BTreeMap<usize, Allocation> = self
+      .jit
+      .allocations()
+      .into_iter()
+      .map(|a| (a.ptr as usize, a))
+      .collect();
+
+    // An allocation was freed unless the one at its address is still the same allocation
+    let freed: Vec<u16> = self
+      .native
+      .iter()
+      .filter(|(_, (allocation, _))| {
+        allocations
+          .get(&(allocation.ptr as usize))
+          .is_none_or(|a| a.index != allocation.index)
+      })
+      .map(|(index, _)| *index)
+      .collect();
+    for index in freed {
+      let (allocation, _) = self.native.remove(&index).unwrap();
+      self.entries.remove(&allocation.index);
+      let generation = self.heap.memory[index as usize].generation;
+      self.heap.free(Pointer {
+        index,
+        generation,
+        offset: 0,
+      })?;
+    }
+
+    let ret = ret_type
+      .map(|ty| self.interp_value(ret, ty, &allocations))
+      .transpose()?;
+
+    self.pending = self.native.keys().copied().collect();
+    while let Some(index) = self.pending.pop() {
+      let (allocation, elem) = self.native[&index].clone();
+      let base = allocation.ptr;
+      for i in 0..self.heap.memory[index as usize].data.len() {
+        let raw = unsafe { read(base.wrapping_add(i * self.size(&elem)), &elem) };
+        let val = self.interp_value(raw, &elem, &allocations)?;
+        self.heap.memory[index as usize].data[i] = val;
+      }
+    }
+
+    // Whatever native code allocated but did not keep a pointer to has leaked, which the
+    // interpreter reports at the end of `main`.
+    for a in allocations.values() {
+      if !self.entries.contains_key(&a.index) {
+        self
+          .heap
+          .alloc((a.size / a.elem_size).try_into().unwrap())?;
+      }
+    }
+
+    self.discard();
+    Ok(ret)
+  }
+
+  // Converts a value from native code. Pointers to native memory that is not yet in the heap get
+  // new heap entries, whose contents are copied by `copy_out`.
+  fn interp_value(
+    &mut self,
+    raw: RawValue,
+    ty: &Type,
+    allocations: &BTreeMap<usize, Allocation>,
+  ) -> Result<Value, InterpError> {
+    Ok(unsafe {
+      match ty {
+        Type::Int => Value::Int(raw.int),
+        Type::Bool => Value::Bool(raw.bool),
+        Type::Float => Value::Float(raw.float),
+        Type::Char => char::from_u32(raw.char).map_or(Value::Uninitialized, Value::Char),
+        Type::Pointer(elem) => self.interp_ptr(raw.ptr as usize, elem, allocations)?,
+        Type::Any => unreachable!(),
+      }
+    })
+  }
+
+  fn interp_ptr(
+    &mut self,
+    addr: usize,
+    elem: &Type,
+    allocations: &BTreeMap<usize, Allocation>,
+  ) -> Result<Value, InterpError> {
+    // Null pointers were never initialized
+    if addr == 0 {
+      return Ok(Value::Uninitialized);
+    }
+    // Other pointers that are not into any allocation dangle, like ones that native code kept
+    // after freeing their memory
+    let Some((&base, allocation)) = allocations
+      .range(..=addr)
+      .next_back()
+      .filter(|(base, allocation)| addr <= *base + allocation.size)
+    else {
+      return self.dead_ptr();
+    };
+    let Ok(offset) = ((addr - base) / allocation.elem_size).try_into() else {
+      return Ok(Value::Uninitialized);
+    };
+    let index = if let Some(&index) = self.entries.get(&allocation.index) {
+      index
+    } else {
+      let Value::Pointer(ptr) = self
+        .heap
+        .alloc((allocation.size / allocation.elem_size).try_into().unwrap())?
+      else {
+        unreachable!()
+      };
+      self.native.insert(ptr.index, (*allocation, elem.clone()));
+      self.entries.insert(allocation.index, ptr.index);
+      self.pending.push(ptr.index);
+      ptr.index
+    };
+    Ok(Value::Pointer(Pointer {
+      index,
+      generation: self.heap.memory[index as usize].generation,
+      offset,
+    }))
+  }
+
+  // Makes a pointer to a heap entry that has already been freed, which the interpreter reports
+  // errors for using like any other dangling pointer
+  fn dead_ptr(&mut self) -> Result<Value, InterpError> {
+    let Value::Pointer(ptr) = self.heap.alloc(1)? else {
+      unreachable!()
+    };
+    self.heap.free(ptr)?;
+    Ok(Value::Pointer(ptr))
+  }
+
+  fn size(&self, ty: &Type) -> usize {
+    self.jit.value_size(ty)
+  }
+
+  // Frees all native memory
+  fn discard(self) {
+    for a in self.jit.allocations() {
+      unsafe { self.jit.free(a.ptr) };
+    }
+  }
+}
+
+// Writes a value of type `ty` to native memory
+unsafe fn write(ptr: *mut u8, val: RawValue, ty: &Type) {
+  unsafe {
+    match ty {
+      Type::Int => ptr.cast::<i64>().write_unaligned(val.int),
+      Type::Bool => ptr.cast::<bool>().write_unaligned(val.bool),
+      Type::Float => ptr.cast::<f64>().write_unaligned(val.float),
+      Type::Char => ptr.cast::<u32>().write_unaligned(val.char),
+      Type::Pointer(_) => ptr.cast::<*mut u8>().write_unaligned(val.ptr),
+      Type::Any => unreachable!(),
+    }
+  }
+}
+
+// Reads a value of type `ty` from native memory
+unsafe fn read(ptr: *mut u8, ty: &Type) -> RawValue {
+  unsafe {
+    match ty {
+      Type::Int => RawValue {
+        int: ptr.cast::<i64>().read_unaligned(),
+      },
+      Type::Bool => RawValue {
+        bool: ptr.read() != 0,
+      },
+      Type::Float => RawValue {
+        float: ptr.cast::<f64>().read_unaligned(),
+      },
+      Type::Char => RawValue {
+        char: ptr.cast::<u32>().read_unaligned(),
+      },
+      Type::Pointer(_) => RawValue {
+        ptr: ptr.cast::<*mut u8>().read_unaligned(),
+      },
+      Type::Any => unreachable!(),
+    }
+  }
+}
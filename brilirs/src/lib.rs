@@ -36,13 +36,28 @@ pub fn run_input<T: std::io::Write, U: std::io::Write>(
   //      - bril_rs takes file.json as input
   //      - bril2json takes file.bril as input
   let prog: Program = if cli_args.text {
-    bril2json::parse_abstract_program_from_read(input, true, true, cli_args.file).try_into()?
+    bril2json::parse_abstract_program_from_read(input, true, true, cli_args.file)
+      .try_into()?
   } else {
     bril_rs::load_abstract_program_from_read(input).try_into()?
   };
   check::type_check(&prog)?;
   let bbprog: BBProgram = prog.try_into()?;
 
+  #[cfg(feature = "jit")]
+  if let Some(threshold) = cli_args.jit
+    && !cli_args.check
+  {
+    return interp::execute_main_tiered(
+      &bbprog,
+      out,
+      &cli_args.args,
+      cli_args.profile,
+      profiling_out,
+      threshold,
+    );
+  }
+
   if !cli_args.check {
     interp::execute_main(
       &bbprog,
@@ -58,6 +58,43 @@ pub fn flatten_instr_array_fields(
     var_idxes
 }
 
+/// Converts the JSON `value` field of a `const` instruction with type `ty`
+/// to a `BrilValue` (returns `None` if the instruction has no `value` field)
+/// - Chars are represented in JSON as strings containing exactly one char
+pub fn flatten_value(
+    value_json: &serde_json::Value,
+    ty: Option<Type>,
+) -> Option<BrilValue> {
+    match ty {
+        Some(Type::Float) => value_json.as_f64().map(BrilValue::FloatVal),
+        Some(Type::Char) => value_json.as_str().map(|s| {
+            let mut chars = s.chars();
+            let c = chars.next().expect("char constant is empty");
+            assert!(
+                chars.next().is_none(),
+                "char constant has more than one char"
+            );
+            BrilValue::CharVal(c.into())
+        }),
+        _ => {
+            if let Some(int_value) = value_json.as_i64() {
+                Some(BrilValue::IntVal(int_value))
+            } else {
+                value_json.as_bool().map(|b| BrilValue::BoolVal(b.into()))
+            }
+        }
+    }
+}
+
+/// Converts a JSON source position (the `pos` / `pos_end` fields that
+/// `bril2json -p` emits) to a `(row, col)` pair
+/// (returns `None` if the instruction has no such field)
+pub fn flatten_pos(pos: &serde_json::Value) -> Option<(u32, u32)> {
+    let row = pos["row"].as_u64()?;
+    let col = pos["col"].as_u64()?;
+    Some((row as u32, col as u32))
+}
+
 /// Takes in a JSON function representing one single Bril function,
 /// and returns a vector containing the flattened instructions in the function
 /// (in the same order)
@@ -159,8 +196,12 @@ pub fn flatten_instrs(func_json: &serde_json::Value) -> InstrStore {
 
             // Obtain the start/end indexes into the all_args_idxes Vec
             // (used to populate the `args` field of the `Instr` struct)
+            // An empty `args` list means the same as a missing one,
+            // so both are represented as `None`
             let mut arg_idxes = None;
-            if let Some(args_json_vec) = instr["args"].as_array() {
+            if let Some(args_json_vec) =
+                instr["args"].as_array().filter(|v| !v.is_empty())
+            {
                 let (start_idx, end_idx) = flatten_instr_array_fields(
                     args_json_vec,
                     &mut all_args_idxes,
@@ -188,16 +229,15 @@ pub fn flatten_instrs(func_json: &serde_json::Value) -> InstrStore {
             }
 
             // Populate the `value` field of the `Instr` struct
-            let mut value = None;
-            if let Some(int_value) = instr["value"].as_i64() {
-                value = Some(BrilValue::IntVal(int_value));
-            } else if let Some(b) = instr["value"].as_bool() {
-                value = Some(BrilValue::BoolVal(b.into()));
-            }
+            // (we need the type of the instr to tell floats that happen to be
+            // integral, e.g. `1`, apart from ints)
+            let value = flatten_value(&instr["value"], ty);
 
             // Populate the `labels` field of the `Instr` struct
             let mut labels_idxes = None;
-            if let Some(labels_json_vec) = instr["labels"].as_array() {
+            if let Some(labels_json_vec) =
+                instr["labels"].as_array().filter(|v| !v.is_empty())
+            {
                 let (start_idx, end_idx) = flatten_instr_array_fields(
                     labels_json_vec,
                     &mut all_labels_idxes,
@@ -207,7 +247,8 @@ pub fn flatten_instrs(func_json: &serde_json::Value) -> InstrStore {
             }
 
             // Handle `func` field in `Instr` struct
-            // Because we only handle core Bril we assume only one func is referenced
+            // `call` is the only instruction with a `funcs` field,
+            // so we assume only one func is referenced
             let mut func_idx = None;
             if let Some(funcs_json_vec) = instr["funcs"].as_array() {
                 let funcs_vec: Vec<&[u8]> = funcs_json_vec
@@ -232,6 +273,8 @@ pub fn flatten_instrs(func_json: &serde_json::Value) -> InstrStore {
                 instr_labels: labels_idxes,
                 value,
                 funcs: func_idx,
+                pos: flatten_pos(&instr["pos"]),
+                pos_end: flatten_pos(&instr["pos_end"]),
             };
             all_instrs_labels.push(instr);
         }
@@ -241,6 +284,8 @@ pub fn flatten_instrs(func_json: &serde_json::Value) -> InstrStore {
         func_name: func_name_bytes,
         func_args,
         func_ret_ty,
+        func_pos: flatten_pos(&func_json["pos"]),
+        func_pos_end: flatten_pos(&func_json["pos_end"]),
         var_store: all_vars,
         args_idxes_store: all_args_idxes,
         labels_idxes_store: all_labels_idxes,
@@ -260,7 +305,7 @@ mod flatten_tests {
     use std::io;
     use std::{fs, fs::File, io::BufReader};
 
-    use crate::types::{InstrStore, Opcode};
+    use crate::types::{InstrStore, Opcode, PrimType, Type};
 
     // We use `strum` to iterate over every variant in the `Opcode` enum easily
     use strum::IntoEnumIterator;
@@ -292,6 +337,30 @@ mod flatten_tests {
         }
     }
 
+    /// Checks that types (including nested pointer types) survive a
+    /// JSON -> `Type` -> JSON round trip
+    #[test]
+    fn test_type_json_round_trip() {
+        let types = [
+            serde_json::json!("int"),
+            serde_json::json!("char"),
+            serde_json::json!({ "ptr": "float" }),
+            serde_json::json!({ "ptr": { "ptr": "bool" } }),
+        ];
+        for json in types {
+            let ty: Type = serde_json::from_value(json.clone())
+                .expect("trouble deserializing type");
+            assert_eq!(ty.to_json(), json);
+        }
+
+        let ty: Type = serde_json::from_value(
+            serde_json::json!({ "ptr": { "ptr": "int" } }),
+        )
+        .unwrap();
+        assert_eq!(ty, Type::Ptr(PrimType::Int, 2));
+        assert_eq!(ty.pointee(), Some(Type::Ptr(PrimType::Int, 1)));
+    }
+
     /// Test that for each JSON file in the `test` directory,
     /// its flattened presentation is well-formed
     /// (i.e. for pairs of indices, the end index is always >= the start index)
@@ -1,13 +1,240 @@
 #![allow(unused_variables)]
-use core::panic;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
 use std::str;
 
+use thiserror::Error;
+
 use crate::types::*;
 
+/* -------------------------------------------------------------------------- */
+/*                                   Errors                                   */
+/* -------------------------------------------------------------------------- */
+
+/// Errors that can occur while interpreting a flat Bril program
+/// (the messages are the same as the ones `brilirs` reports)
+#[derive(Debug, Error)]
+pub enum InterpError {
+    #[error("Attempt to divide by 0")]
+    DivisionByZero,
+    #[error(
+        "Some memory locations have not been freed by the end of execution"
+    )]
+    MemLeak,
+    #[error("Trying to load from uninitialized memory")]
+    UsingUninitializedMemory,
+    #[error("Could not find label: {0}")]
+    MissingLabel(String),
+    #[error("no main function defined, doing nothing")]
+    NoMainFunction,
+    #[error("char must have one character")]
+    NotOneChar,
+    #[error("multiple functions of the same name found")]
+    DuplicateFunction,
+    #[error("duplicate label `{0}` found")]
+    DuplicateLabel(String),
+    #[error("Expected empty return for `{0}`, found value")]
+    NonEmptyRetForFunc(String),
+    #[error("non-void function (type {0}) didn't return anything")]
+    NonVoidFuncNoRet(bril_rs::Type),
+    #[error("cannot allocate `{0}` entries")]
+    CannotAllocSize(i64),
+    #[error(
+        "Tried to free illegal memory location index: `{0}`, offset: `{1}`. Offset must be 0."
+    )]
+    IllegalFree(usize, i64), // (index, offset)
+    #[error("Uninitialized heap location `{0}` and/or illegal offset `{1}`")]
+    InvalidMemoryAccess(usize, i64), // (index, offset)
+    #[error("Expected `{0}` function arguments, found `{1}`")]
+    BadNumFuncArgs(usize, usize), // (expected, actual)
+    #[error("Expected `{0}` instruction arguments, found `{1}`")]
+    BadNumArgs(usize, usize), // (expected, actual)
+    #[error("Expected `{0}` labels, found `{1}`")]
+    BadNumLabels(usize, usize), // (expected, actual)
+    #[error("no function of name `{0}` found")]
+    FuncNotFound(String),
+    #[error("undefined variable `{0}`")]
+    VarUndefined(String),
+    #[error("get without corresponding set")]
+    GetWithoutSet,
+    #[error("unspecified pointer type `{0:?}`")]
+    ExpectedPointerType(bril_rs::Type), // found type
+    #[error("Expected type `{0:?}` for function argument, found `{1:?}`")]
+    BadFuncArgType(bril_rs::Type, String), // (expected, actual)
+    #[error("Expected type `{0:?}` for assignment, found `{1:?}`")]
+    BadAsmtType(bril_rs::Type, bril_rs::Type), // (expected, actual)
+    #[error("There has been an io error: `{0:?}`")]
+    IoError(#[from] std::io::Error),
+    #[error("value {0} cannot be converted to char")]
+    ToCharError(i64),
+}
+
+impl InterpError {
+    /// Attaches the source position `pos` (and `pos_end`, if the instruction
+    /// has one) to the error
+    pub fn add_pos(
+        self,
+        pos: I32Pair,
+        pos_end: I32Pair,
+    ) -> PositionalInterpError {
+        PositionalInterpError {
+            e: self,
+            pos: pos.into(),
+            pos_end: pos_end.into(),
+        }
+    }
+}
+
+/// An `InterpError`, along with the `(row, col)` source position of the
+/// instruction (or function) that caused it
+/// - The positions are only known if the program was converted to JSON
+///   with `bril2json -p`, and are printed the same way as `brilirs` does
+#[derive(Debug, Error)]
+pub struct PositionalInterpError {
+    pub e: InterpError,
+    pub pos: Option<(u32, u32)>,
+    pub pos_end: Option<(u32, u32)>,
+}
+
+impl std::fmt::Display for PositionalInterpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let e = &self.e;
+        match (self.pos, self.pos_end) {
+            (Some((row, col)), Some((end_row, end_col))) => write!(
+                f,
+                "Line {row}, Column {col} to Line {end_row}, Column {end_col}: {e}"
+            ),
+            (Some((row, col)), None) => {
+                write!(f, "Line {row}, Column {col}: {e}")
+            }
+            (None, _) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<InterpError> for PositionalInterpError {
+    fn from(e: InterpError) -> Self {
+        PositionalInterpError {
+            e,
+            pos: None,
+            pos_end: None,
+        }
+    }
+}
+
+/// Checks that `value` has the type `expected`
+fn check_type(expected: Type, value: &BrilValue) -> Result<(), InterpError> {
+    let actual = value.get_type();
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(InterpError::BadAsmtType(expected.into(), actual.into()))
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                    State                                   */
+/* -------------------------------------------------------------------------- */
+
 // An environment maps variable names (`&str`s) to values
 pub type Environment<'a> = HashMap<&'a str, BrilValue>;
 
+/// The heap used by the memory extension:
+/// - Each allocation is a vector of values, where `None` means that
+///   the location hasn't been initialized yet
+/// - Freed allocations are replaced with `None`
+#[derive(Debug, Default)]
+pub struct Heap {
+    allocs: Vec<Option<Vec<Option<BrilValue>>>>,
+}
+
+impl Heap {
+    /// Allocates `size` uninitialized locations, and returns a pointer
+    /// (with the pointer type `ty`) to the start of the allocation
+    pub fn alloc(
+        &mut self,
+        size: i64,
+        ty: Type,
+    ) -> Result<BrilValue, InterpError> {
+        let size = usize::try_from(size)
+            .map_err(|_| InterpError::CannotAllocSize(size))?;
+        self.allocs.push(Some(vec![None; size]));
+        Ok(BrilValue::PtrVal(Pointer {
+            alloc: self.allocs.len() - 1,
+            offset: 0,
+            ty,
+        }))
+    }
+
+    /// Frees the allocation that `ptr` points to the start of
+    pub fn free(&mut self, ptr: Pointer) -> Result<(), InterpError> {
+        match self.allocs.get_mut(ptr.alloc) {
+            Some(alloc @ Some(_)) if ptr.offset == 0 => {
+                *alloc = None;
+                Ok(())
+            }
+            _ => Err(InterpError::IllegalFree(ptr.alloc, ptr.offset)),
+        }
+    }
+
+    /// Returns a mutable reference to the location that `ptr` points to
+    /// (errors if `ptr` doesn't point into a live allocation)
+    fn location(
+        &mut self,
+        ptr: Pointer,
+    ) -> Result<&mut Option<BrilValue>, InterpError> {
+        let offset = usize::try_from(ptr.offset).ok();
+        self.allocs
+            .get_mut(ptr.alloc)
+            .and_then(Option::as_mut)
+            .zip(offset)
+            .and_then(|(alloc, offset)| alloc.get_mut(offset))
+            .ok_or(InterpError::InvalidMemoryAccess(ptr.alloc, ptr.offset))
+    }
+
+    /// Reads the value stored at the location `ptr` points to
+    pub fn load(&mut self, ptr: Pointer) -> Result<BrilValue, InterpError> {
+        self.location(ptr)?
+            .ok_or(InterpError::UsingUninitializedMemory)
+    }
+
+    /// Writes `value` to the location `ptr` points to
+    pub fn store(
+        &mut self,
+        ptr: Pointer,
+        value: BrilValue,
+    ) -> Result<(), InterpError> {
+        *self.location(ptr)? = Some(value);
+        Ok(())
+    }
+
+    /// Determines if every allocation has been freed
+    pub fn is_empty(&self) -> bool {
+        self.allocs.iter().all(Option::is_none)
+    }
+}
+
+/// State shared by all the functions in a program while it's being interpreted
+/// - `funcs` maps the name of each function to its `InstrView`
+/// - `heap` is the heap used by the memory extension
+/// - `out` is the writer that `print` instructions write to
+/// - `instruction_count` is the no. of instructions executed so far
+///   (labels aren't counted)
+/// - `pos` and `pos_end` are the source position of the instruction
+///   being executed, which is attached to any error it causes
+pub struct ProgramState<'a, W: Write> {
+    pub funcs: HashMap<&'a str, &'a InstrView<'a>>,
+    pub heap: Heap,
+    pub out: W,
+    pub instruction_count: usize,
+    pub pos: I32Pair,
+    pub pos_end: I32Pair,
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                 Extractors                                 */
+/* -------------------------------------------------------------------------- */
+
 /// Extracts the variable name (string) that occupies `start_idx` to `end_idx`
 /// (inclusive) in `instr_view.var_store`
 pub fn get_var<'a>(
@@ -40,6 +267,63 @@ pub fn get_args<'a>(
         .collect()
 }
 
+/// Extracts the args of `instr` (which is empty if `instr` has no args)
+pub fn get_instr_args<'a>(
+    instr_view: &'a InstrView,
+    instr: &FlatInstr,
+) -> Vec<&'a str> {
+    match Option::<(u32, u32)>::from(instr.args) {
+        Some((args_start, args_end)) => {
+            get_args(instr_view, args_start, args_end)
+        }
+        None => vec![],
+    }
+}
+
+/// Extracts the args of `instr`, checking that there are exactly `expected`
+/// of them
+fn get_n_instr_args<'a>(
+    instr_view: &'a InstrView,
+    instr: &FlatInstr,
+    expected: usize,
+) -> Result<Vec<&'a str>, InterpError> {
+    let args = get_instr_args(instr_view, instr);
+    if args.len() == expected {
+        Ok(args)
+    } else {
+        Err(InterpError::BadNumArgs(expected, args.len()))
+    }
+}
+
+/// Extracts the destination variable of `instr`
+fn get_dest<'a>(instr_view: &'a InstrView, instr: &FlatInstr) -> &'a str {
+    let (dest_start, dest_end): (u32, u32) = instr.dest.into();
+    get_var(instr_view, dest_start, dest_end)
+}
+
+/// Looks up the value of the variable `arg` in `env`
+/// (errors if `arg` is undefined)
+fn get_value(env: &Environment, arg: &str) -> Result<BrilValue, InterpError> {
+    env.get(arg)
+        .copied()
+        .ok_or_else(|| InterpError::VarUndefined(arg.to_string()))
+}
+
+/// Extends `env` so that `dest |-> value`, checking that `value` has the
+/// type that `instr` declares for `dest`
+fn assign<'a>(
+    env: &mut Environment<'a>,
+    dest: &'a str,
+    instr: &FlatInstr,
+    value: BrilValue,
+) -> Result<(), InterpError> {
+    if let Ok(ty) = Type::try_from(instr.ty) {
+        check_type(ty, &value)?;
+    }
+    env.insert(dest, value);
+    Ok(())
+}
+
 /// Extracts the label name (string) that occupies `start_idx` to `end_idx`
 /// (inclusive) in `instr_view.labels_store`
 pub fn get_label_name<'a>(
@@ -73,6 +357,26 @@ pub fn get_labels_vec<'a>(
         .collect()
 }
 
+/// Extracts the labels of `instr` (a `jmp` or `br`), checking that there are
+/// exactly `expected` of them
+fn get_n_instr_labels<'a>(
+    instr_view: &'a InstrView,
+    instr: &FlatInstr,
+    expected: usize,
+) -> Result<Vec<&'a str>, InterpError> {
+    let labels = match Option::<(u32, u32)>::from(instr.instr_labels) {
+        Some((labels_start, labels_end)) => {
+            get_labels_vec(instr_view, labels_start, labels_end)
+        }
+        None => vec![],
+    };
+    if labels.len() == expected {
+        Ok(labels)
+    } else {
+        Err(InterpError::BadNumLabels(expected, labels.len()))
+    }
+}
+
 /// Extracts the function name (string) that occupies `start_idx` to `end_idx`
 /// (inclusive) in `instr_view.funcs_store`
 pub fn get_func<'a>(
@@ -86,6 +390,14 @@ pub fn get_func<'a>(
         .expect("invalid utf-8")
 }
 
+/// Extracts the name of the function that `instr_view` stores
+/// (without the null terminators that pad it)
+pub fn get_func_name<'a>(instr_view: &'a InstrView) -> &'a str {
+    str::from_utf8(instr_view.func_name)
+        .expect("invalid utf-8")
+        .trim_end_matches(char::from(0))
+}
+
 /// Returns the PC (index in the list of instrs) corresponding to a label
 /// as an `Option`. (Returns `None` if no such PC exists.)
 pub fn get_pc_of_label(
@@ -109,40 +421,177 @@ pub fn get_pc_of_label(
     })
 }
 
-/// Interprets a unary value operation (`not` and `id`)
+/// Like `get_pc_of_label`, but errors if there's no such label
+fn find_label(
+    instr_view: &InstrView,
+    label_str: &str,
+) -> Result<usize, InterpError> {
+    get_pc_of_label(instr_view, label_str)
+        .ok_or_else(|| InterpError::MissingLabel(label_str.to_string()))
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                 Interpreter                                */
+/* -------------------------------------------------------------------------- */
+
+/// The type of the operands of a unary/binary value operation
+/// (for `ptradd`, this is the type of the offset)
+fn operand_type(op: Opcode) -> Type {
+    use Opcode::*;
+    match op {
+        And | Or | Not => Type::Bool,
+        Fadd | Fsub | Fmul | Fdiv | Feq | Flt | Fgt | Fle | Fge
+        | Float2Bits => Type::Float,
+        Ceq | Clt | Cgt | Cle | Cge | Char2int => Type::Char,
+        _ => Type::Int,
+    }
+}
+
+/// Returns the error for a value operation whose `args` are ill-typed
+fn ill_typed_args(op: Opcode, args: &[BrilValue]) -> InterpError {
+    if let (Opcode::PtrAdd, Some(ptr)) = (op, args.first())
+        && !matches!(ptr, BrilValue::PtrVal(_))
+    {
+        return InterpError::ExpectedPointerType(ptr.get_type().into());
+    }
+    let expected = operand_type(op);
+    let start = usize::from(op == Opcode::PtrAdd);
+    args[start..]
+        .iter()
+        .find_map(|arg| check_type(expected, arg).err())
+        .expect("value operation is well-typed")
+}
+
+/// Evaluates a unary value operation (`not` and `id`, and the conversion
+/// operations from the char and bitcast extensions) on the argument `value`
 /// (panics if `op` is not an unop)
+pub fn eval_unop(
+    op: Opcode,
+    value: BrilValue,
+) -> Result<BrilValue, InterpError> {
+    if !op.is_unop() {
+        panic!("eval_unop called on a non-unary value operation");
+    }
+
+    match (op, value) {
+        (Opcode::Not, BrilValue::BoolVal(b)) => {
+            let b = bool::from(b);
+            Ok(BrilValue::BoolVal((!b).into()))
+        }
+        (Opcode::Id, _) => Ok(value),
+        (Opcode::Char2int, BrilValue::CharVal(c)) => {
+            Ok(BrilValue::IntVal(u32::from(char::from(c)).into()))
+        }
+        (Opcode::Int2char, BrilValue::IntVal(i)) => {
+            let c = u32::try_from(i)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or(InterpError::ToCharError(i))?;
+            Ok(BrilValue::CharVal(c.into()))
+        }
+        (Opcode::Float2Bits, BrilValue::FloatVal(f)) => {
+            Ok(BrilValue::IntVal(i64::from_ne_bytes(f.to_ne_bytes())))
+        }
+        (Opcode::Bits2Float, BrilValue::IntVal(i)) => {
+            Ok(BrilValue::FloatVal(f64::from_ne_bytes(i.to_ne_bytes())))
+        }
+        _ => Err(ill_typed_args(op, &[value])),
+    }
+}
+
+/// Interprets a unary value operation (panics if `op` is not an unop)
 pub fn interp_unop<'a>(
     instr_view: &'a InstrView,
     op: Opcode,
     instr: &FlatInstr,
     env: &mut Environment<'a>,
-) {
-    if !op.is_unop() {
-        panic!("interp_unop called on a non-unary value operation");
+) -> Result<(), InterpError> {
+    let dest = get_dest(instr_view, instr);
+    let args = get_n_instr_args(instr_view, instr, 1)?;
+    let arg = args[0];
+
+    // Copying a variable that was made undefined by `undef` (SSA extension)
+    // makes `dest` undefined too
+    if op == Opcode::Id && !env.contains_key(arg) {
+        env.remove(dest);
+        return Ok(());
     }
 
-    let (dest_start, dest_end): (u32, u32) = instr.dest.into();
-    let dest = get_var(instr_view, dest_start, dest_end);
-    let (args_start, args_end): (u32, u32) = instr.args.into();
-    let args = get_args(instr_view, args_start, args_end);
-    assert!(
-        args.len() == 1,
-        "unary instruction is malformed (no. of args != 1)"
-    );
+    let result = eval_unop(op, get_value(env, arg)?)?;
+    assign(env, dest, instr, result)
+}
 
-    let arg = args[0];
-    let value = env.get(arg).expect("arg missing from env");
-    let result = match (op, value) {
-        (Opcode::Not, BrilValue::BoolVal(b)) => {
-            let b = bool::from(*b);
-            BrilValue::BoolVal((!b).into())
+/// Evaluates a binary value operation on the arguments `x` and `y`
+/// (panics if `op` is not a binop)
+pub fn eval_binop(
+    op: Opcode,
+    x: BrilValue,
+    y: BrilValue,
+) -> Result<BrilValue, InterpError> {
+    use BrilValue::*;
+    use Opcode::*;
+
+    if !op.is_binop() {
+        panic!("eval_binop called on a non-binary value operation");
+    }
+
+    let value = match (op, x, y) {
+        // Arithmetic
+        (Add, IntVal(v1), IntVal(v2)) => IntVal(v1.wrapping_add(v2)),
+        (Sub, IntVal(v1), IntVal(v2)) => IntVal(v1.wrapping_sub(v2)),
+        (Mul, IntVal(v1), IntVal(v2)) => IntVal(v1.wrapping_mul(v2)),
+        (Div, IntVal(_), IntVal(0)) => {
+            return Err(InterpError::DivisionByZero);
+        }
+        (Div, IntVal(v1), IntVal(v2)) => IntVal(v1.wrapping_div(v2)),
+        // Comparison
+        (Eq, IntVal(v1), IntVal(v2)) => BoolVal((v1 == v2).into()),
+        (Ge, IntVal(v1), IntVal(v2)) => BoolVal((v1 >= v2).into()),
+        (Gt, IntVal(v1), IntVal(v2)) => BoolVal((v1 > v2).into()),
+        (Le, IntVal(v1), IntVal(v2)) => BoolVal((v1 <= v2).into()),
+        (Lt, IntVal(v1), IntVal(v2)) => BoolVal((v1 < v2).into()),
+        // Logic
+        (And, BoolVal(b1), BoolVal(b2)) => {
+            BoolVal((bool::from(b1) && bool::from(b2)).into())
+        }
+        (Or, BoolVal(b1), BoolVal(b2)) => {
+            BoolVal((bool::from(b1) || bool::from(b2)).into())
         }
-        (Opcode::Id, _) => *value,
-        _ => {
-            panic!("argument to unary instruction is ill-typed");
+        // Floating-point arithmetic
+        (Fadd, FloatVal(v1), FloatVal(v2)) => FloatVal(v1 + v2),
+        (Fsub, FloatVal(v1), FloatVal(v2)) => FloatVal(v1 - v2),
+        (Fmul, FloatVal(v1), FloatVal(v2)) => FloatVal(v1 * v2),
+        (Fdiv, FloatVal(v1), FloatVal(v2)) => FloatVal(v1 / v2),
+        // Floating-point comparison
+        (Feq, FloatVal(v1), FloatVal(v2)) => BoolVal((v1 == v2).into()),
+        (Fge, FloatVal(v1), FloatVal(v2)) => BoolVal((v1 >= v2).into()),
+        (Fgt, FloatVal(v1), FloatVal(v2)) => BoolVal((v1 > v2).into()),
+        (Fle, FloatVal(v1), FloatVal(v2)) => BoolVal((v1 <= v2).into()),
+        (Flt, FloatVal(v1), FloatVal(v2)) => BoolVal((v1 < v2).into()),
+        // Character comparison
+        (Ceq, CharVal(c1), CharVal(c2)) => {
+            BoolVal((char::from(c1) == char::from(c2)).into())
         }
+        (Cge, CharVal(c1), CharVal(c2)) => {
+            BoolVal((char::from(c1) >= char::from(c2)).into())
+        }
+        (Cgt, CharVal(c1), CharVal(c2)) => {
+            BoolVal((char::from(c1) > char::from(c2)).into())
+        }
+        (Cle, CharVal(c1), CharVal(c2)) => {
+            BoolVal((char::from(c1) <= char::from(c2)).into())
+        }
+        (Clt, CharVal(c1), CharVal(c2)) => {
+            BoolVal((char::from(c1) < char::from(c2)).into())
+        }
+        // Pointer arithmetic
+        (PtrAdd, PtrVal(ptr), IntVal(offset)) => PtrVal(Pointer {
+            offset: ptr.offset.wrapping_add(offset),
+            ..ptr
+        }),
+        _ => return Err(ill_typed_args(op, &[x, y])),
     };
-    env.insert(dest, result);
+    Ok(value)
 }
 
 /// Interprets a binary value operation (panics if `op` is not a binop)
@@ -151,168 +600,164 @@ pub fn interp_binop<'a>(
     op: Opcode,
     instr: &FlatInstr,
     env: &mut Environment<'a>,
-) {
-    use BrilValue::*;
-    use Opcode::*;
+) -> Result<(), InterpError> {
+    let dest = get_dest(instr_view, instr);
+    let args = get_n_instr_args(instr_view, instr, 2)?;
+    let x = get_value(env, args[0])?;
+    let y = get_value(env, args[1])?;
+    let value = eval_binop(op, x, y)?;
+    assign(env, dest, instr, value)
+}
 
-    if !op.is_binop() {
-        panic!("interp_binop called on a non-binary value operation");
-    }
+/// Interprets a memory operation that accesses the heap
+/// (`alloc`, `load`, `store` and `free`)
+pub fn interp_mem_op<'a>(
+    instr_view: &'a InstrView,
+    op: Opcode,
+    instr: &FlatInstr,
+    env: &mut Environment<'a>,
+    heap: &mut Heap,
+) -> Result<(), InterpError> {
+    let num_args = if op == Opcode::Store { 2 } else { 1 };
+    let args = get_n_instr_args(instr_view, instr, num_args)?;
+    let arg_values = args
+        .iter()
+        .map(|arg| get_value(env, arg))
+        .collect::<Result<Vec<BrilValue>, InterpError>>()?;
 
-    let (dest_start, dest_end): (u32, u32) = instr.dest.into();
-    let dest = get_var(instr_view, dest_start, dest_end);
-
-    let (args_start, args_end): (u32, u32) = instr.args.into();
-    let args = get_args(instr_view, args_start, args_end);
-    assert!(args.len() == 2, "no. of args to arithmetic op != 2");
-
-    let x = env.get(args[0]).expect("left operand missing from env");
-    let y = env.get(args[1]).expect("right operand missing from env");
-
-    match (x, y) {
-        (IntVal(v1), IntVal(v2)) => {
-            let value = match op {
-                // Arithmetic
-                Add => IntVal(v1.wrapping_add(*v2)),
-                Sub => IntVal(v1.wrapping_sub(*v2)),
-                Mul => IntVal(v1.wrapping_mul(*v2)),
-                Div => IntVal(v1.wrapping_div(*v2)),
-                // Comparison
-                Eq => BoolVal((v1 == v2).into()),
-                Ge => BoolVal((v1 >= v2).into()),
-                Gt => BoolVal((v1 > v2).into()),
-                Le => BoolVal((v1 <= v2).into()),
-                Lt => BoolVal((v1 < v2).into()),
-                _ => unreachable!(),
-            };
-
-            env.insert(dest, value);
-        }
-        (BoolVal(b1), BoolVal(b2)) => {
-            let b1 = bool::from(*b1);
-            let b2 = bool::from(*b2);
-            // Logic
-            let value = match op {
-                And => BoolVal((b1 && b2).into()),
-                Or => BoolVal((b1 || b2).into()),
-                _ => unreachable!(),
-            };
-            env.insert(dest, value);
-        }
-        (_, _) => {
-            panic!("operands to binop are ill-typed")
+    match (op, arg_values.as_slice()) {
+        (Opcode::Alloc, [BrilValue::IntVal(size)]) => {
+            let dest = get_dest(instr_view, instr);
+            let ty = Type::try_from(instr.ty)
+                .expect("alloc instruction is missing a type");
+            let ptr = heap.alloc(*size, ty)?;
+            assign(env, dest, instr, ptr)
+        }
+        (Opcode::Load, [BrilValue::PtrVal(ptr)]) => {
+            let dest = get_dest(instr_view, instr);
+            let value = heap.load(*ptr)?;
+            assign(env, dest, instr, value)
+        }
+        (Opcode::Store, [BrilValue::PtrVal(ptr), value]) => {
+            let pointee =
+                ptr.ty.pointee().expect("pointer has a non-pointer type");
+            check_type(pointee, value)?;
+            heap.store(*ptr, *value)
         }
+        (Opcode::Free, [BrilValue::PtrVal(ptr)]) => heap.free(*ptr),
+        (Opcode::Alloc, [size]) => {
+            Err(check_type(Type::Int, size)
+                .expect_err("alloc size is well-typed"))
+        }
+        (_, [ptr, ..]) => {
+            Err(InterpError::ExpectedPointerType(ptr.get_type().into()))
+        }
+        _ => unreachable!(),
     }
 }
 
 /// Interprets a function call
-pub fn interp_call<'a>(
+pub fn interp_call<'a, W: Write>(
     instr_view: &'a InstrView,
     env: &mut Environment<'a>,
-    funcs: &HashMap<&str, &InstrView>,
+    state: &mut ProgramState<'a, W>,
     instr: &FlatInstr,
     instr_kind: InstrKind,
-) {
+) -> Result<(), InterpError> {
     let (funcs_start, funcs_end): (u32, u32) = instr.funcs.into();
     let func_name = get_func(instr_view, funcs_start, funcs_end);
 
-    let call_view = funcs
+    let call_view: &'a InstrView = state
+        .funcs
         .get(func_name)
-        .expect("func_name missing from funcs hashmap");
+        .ok_or_else(|| InterpError::FuncNotFound(func_name.to_string()))?;
+
+    // Args supplied to call instruction
+    let args = get_instr_args(instr_view, instr);
+    if args.len() != call_view.func_args.len() {
+        return Err(InterpError::BadNumArgs(
+            call_view.func_args.len(),
+            args.len(),
+        ));
+    }
 
     let mut fresh_env = Environment::new();
-    // No args supplied to function call, just interpret the callee
-    if instr.args.first == -1 && instr.args.second == -1 {
-        let possible_return_value =
-            interp_instr_view(call_view, &mut fresh_env, funcs)
-                .expect("error encountered when interpreting instr_view");
-        match instr_kind {
-            InstrKind::ValueOp => {
-                // Call function
-                let ret_value = possible_return_value
-                    .expect("missing return value for Call ValueOp");
-                let (dest_start, dest_end): (u32, u32) = instr.dest.into();
-                let dest_var = get_var(instr_view, dest_start, dest_end);
-                env.insert(dest_var, ret_value);
-            }
-            InstrKind::EffectOp => {
-                // There's no dest if it's an effect-op, so we're done
-            }
-            _ => unreachable!(),
-        }
-    } else {
-        // The function call has args supplied to it
-        let instr_args = instr.args;
-
-        let (args_start, args_end): (u32, u32) = instr.args.into();
-
-        // Args supplied to call instruction
-        let args = get_args(instr_view, args_start, args_end);
-
-        let args_values: Vec<&BrilValue> = args
-            .into_iter()
-            .map(|a| env.get(a).expect("arg missing from env"))
-            .collect();
-
-        for (flat_arg, arg_value) in call_view.func_args.iter().zip(args_values)
-        {
-            // Check typing
-            let (start_idx, end_idx): (u32, u32) =
-                flat_arg.arg_name_idxes.into();
-
-            // Function args
-            let arg_name = get_var(call_view, start_idx, end_idx);
-
-            let desired_arg_type: FlatType = flat_arg.arg_type;
-            let actual_arg_type: FlatType = arg_value.get_type().into();
-            match (desired_arg_type, actual_arg_type) {
-                (FlatType::Int, FlatType::Int)
-                | (FlatType::Bool, FlatType::Bool) => {
-                    // Function arg is well-typed, extend the env with the arg_value
-                    fresh_env.insert(arg_name, *arg_value);
-                }
-                (FlatType::Null, _) | (_, FlatType::Null) => {
-                    panic!("encountered null type for function argument");
-                }
-                (_, _) => {
-                    panic!(
-                        "Type of supplied argument doesn't match expected type of function argument"
-                    );
-                }
-            }
+    for (flat_arg, arg) in call_view.func_args.iter().zip(args) {
+        let (start_idx, end_idx): (u32, u32) = flat_arg.arg_name_idxes.into();
+
+        // Function args
+        let arg_name = get_var(call_view, start_idx, end_idx);
+
+        // Check typing (`brilirs` reports the type of the value first)
+        let arg_value = get_value(env, arg)?;
+        let desired_arg_type = Type::try_from(flat_arg.arg_type)
+            .expect("encountered null type for function argument");
+        if arg_value.get_type() != desired_arg_type {
+            return Err(InterpError::BadAsmtType(
+                arg_value.get_type().into(),
+                desired_arg_type.into(),
+            ));
         }
 
-        // Call function
-        match instr_kind {
-            InstrKind::ValueOp => {
-                // Call function
-                let ret_value =
-                    interp_instr_view(call_view, &mut fresh_env, funcs)
-                        .expect("error interpreting function call");
-                let (dest_start, dest_end): (u32, u32) = instr.dest.into();
-                let dest_var = get_var(instr_view, dest_start, dest_end);
-                env.insert(dest_var, ret_value.expect("missing return value"));
-            }
-            InstrKind::EffectOp => {
-                // There is no return value, so we can just ignore the result
-                // of `inerp_instr_view`
-                interp_instr_view(call_view, &mut fresh_env, funcs)
-                    .expect("error interpreting function call");
+        // Function arg is well-typed, extend the env with the arg_value
+        fresh_env.insert(arg_name, arg_value);
+    }
 
-                // There's no dest if it's an effect-op, so we're done
+    // A value call's dest must have the callee's return type
+    if let InstrKind::ValueOp = instr_kind {
+        let dest_type = Type::try_from(instr.ty)
+            .expect("call instruction is missing a type");
+        match Option::<Type>::from(call_view.func_ret_ty) {
+            None => {
+                return Err(InterpError::NonEmptyRetForFunc(
+                    func_name.to_string(),
+                ));
             }
-            _ => unreachable!(),
+            Some(ret_type) if ret_type != dest_type => {
+                return Err(InterpError::BadAsmtType(
+                    dest_type.into(),
+                    ret_type.into(),
+                ));
+            }
+            Some(_) => {}
         }
     }
+
+    // Call function (any error in the callee is reported at the position
+    // of the callee's instruction, so we only restore the position of the
+    // call instruction once the callee returns)
+    let ret_value = interp_instr_view(call_view, &mut fresh_env, state)?;
+    state.pos = instr.pos;
+    state.pos_end = instr.pos_end;
+    match instr_kind {
+        InstrKind::ValueOp => {
+            // The callee is non-void, so it must have returned a value
+            let ret_value =
+                ret_value.expect("non-void function didn't return a value");
+            let dest_var = get_dest(instr_view, instr);
+            assign(env, dest_var, instr, ret_value)
+        }
+        InstrKind::EffectOp => {
+            // There's no dest if it's an effect-op, so we can just ignore
+            // the return value
+            Ok(())
+        }
+        _ => unreachable!(),
+    }
 }
 
 /// Interprets all the instructions in `instr_view` using the supplied `env`
-pub fn interp_instr_view<'a>(
+/// (`state` is shared by all functions in the program)
+pub fn interp_instr_view<'a, W: Write>(
     instr_view: &'a InstrView,
     env: &mut Environment<'a>,
-    funcs: &HashMap<&str, &InstrView>,
-) -> Result<Option<BrilValue>, String> {
-    let func_name = str::from_utf8(instr_view.func_name).unwrap();
+    state: &mut ProgramState<'a, W>,
+) -> Result<Option<BrilValue>, InterpError> {
+    let ret_type = Option::<Type>::from(instr_view.func_ret_ty);
+
+    // The shadow environment written to by `set` & read by `get`
+    // (SSA extension), which is local to each function invocation
+    let mut shadow_env = Environment::new();
 
     let mut current_instr_ptr = 0; // Initialize program counter
 
@@ -324,6 +769,9 @@ pub fn interp_instr_view<'a>(
             current_instr_ptr += 1;
             continue;
         }
+        state.instruction_count += 1;
+        state.pos = instr.pos;
+        state.pos_end = instr.pos_end;
         let op: Opcode = Opcode::u32_to_opcode(instr.op)
             .expect("unable to convert u32 to opcode");
         match instr_kind {
@@ -332,8 +780,7 @@ pub fn interp_instr_view<'a>(
                 unreachable!()
             }
             InstrKind::Const => {
-                let (dest_start, dest_end): (u32, u32) = instr.dest.into();
-                let dest = get_var(instr_view, dest_start, dest_end);
+                let dest = get_dest(instr_view, instr);
                 let value =
                     instr.value.try_into().expect("Encountered a null value");
 
@@ -344,129 +791,80 @@ pub fn interp_instr_view<'a>(
             }
             InstrKind::EffectOp => {
                 if let Opcode::Print = op {
-                    let (args_start, args_end): (u32, u32) = instr.args.into();
-                    let args = get_args(instr_view, args_start, args_end);
-
-                    let arg_values: Vec<&BrilValue> = args
-                        .iter()
-                        .map(|arg| env.get(arg).expect("arg missing from env"))
-                        .collect();
+                    // `print` with no args just prints an empty line
+                    let args = get_instr_args(instr_view, instr);
 
-                    let value_strs: Vec<String> = arg_values
+                    let value_strs = args
                         .iter()
-                        .map(|value| format!("{value}"))
-                        .collect();
+                        .map(|arg| Ok(get_value(env, arg)?.to_string()))
+                        .collect::<Result<Vec<String>, InterpError>>()?;
 
                     let string_to_print = value_strs.join(" ");
 
                     // Actually print out the value of the arguments
                     // NOTE TO SELF: DO NOT REMOVE THIS PRINTLN
-                    println!("{string_to_print}");
+                    writeln!(state.out, "{string_to_print}")?;
 
                     current_instr_ptr += 1;
                 } else if let Opcode::Jmp = op {
-                    // Fetch the start/end idx of the label in the `labels_store`
-                    let (label_start, label_end): (u32, u32) =
-                        instr.instr_labels.into();
-
                     // Grab the actual vector of label strings corresponding
-                    // to these indices
-                    let labels_vec =
-                        get_labels_vec(instr_view, label_start, label_end);
-
-                    assert!(
-                        labels_vec.len() == 1,
-                        "no. of args to jump instr != 1"
-                    );
-
-                    let label_str = labels_vec[0];
+                    // to the instr's label indices
+                    let labels_vec = get_n_instr_labels(instr_view, instr, 1)?;
 
-                    let label_start = label_start as usize;
-                    let label_end = label_end as usize;
+                    // Update `current_instr_ptr` to the PC of the label
+                    current_instr_ptr = find_label(instr_view, labels_vec[0])?;
+                    continue;
+                } else if let Opcode::Br = op {
+                    let args = get_n_instr_args(instr_view, instr, 1)?;
+                    let value_of_arg = get_value(env, args[0])?;
 
-                    let all_instrs = instr_view.instrs;
+                    let BrilValue::BoolVal(surrogate_bool) = value_of_arg
+                    else {
+                        return Err(check_type(Type::Bool, &value_of_arg)
+                            .expect_err("br argument is well-typed"));
+                    };
+                    let br_condition = bool::from(surrogate_bool);
 
-                    // Iterate over the list of instrs to find the index (PC)
-                    // of the instr corresponding to the label (we do this
-                    // by comparing the actual label strings)
-                    let pc_of_label = get_pc_of_label(instr_view, label_str);
+                    let labels = get_n_instr_labels(instr_view, instr, 2)?;
+                    let true_lbl = labels[0];
+                    let false_lbl = labels[1];
 
-                    if let Some(new_pc) = pc_of_label {
-                        // Update `current_instr_ptr` to the PC of the label
-                        current_instr_ptr = new_pc;
-                        continue;
+                    current_instr_ptr = if br_condition {
+                        find_label(instr_view, true_lbl)?
                     } else {
-                        panic!("cannot find PC corresponding to label")
-                    }
-                } else if let Opcode::Br = op {
-                    let (args_start, args_end): (u32, u32) = instr.args.into();
-                    let args = get_args(instr_view, args_start, args_end);
-                    assert!(
-                        args.len() == 1,
-                        "br instruction must only have 1 arg"
-                    );
-                    let arg = args[0];
-                    let value_of_arg =
-                        env.get(arg).expect("arg missing from env");
-
-                    if let BrilValue::BoolVal(surrogate_bool) = value_of_arg {
-                        let br_condition = bool::from(*surrogate_bool);
-
-                        let (labels_start, labels_end): (u32, u32) =
-                            instr.instr_labels.into();
-                        let labels = get_labels_vec(
-                            instr_view,
-                            labels_start,
-                            labels_end,
-                        );
-
-                        assert!(
-                            labels.len() == 2,
-                            "br instruction is malformed (has != 2 labels)"
-                        );
-
-                        let true_lbl = labels[0];
-                        let true_pc = get_pc_of_label(instr_view, true_lbl)
-                            .expect("label for true case doesn't have a PC");
-
-                        let false_lbl = labels[1];
-                        let false_pc = get_pc_of_label(instr_view, false_lbl)
-                            .expect("label for false case doesn't have a PC");
-
-                        if br_condition {
-                            current_instr_ptr = true_pc;
-                            continue;
-                        } else {
-                            current_instr_ptr = false_pc;
-                            continue;
-                        }
-                    } else {
-                        panic!(
-                            "argument to br instruction is ill-typed (doesn't have type bool)"
-                        );
-                    }
+                        find_label(instr_view, false_lbl)?
+                    };
+                    continue;
                 } else if let Opcode::Call = op {
-                    interp_call(instr_view, env, funcs, instr, instr_kind);
+                    interp_call(instr_view, env, state, instr, instr_kind)?;
                     current_instr_ptr += 1;
                 } else if let Opcode::Ret = op {
-                    let return_args = instr.args;
-                    if return_args.first == -1 && return_args.second == -1 {
+                    let args = get_instr_args(instr_view, instr);
+                    return match (ret_type, args.as_slice()) {
                         // No args supplied to Ret
-                        return Ok(None);
-                    }
-                    let args = get_args(
-                        instr_view,
-                        return_args.first as u32,
-                        return_args.second as u32,
-                    );
-                    assert!(
-                        args.len() <= 1,
-                        "too many args supplied to Ret instruction"
-                    );
-
-                    let arg = args[0];
-                    let ret_value = env.get(arg).expect("missing arg in env");
-                    return Ok(Some(*ret_value));
+                        (None, []) => Ok(None),
+                        (None, [_]) => Err(InterpError::NonEmptyRetForFunc(
+                            get_func_name(instr_view).to_string(),
+                        )),
+                        (Some(_), []) => Err(InterpError::BadNumArgs(1, 0)),
+                        (Some(ty), [arg]) => {
+                            let ret_value = get_value(env, arg)?;
+                            check_type(ty, &ret_value)?;
+                            Ok(Some(ret_value))
+                        }
+                        (_, _) => Err(InterpError::BadNumArgs(1, args.len())),
+                    };
+                } else if let Opcode::Store | Opcode::Free = op {
+                    interp_mem_op(instr_view, op, instr, env, &mut state.heap)?;
+                    current_instr_ptr += 1;
+                } else if let Opcode::Set = op {
+                    let args = get_n_instr_args(instr_view, instr, 2)?;
+                    let value = get_value(env, args[1])?;
+
+                    // Write the value of the 2nd arg to the shadow env
+                    // entry for the 1st arg
+                    shadow_env.insert(args[0], value);
+                    current_instr_ptr += 1;
                 } else {
                     // There are no more EffectOps to handle
                     unreachable!()
@@ -474,11 +872,23 @@ pub fn interp_instr_view<'a>(
             }
             InstrKind::ValueOp => {
                 if op.is_binop() {
-                    interp_binop(instr_view, op, instr, env);
+                    interp_binop(instr_view, op, instr, env)?;
                 } else if op.is_unop() {
-                    interp_unop(instr_view, op, instr, env);
+                    interp_unop(instr_view, op, instr, env)?;
                 } else if let Opcode::Call = op {
-                    interp_call(instr_view, env, funcs, instr, instr_kind);
+                    interp_call(instr_view, env, state, instr, instr_kind)?;
+                } else if let Opcode::Alloc | Opcode::Load = op {
+                    interp_mem_op(instr_view, op, instr, env, &mut state.heap)?;
+                } else if let Opcode::Get = op {
+                    let dest = get_dest(instr_view, instr);
+                    let value = *shadow_env
+                        .get(dest)
+                        .ok_or(InterpError::GetWithoutSet)?;
+                    assign(env, dest, instr, value)?;
+                } else if let Opcode::Undef = op {
+                    // `dest` becomes undefined, so reading it is an error
+                    let dest = get_dest(instr_view, instr);
+                    env.remove(dest);
                 } else {
                     // there are no more ValueOps to handle
                     unreachable!()
@@ -491,50 +901,133 @@ pub fn interp_instr_view<'a>(
             }
         }
     }
-    Ok(None)
+
+    // Falling off the end of a non-void function is reported at the
+    // position of the function
+    match ret_type {
+        None => Ok(None),
+        Some(ty) => {
+            state.pos = instr_view.func_pos;
+            state.pos_end = instr_view.func_pos_end;
+            Err(InterpError::NonVoidFuncNoRet(ty.into()))
+        }
+    }
+}
+
+/// Parses the command line argument `arg_value` as a value of type `ty`
+/// (the type of an argument to `main`)
+fn parse_arg(ty: Type, arg_value: &str) -> Result<BrilValue, InterpError> {
+    let bad_arg =
+        || InterpError::BadFuncArgType(ty.into(), arg_value.to_string());
+    match ty {
+        Type::Bool => arg_value
+            .parse::<bool>()
+            .map(|b| BrilValue::BoolVal(b.into()))
+            .map_err(|_| bad_arg()),
+        Type::Int => arg_value
+            .parse::<i64>()
+            .map(BrilValue::IntVal)
+            .map_err(|_| bad_arg()),
+        Type::Float => arg_value
+            .parse::<f64>()
+            .map(BrilValue::FloatVal)
+            .map_err(|_| bad_arg()),
+        Type::Char => {
+            let mut chars = arg_value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(BrilValue::CharVal(c.into())),
+                _ => Err(InterpError::NotOneChar),
+            }
+        }
+        // `main` can't take pointers as arguments
+        Type::Ptr(_, _) => Err(bad_arg()),
+    }
 }
 
-/// Interprets an entire program using the `cmd_line_args` (args to `main`)
-pub fn interp_program(program: &[InstrView], cmd_line_args: Vec<&str>) {
+/// Checks that no two labels in `instr_view` have the same name
+fn check_labels(instr_view: &InstrView) -> Result<(), InterpError> {
+    let mut labels = HashSet::new();
+    for instr in instr_view.instrs {
+        if let InstrKind::Label = instr.get_instr_kind() {
+            let label = get_label_name(
+                instr_view,
+                instr.label.first as u32,
+                instr.label.second as u32,
+            );
+            if !labels.insert(label) {
+                return Err(InterpError::DuplicateLabel(label.to_string()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Interprets an entire program using the `cmd_line_args` (args to `main`),
+/// writing the output of the program to `out`
+/// - If `profiling` is enabled, the no. of instructions executed is written
+///   to `profiling_out` (as `total_dyn_inst: N`) once the program finishes
+/// - Errors are reported at the position of the instruction that caused them
+///   (errors in the arguments to `main` and memory leaks are reported at the
+///   position of `main`)
+pub fn interp_program<W: Write, P: Write>(
+    program: &[InstrView],
+    cmd_line_args: Vec<&str>,
+    out: W,
+    profiling: bool,
+    mut profiling_out: P,
+) -> Result<(), PositionalInterpError> {
     let mut funcs = HashMap::new();
+    for view in program.iter() {
+        check_labels(view)?;
+        if funcs.insert(get_func_name(view), view).is_some() {
+            return Err(InterpError::DuplicateFunction.into());
+        }
+    }
 
     // Find the main function
-    for view in program.iter() {
-        let func_name = str::from_utf8(view.func_name).expect("invalid utf-8");
-        // Remove excess null terminators at end of string
-        let func_name = func_name.trim_end_matches(char::from(0));
-        funcs.insert(func_name, view);
+    let main: &InstrView =
+        funcs.get("main").ok_or(InterpError::NoMainFunction)?;
+    let main_pos = |e: InterpError| e.add_pos(main.func_pos, main.func_pos_end);
+    if main.func_args.len() != cmd_line_args.len() {
+        return Err(main_pos(InterpError::BadNumFuncArgs(
+            main.func_args.len(),
+            cmd_line_args.len(),
+        )));
     }
 
     // Prepopulate the env with command line arguments
     let mut env = Environment::new();
-    for (ff_arg, arg_value) in
-        funcs["main"].func_args.iter().zip(cmd_line_args.iter())
-    {
+    for (ff_arg, arg_value) in main.func_args.iter().zip(cmd_line_args) {
         let (ff_args_start, ff_args_end): (u32, u32) =
             ff_arg.arg_name_idxes.into();
-        let arg_name = get_var(funcs["main"], ff_args_start, ff_args_end);
-        match ff_arg.arg_type {
-            FlatType::Bool => {
-                if *arg_value == "true" {
-                    env.insert(arg_name, BrilValue::BoolVal(true.into()));
-                } else if *arg_value == "false" {
-                    env.insert(arg_name, BrilValue::BoolVal(false.into()));
-                }
-            }
-            FlatType::Int => {
-                // Actually try to parse the string as an int
-                let i = arg_value
-                    .parse::<i64>()
-                    .expect("Unable to parse string as i64");
-                env.insert(arg_name, BrilValue::IntVal(i));
-            }
-            FlatType::Null => {
-                panic!("function argument has unexpected null type");
-            }
-        }
+        let arg_name = get_var(main, ff_args_start, ff_args_end);
+        let ty = Type::try_from(ff_arg.arg_type)
+            .expect("function argument has unexpected null type");
+        env.insert(arg_name, parse_arg(ty, arg_value).map_err(main_pos)?);
+    }
+
+    let mut state = ProgramState {
+        funcs,
+        heap: Heap::default(),
+        out,
+        instruction_count: 0,
+        pos: None.into(),
+        pos_end: None.into(),
+    };
+    interp_instr_view(main, &mut env, &mut state)
+        .map_err(|e| e.add_pos(state.pos, state.pos_end))?;
+
+    if !state.heap.is_empty() {
+        return Err(main_pos(InterpError::MemLeak));
+    }
+
+    state.out.flush().map_err(InterpError::from)?;
+
+    if profiling {
+        writeln!(profiling_out, "total_dyn_inst: {}", state.instruction_count)
+            .map_err(InterpError::from)?;
+        profiling_out.flush().map_err(InterpError::from)?;
     }
 
-    interp_instr_view(funcs["main"], &mut env, &funcs)
-        .expect("unexpected error when interpreting main");
+    Ok(())
 }
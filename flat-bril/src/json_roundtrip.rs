@@ -1,8 +1,7 @@
 use std::fs::File;
 use std::io::{self, BufReader, Read};
 
-use crate::flatten;
-use crate::unflatten;
+use flat_bril::{flatten, memfile, unflatten};
 
 /// Does a round trip from JSON -> flattened representation -> back to JSON
 /// - `input_json` is the filename of the input JSON (if supplied)
@@ -22,6 +21,10 @@ pub fn json_roundtrip(input_json: Option<String>, verbose: bool) {
             .expect("Unable to read from stdin");
         serde_json::from_str(&buffer).expect("Unable to parse malformed JSON")
     };
+    if let Err(e) = memfile::check_extensions(&json) {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
 
     let functions = json["functions"]
         .as_array()
@@ -0,0 +1,306 @@
+//! A [flattened](https://www.cs.cornell.edu/~asampson/blog/flattening.html)
+//! representation for Bril programs.
+//!
+//! Each function is flattened into an [`InstrStore`], which stores all of the
+//! function's instructions, variables & labels in a handful of flat arrays.
+//! Flattened programs can be written to `.fbril` files, which are read back
+//! (without copying) as a list of [`InstrView`]s, one per function. The
+//! [`interp`] module contains an interpreter that works directly over
+//! `InstrView`s, and the [`passes`] module contains optimization passes that
+//! rewrite `InstrStore`s in place.
+
+pub mod flatten;
+pub mod interp;
+pub mod memfile;
+pub mod passes;
+pub mod types;
+pub mod unflatten;
+
+pub use interp::{InterpError, PositionalInterpError, interp_program};
+pub use memfile::{FbrilError, read_fbril};
+pub use types::{InstrStore, InstrView};
+
+use bril_rs::Program;
+
+/// Flattens every function in a Bril program
+/// (the result contains one `InstrStore` per function)
+/// - Panics if the program uses an extension that flat-bril doesn't support
+pub fn flatten_program(program: &Program) -> Vec<InstrStore> {
+    let json =
+        serde_json::to_value(program).expect("unable to serialize program");
+    json["functions"]
+        .as_array()
+        .expect("Expected `functions` to be a JSON array")
+        .iter()
+        .map(flatten::flatten_instrs)
+        .collect()
+}
+
+/// Converts flattened functions back to a Bril program
+pub fn unflatten_program(instr_stores: &[InstrStore]) -> Program {
+    let functions: Vec<serde_json::Value> = instr_stores
+        .iter()
+        .map(unflatten::unflatten_instrs)
+        .collect();
+    serde_json::from_value(serde_json::json!({ "functions": functions }))
+        .expect("unflattened program is malformed")
+}
+
+/// Converts a Bril program to the contents of a `.fbril` file
+pub fn program_to_fbril(program: &Program) -> Vec<u8> {
+    memfile::instr_stores_to_fbril_bytes(flatten_program(program))
+}
+
+/// Runs the optimization `passes` over the contents of a `.fbril` file,
+/// returning the contents of the optimized `.fbril` file
+pub fn optimize_fbril(
+    data: &[u8],
+    passes: &[passes::Pass],
+) -> Result<Vec<u8>, FbrilError> {
+    let mut instr_stores: Vec<InstrStore> = read_fbril(data)?
+        .into_iter()
+        .map(InstrStore::from)
+        .collect();
+    passes::run_passes(&mut instr_stores, passes);
+    Ok(memfile::instr_stores_to_fbril_bytes(instr_stores))
+}
+
+/// Converts the contents of a `.fbril` file back to a Bril program
+pub fn fbril_to_program(data: &[u8]) -> Result<Program, FbrilError> {
+    let instr_stores: Vec<InstrStore> = read_fbril(data)?
+        .into_iter()
+        .map(InstrStore::from)
+        .collect();
+    Ok(unflatten_program(&instr_stores))
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                    Tests                                   */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod lib_tests {
+    use std::{
+        fs,
+        fs::File,
+        io,
+        io::BufReader,
+        path::{Path, PathBuf},
+    };
+
+    use super::*;
+
+    /// Loads every JSON program in the `test` directory
+    fn test_programs() -> io::Result<Vec<(PathBuf, Program)>> {
+        let mut programs = vec![];
+        for entry in fs::read_dir("test")? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+                let file = File::open(&path)?;
+                let program =
+                    bril_rs::load_program_from_read(BufReader::new(file));
+                programs.push((path, program));
+            }
+        }
+        Ok(programs)
+    }
+
+    /// Checks that programs survive a `Program` -> `.fbril` -> `Program`
+    /// round trip unchanged
+    #[test]
+    fn test_fbril_program_round_trip() -> io::Result<()> {
+        for (path, program) in test_programs()? {
+            let bytes = program_to_fbril(&program);
+            let round_tripped =
+                fbril_to_program(&bytes).expect("unable to read fbril file");
+            assert_eq!(program, round_tripped, "{}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Interprets the `.fbril` file `bytes` (flattened from the test program
+    /// at `path`), returning the output it writes
+    fn interp_output(path: &Path, bytes: &[u8]) -> String {
+        let instr_views = read_fbril(bytes).expect("unable to read fbril file");
+
+        // The only test program that takes arguments is `ssa-two-phi`
+        let args = if path.ends_with("ssa-two-phi.json") {
+            vec!["true"]
+        } else {
+            vec![]
+        };
+        let mut out = vec![];
+        interp_program(&instr_views, args, &mut out, false, io::sink())
+            .expect("unable to interpret program");
+        String::from_utf8(out).unwrap()
+    }
+
+    /// Checks that interpreting each program writes the expected output
+    /// (stored in the corresponding `.out` file) to the supplied writer
+    #[test]
+    fn test_interp_program_output() -> io::Result<()> {
+        for (path, program) in test_programs()? {
+            let bytes = program_to_fbril(&program);
+            let expected = fs::read_to_string(path.with_extension("out"))?;
+            assert_eq!(
+                interp_output(&path, &bytes),
+                expected,
+                "{}",
+                path.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks that optimizing each program doesn't change its output
+    /// (and that the optimized program can still be converted back to JSON)
+    #[test]
+    fn test_optimized_program_output() -> io::Result<()> {
+        let all_passes: Vec<passes::Pass> =
+            passes::PASSES.iter().map(|(_, pass)| *pass).collect();
+        for (path, program) in test_programs()? {
+            let bytes =
+                optimize_fbril(&program_to_fbril(&program), &all_passes)
+                    .expect("unable to optimize fbril file");
+            fbril_to_program(&bytes).expect("unable to read fbril file");
+            let expected = fs::read_to_string(path.with_extension("out"))?;
+            assert_eq!(
+                interp_output(&path, &bytes),
+                expected,
+                "{}",
+                path.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks that profiling counts every instruction that's executed
+    /// (but not labels)
+    #[test]
+    fn test_interp_program_profiling() -> io::Result<()> {
+        let file = File::open("test/call.json")?;
+        let program = bril_rs::load_program_from_read(BufReader::new(file));
+        let bytes = program_to_fbril(&program);
+        let instr_views =
+            read_fbril(&bytes).expect("unable to read fbril file");
+
+        let mut profiling_out = vec![];
+        interp_program(
+            &instr_views,
+            vec![],
+            io::sink(),
+            true,
+            &mut profiling_out,
+        )
+        .expect("unable to interpret program");
+        assert_eq!(
+            String::from_utf8(profiling_out).unwrap(),
+            "total_dyn_inst: 6\n"
+        );
+        Ok(())
+    }
+
+    /// Checks that runtime errors are reported as `InterpError`s
+    /// instead of panicking
+    #[test]
+    fn test_interp_program_errors() {
+        let program: Program = serde_json::from_value(serde_json::json!({
+            "functions": [{
+                "name": "main",
+                "args": [{ "name": "x", "type": "int" }],
+                "instrs": [
+                    {
+                        "op": "const", "dest": "zero", "type": "int",
+                        "value": 0
+                    },
+                    {
+                        "op": "div", "dest": "q", "type": "int",
+                        "args": ["x", "zero"]
+                    },
+                    { "op": "print", "args": ["q"] }
+                ]
+            }]
+        }))
+        .unwrap();
+        let bytes = program_to_fbril(&program);
+        let instr_views =
+            read_fbril(&bytes).expect("unable to read fbril file");
+
+        let interp = |args| {
+            interp_program(&instr_views, args, io::sink(), false, io::sink())
+                .map_err(|e| e.e)
+        };
+        assert!(matches!(
+            interp(vec!["1"]),
+            Err(InterpError::DivisionByZero)
+        ));
+        assert!(matches!(
+            interp(vec![]),
+            Err(InterpError::BadNumFuncArgs(1, 0))
+        ));
+        assert!(matches!(
+            interp(vec!["one"]),
+            Err(InterpError::BadFuncArgType(bril_rs::Type::Int, _))
+        ));
+    }
+
+    /// Checks that runtime errors are reported at the source position
+    /// of the instruction that caused them (in a callee, for calls),
+    /// and that errors in `main`'s arguments are reported at `main`
+    #[test]
+    fn test_interp_program_error_positions() {
+        let json = serde_json::json!({
+            "functions": [
+                {
+                    "name": "main",
+                    "args": [{ "name": "x", "type": "int" }],
+                    "pos": { "row": 1, "col": 1 },
+                    "instrs": [
+                        {
+                            "op": "call", "dest": "q", "type": "int",
+                            "args": ["x"], "funcs": ["inv"],
+                            "pos": { "row": 2, "col": 3 }
+                        }
+                    ]
+                },
+                {
+                    "name": "inv",
+                    "args": [{ "name": "x", "type": "int" }],
+                    "type": "int",
+                    "pos": { "row": 4, "col": 1 },
+                    "instrs": [
+                        {
+                            "op": "const", "dest": "one", "type": "int",
+                            "value": 1,
+                            "pos": { "row": 5, "col": 3 }
+                        },
+                        {
+                            "op": "div", "dest": "q", "type": "int",
+                            "args": ["one", "x"],
+                            "pos": { "row": 6, "col": 3 },
+                            "pos_end": { "row": 6, "col": 24 }
+                        },
+                        { "op": "ret", "args": ["q"] }
+                    ]
+                }
+            ]
+        });
+        let bytes = memfile::json_to_fbril_bytes(&json)
+            .expect("unable to flatten program");
+        let instr_views =
+            read_fbril(&bytes).expect("unable to read fbril file");
+
+        let interp = |args| {
+            interp_program(&instr_views, args, io::sink(), false, io::sink())
+                .expect_err("program should fail")
+                .to_string()
+        };
+        assert_eq!(
+            interp(vec!["0"]),
+            "Line 6, Column 3 to Line 6, Column 24: Attempt to divide by 0"
+        );
+        assert_eq!(
+            interp(vec![]),
+            "Line 1, Column 1: Expected `1` function arguments, found `0`"
+        );
+    }
+}
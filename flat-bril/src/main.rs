@@ -1,15 +1,9 @@
-use std::path::Path;
+use std::io::{self, BufWriter};
 
 use clap::{Arg, ArgAction, Command};
-use interp::interp_program;
-use types::Header;
-use zerocopy::FromBytes;
-mod flatten;
-mod interp;
+use flat_bril::passes::{self, Pass};
+use flat_bril::{interp_program, memfile, optimize_fbril};
 mod json_roundtrip;
-mod memfile;
-mod types;
-mod unflatten;
 
 // To create an `.fbril` file from an existing `.bril` file, do one of the following:
 // 1. Convert a `.bril` file to JSON using `bril2json`, then redirect it to `cargo run`:
@@ -17,7 +11,13 @@ mod unflatten;
 // 2. Read in an existing JSON file
 // `cargo run -- --json --filename test/call.json`
 
+// To optimize a file in place (running every pass if none are specified):
+// `cargo run -- --filename test/call.fbril --opt const-fold copy-prop dce`
+
 // To interpret a file: `cargo run -- --filename test/call.fbril --interp`
+// To interpret a JSON program without writing a file (add `-p` to count the
+// no. of instructions executed):
+// `bril2json < test/call.bril | cargo run -- --interp`
 
 fn main() {
     let matches = Command::new("flat-bril")
@@ -28,7 +28,22 @@ fn main() {
                 .num_args(0..)
                 .allow_hyphen_values(true)
                 .value_name("ARGS_TO_MAIN")
-                .help("Interprets a Flat Bril (.fbril) file"),
+                .help(
+                    "Interprets a Flat Bril (.fbril) file\n\
+                    (or a JSON Bril program read from stdin if `--filename` \
+                    isn't specified)"
+                ),
+        )
+        .arg(
+            Arg::new("profile")
+                .short('p')
+                .long("profile")
+                .action(ArgAction::SetTrue)
+                .requires("interp")
+                .help(
+                    "Prints the no. of instructions executed to stderr\n\
+                    (only works when `--interp` is also specified)"
+                ),
         )
         .arg(
             Arg::new("json")
@@ -54,6 +69,19 @@ fn main() {
                 .requires("filename")
                 .help("Produces a Flat Bril (.fbril) file"),
         )
+        .arg(
+            Arg::new("opt")
+                .long("opt")
+                .action(ArgAction::Append)
+                .num_args(0..)
+                .value_name("PASSES")
+                .value_parser(passes::PASSES.map(|(name, _)| name))
+                .requires("filename")
+                .help(
+                    "Optimizes a Flat Bril (.fbril) file in place\n\
+                    (runs every pass if no passes are specified)"
+                ),
+        )
         .arg(
             Arg::new("filename")
                 .long("filename")
@@ -62,7 +90,8 @@ fn main() {
                 .help(
                     "If `--json` is enabled, then `--filename` is the JSON file \
                     to open.\nIf `--interp` is enabled, then `--filename` is the \
-                    `.fbril` file to write to."
+                    `.fbril` file to write to.\nIf `--opt` is enabled, then \
+                    `--filename` is the `.fbril` file to optimize."
                 ),
         )
         .get_matches();
@@ -79,46 +108,92 @@ fn main() {
         match matches.get_one::<String>("filename") {
             Some(filename) => {
                 println!("Processing {}", filename);
-                memfile::json_to_fbril(filename.clone());
+                if let Err(e) = memfile::json_to_fbril(filename.clone()) {
+                    eprintln!("Error: {e}");
+                    std::process::exit(1);
+                }
             }
             None => {
                 eprintln!("Error: --fbril requires a filename argument");
                 std::process::exit(1);
             }
         }
-    } else if let Some(possible_arg_values) =
-        matches.get_many::<String>("interp")
-    {
+    } else if let Some(pass_names) = matches.get_many::<String>("opt") {
         let filename = matches
             .get_one::<String>("filename")
             .expect("missing filename");
 
-        let arg_values: Vec<&str> =
-            possible_arg_values.map(|s| s.as_str()).collect();
-        // Actually interpret a flat Bril file
-        if !Path::new(&filename).exists() {
-            panic!("tried to open a non-existent file");
+        let mut passes: Vec<Pass> = pass_names
+            .map(|name| passes::get_pass(name))
+            .collect::<Result<_, _>>()
+            .unwrap_or_else(|e| {
+                eprintln!("Error: {e}");
+                std::process::exit(1);
+            });
+        if passes.is_empty() {
+            passes = passes::PASSES.iter().map(|(_, pass)| *pass).collect();
         }
 
-        let new_mmap =
-            memfile::mmap_new_file(filename.as_str(), 100000000, false);
-        let (new_header, remaining_mmap) =
-            Header::ref_from_prefix(&new_mmap).unwrap();
-
-        let mut offset = 0;
+        // The file is unmapped before we overwrite it with the optimized
+        // program
+        let result = memfile::mmap_file(filename)
+            .and_then(|mmap| optimize_fbril(&mmap, &passes))
+            .and_then(|bytes| memfile::write_fbril(filename, &bytes));
+        if let Err(e) = result {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+    } else if let Some(possible_arg_values) =
+        matches.get_many::<String>("interp")
+    {
+        let arg_values: Vec<&str> =
+            possible_arg_values.map(|s| s.as_str()).collect();
 
-        let mut program_vec = vec![];
-        for size in new_header.sizes {
-            if size != 0 {
-                let size = size as usize;
-                let instr_view = memfile::get_instr_view(
-                    &remaining_mmap[offset..offset + size],
-                );
-                program_vec.push(instr_view);
-                offset += size;
+        // Read in the flat Bril program, either by mmap-ing a `.fbril` file
+        // or by flattening a JSON program read from stdin
+        // (either way, the program is validated before we start interpreting it)
+        let mmap;
+        let flattened;
+        let bytes: &[u8] = match matches.get_one::<String>("filename") {
+            Some(filename) => {
+                mmap = memfile::mmap_file(filename).unwrap_or_else(|e| {
+                    eprintln!("Error: {e}");
+                    std::process::exit(1);
+                });
+                &mmap
             }
+            None => {
+                let json: serde_json::Value = serde_json::from_reader(
+                    io::stdin().lock(),
+                )
+                .unwrap_or_else(|e| {
+                    eprintln!("Error: {}", memfile::FbrilError::from(e));
+                    std::process::exit(1);
+                });
+                flattened =
+                    memfile::json_to_fbril_bytes(&json).unwrap_or_else(|e| {
+                        eprintln!("Error: {e}");
+                        std::process::exit(1);
+                    });
+                &flattened
+            }
+        };
+        let program = memfile::read_fbril(bytes).unwrap_or_else(|e| {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        });
+
+        // Runtime errors are reported the same way as `brilirs`
+        // (i.e. with exit code 2)
+        if let Err(e) = interp_program(
+            &program,
+            arg_values,
+            BufWriter::new(io::stdout().lock()),
+            matches.get_flag("profile"),
+            io::stderr(),
+        ) {
+            eprintln!("error: {e}");
+            std::process::exit(2);
         }
-        let program = program_vec.as_slice();
-        interp_program(program, arg_values);
     }
 }
@@ -4,10 +4,14 @@ use std::convert::Infallible;
 use std::io::Read;
 use std::str;
 
+use bril_rs::AbstractProgram;
+use bril_rs::extensions::{Extension, ExtensionError, ExtensionUsage};
 use memmap2::{Mmap, MmapMut};
 use num_traits::ops::bytes;
+use thiserror::Error;
 use zerocopy::{
-    FromBytes, Immutable, IntoBytes, KnownLayout, SizeError, Unaligned,
+    ConvertError, FromBytes, Immutable, IntoBytes, KnownLayout, SizeError,
+    Unaligned,
 };
 use zerocopy::{TryFromBytes, ValidityError};
 
@@ -15,6 +19,56 @@ use crate::flatten;
 use crate::interp;
 use crate::types::*;
 
+/* -------------------------------------------------------------------------- */
+/*                                   Errors                                   */
+/* -------------------------------------------------------------------------- */
+
+/// Errors that can occur when reading/writing a `.fbril` file
+#[derive(Debug, Error)]
+pub enum FbrilError {
+    #[error("unable to access `{0}`: {1}")]
+    Io(String, #[source] std::io::Error),
+    #[error("unable to parse malformed JSON: {0}")]
+    MalformedJson(#[from] serde_json::Error),
+    #[error("not a flat Bril file (bad magic number)")]
+    BadMagic,
+    #[error(
+        "unsupported .fbril format version {0} (expected version {expected})",
+        expected = FBRIL_VERSION
+    )]
+    UnsupportedVersion(u32),
+    #[error("file is truncated: expected {expected} bytes, found {actual}")]
+    Truncated { expected: u64, actual: u64 },
+    #[error("file has {0} unexpected trailing bytes")]
+    TrailingBytes(u64),
+    #[error(
+        "checksum mismatch (expected {expected:#018x}, found {actual:#018x}), file is corrupt"
+    )]
+    ChecksumMismatch { expected: u64, actual: u64 },
+    #[error("function {func} is malformed: {reason}")]
+    MalformedFunc { func: usize, reason: String },
+    #[error("{0}")]
+    Unsupported(#[from] ExtensionError),
+}
+
+/// The Bril extensions that `flat-bril` supports (on top of core Bril)
+pub const SUPPORTED_EXTENSIONS: [Extension; 5] = [
+    Extension::Float,
+    Extension::Char,
+    Extension::Memory,
+    Extension::Ssa,
+    Extension::Bitcast,
+];
+
+/// Checks that the JSON Bril program only uses core Bril and the
+/// `SUPPORTED_EXTENSIONS`, so that unsupported programs are rejected before
+/// we try to flatten them
+pub fn check_extensions(json: &serde_json::Value) -> Result<(), FbrilError> {
+    let program: AbstractProgram = serde_json::from_value(json.clone())?;
+    ExtensionUsage::from(&program).check(&SUPPORTED_EXTENSIONS)?;
+    Ok(())
+}
+
 /* -------------------------------------------------------------------------- */
 /*                              Writing to buffer                             */
 /* -------------------------------------------------------------------------- */
@@ -24,17 +78,32 @@ pub fn mmap_new_file(
     filename: &str,
     size: u64,
     should_truncate: bool,
-) -> MmapMut {
+) -> Result<MmapMut, FbrilError> {
+    let io_error = |e| FbrilError::Io(filename.to_string(), e);
     let file = std::fs::OpenOptions::new()
         .read(true)
         .write(true)
         .truncate(should_truncate)
         .create(true)
         .open(filename)
-        .unwrap();
-    file.set_len(size).unwrap();
-    let buffer = unsafe { MmapMut::map_mut(&file) };
-    buffer.unwrap()
+        .map_err(io_error)?;
+    file.set_len(size).map_err(io_error)?;
+    unsafe { MmapMut::map_mut(&file) }.map_err(io_error)
+}
+
+/// Mmaps an existing file (read-only), returning a handle to the mmap-ed buffer
+/// (the buffer is exactly as long as the file)
+pub fn mmap_file(filename: &str) -> Result<Mmap, FbrilError> {
+    let io_error = |e| FbrilError::Io(filename.to_string(), e);
+    let file = std::fs::File::open(filename).map_err(io_error)?;
+    unsafe { Mmap::map(&file) }.map_err(io_error)
+}
+
+/// Computes the (64-bit FNV-1a) checksum of a byte buffer
+pub fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
 }
 
 /// Writes `data` to the first `len` bytes of `buffer`,
@@ -72,6 +141,10 @@ pub fn convert_instr_view_to_bytes(instr_view: &InstrView) -> Vec<u8> {
     bytes_vec.extend_from_slice(instr_view.func_args.as_bytes());
     let func_ret_ty = instr_view.func_ret_ty;
     bytes_vec.extend_from_slice(func_ret_ty.as_bytes());
+    let func_pos = instr_view.func_pos;
+    bytes_vec.extend_from_slice(func_pos.as_bytes());
+    let func_pos_end = instr_view.func_pos_end;
+    bytes_vec.extend_from_slice(func_pos_end.as_bytes());
     bytes_vec.extend_from_slice(instr_view.var_store.as_bytes());
     bytes_vec.extend_from_slice(instr_view.arg_idxes_store.as_bytes());
     bytes_vec.extend_from_slice(instr_view.labels_idxes_store.as_bytes());
@@ -95,6 +168,10 @@ fn dump_to_buffer<'a>(instr_view: &'a InstrView, buffer: &'a mut [u8]) {
     let new_buffer = write_bump(new_buffer, instr_view.func_args).unwrap();
     let func_ret_ty = instr_view.func_ret_ty;
     let new_buffer = write_bump(new_buffer, &func_ret_ty).unwrap();
+    let func_pos = instr_view.func_pos;
+    let new_buffer = write_bump(new_buffer, &func_pos).unwrap();
+    let func_pos_end = instr_view.func_pos_end;
+    let new_buffer = write_bump(new_buffer, &func_pos_end).unwrap();
     let new_buffer = write_bytes(new_buffer, instr_view.var_store).unwrap();
     let new_buffer =
         write_bump(new_buffer, instr_view.arg_idxes_store).unwrap();
@@ -123,88 +200,193 @@ pub fn pad_vec(mut vec: Vec<u8>) -> Vec<u8> {
 /*                             Reading from buffer                            */
 /* -------------------------------------------------------------------------- */
 
-/// Consume `size.len` items from a byte slice,
-/// skip the remainder of `size.capacity`
-/// elements, and return the items and the rest of the slice.
-fn slice_prefix<T: TryFromBytes + Immutable>(
-    data: &[u8],
+/// Describes why a zerocopy conversion of the field `field` failed
+fn describe_error<A, S, V>(field: &str, err: ConvertError<A, S, V>) -> String {
+    match err {
+        ConvertError::Alignment(_) => format!("`{field}` is misaligned"),
+        ConvertError::Size(_) => format!("`{field}` is truncated"),
+        ConvertError::Validity(_) => {
+            format!("`{field}` contains invalid data")
+        }
+    }
+}
+
+/// Consume `size` items from a byte slice, and return the items
+/// and the rest of the slice (`field` is the name of the field being read,
+/// which is used in error messages)
+fn slice_prefix<'a, T: TryFromBytes + Immutable>(
+    data: &'a [u8],
     size: usize,
-) -> (&[T], &[u8]) {
+    field: &str,
+) -> Result<(&'a [T], &'a [u8]), String> {
     <[T]>::try_ref_from_prefix_with_elems(data, size)
-        .expect("Deserialization error in slice_prefix")
+        .map_err(|e| describe_error(field, e))
 }
 
 /// Reads the table of contents from a prefix of the byte buffer
-fn read_toc(data: &[u8]) -> (&Toc, &[u8]) {
-    let (toc, remaining_buffer) =
-        Toc::ref_from_prefix(data).expect("error deserializing ToC");
-    (toc, remaining_buffer)
+fn read_toc(data: &[u8]) -> Result<(&Toc, &[u8]), String> {
+    Toc::ref_from_prefix(data).map_err(|e| describe_error("Toc", e))
 }
 
 /// Get an `InstrView` backed by the data in a byte buffer
-pub fn get_instr_view(data: &[u8]) -> InstrView {
-    let (toc, buffer) = read_toc(data);
+/// (returns an error describing the problem if the buffer is malformed)
+pub fn get_instr_view(data: &[u8]) -> Result<InstrView<'_>, String> {
+    let (toc, buffer) = read_toc(data)?;
 
-    let (func_name, new_buffer) = slice_prefix::<u8>(buffer, toc.func_name);
+    let (func_name, new_buffer) =
+        slice_prefix::<u8>(buffer, toc.func_name, "func_name")?;
     let (func_args, new_buffer) =
-        slice_prefix::<FlatFuncArg>(new_buffer, toc.func_args);
+        slice_prefix::<FlatFuncArg>(new_buffer, toc.func_args, "func_args")?;
 
     let (func_ret_ty, new_buffer) =
         <FlatType>::try_read_from_prefix(new_buffer)
-            .expect("error deserializing func_ret_ty");
-
-    let (var_store, new_buffer) = slice_prefix::<u8>(new_buffer, toc.var_store);
-
-    let (arg_idxes_store, new_buffer) =
-        slice_prefix::<I32Pair>(new_buffer, toc.arg_idxes_store);
-    let (labels_idxes_store, new_buffer) =
-        slice_prefix::<I32Pair>(new_buffer, toc.labels_idxes_store);
+            .map_err(|e| describe_error("func_ret_ty", e))?;
+    let (func_pos, new_buffer) = <I32Pair>::try_read_from_prefix(new_buffer)
+        .map_err(|e| describe_error("func_pos", e))?;
+    let (func_pos_end, new_buffer) =
+        <I32Pair>::try_read_from_prefix(new_buffer)
+            .map_err(|e| describe_error("func_pos_end", e))?;
+
+    let (var_store, new_buffer) =
+        slice_prefix::<u8>(new_buffer, toc.var_store, "var_store")?;
+
+    let (arg_idxes_store, new_buffer) = slice_prefix::<I32Pair>(
+        new_buffer,
+        toc.arg_idxes_store,
+        "arg_idxes_store",
+    )?;
+    let (labels_idxes_store, new_buffer) = slice_prefix::<I32Pair>(
+        new_buffer,
+        toc.labels_idxes_store,
+        "labels_idxes_store",
+    )?;
     let (labels_store, new_buffer) =
-        slice_prefix::<u8>(new_buffer, toc.labels_store);
+        slice_prefix::<u8>(new_buffer, toc.labels_store, "labels_store")?;
     let (funcs_store, new_buffer) =
-        slice_prefix::<u8>(new_buffer, toc.funcs_store);
-    let (instrs, _) = slice_prefix::<FlatInstr>(new_buffer, toc.instrs);
+        slice_prefix::<u8>(new_buffer, toc.funcs_store, "funcs_store")?;
+    let (instrs, remaining_buffer) =
+        slice_prefix::<FlatInstr>(new_buffer, toc.instrs, "instrs")?;
+
+    if !remaining_buffer.is_empty() {
+        return Err(format!(
+            "{} bytes are left over after the instrs",
+            remaining_buffer.len()
+        ));
+    }
 
-    InstrView {
+    Ok(InstrView {
         func_name,
         func_args,
         func_ret_ty,
+        func_pos,
+        func_pos_end,
         var_store,
         arg_idxes_store,
         labels_idxes_store,
         labels_store,
         funcs_store,
         instrs,
+    })
+}
+
+/// Reads the contents of a `.fbril` file, returning an `InstrView` for each
+/// function in the program. Before the functions are read, the header is
+/// checked, and the file's size & checksum are validated against the header
+/// and the table of contents.
+pub fn read_fbril(data: &[u8]) -> Result<Vec<InstrView<'_>>, FbrilError> {
+    let actual_len = data.len() as u64;
+    let header_len = size_of::<Header>() as u64;
+
+    let (header, rest) =
+        Header::read_from_prefix(data).map_err(|_| FbrilError::Truncated {
+            expected: header_len,
+            actual: actual_len,
+        })?;
+    if header.magic != FBRIL_MAGIC {
+        return Err(FbrilError::BadMagic);
     }
+    if header.version != FBRIL_VERSION {
+        return Err(FbrilError::UnsupportedVersion(header.version));
+    }
+
+    // Read the table of contents (the size of each function)
+    let toc_len = header.num_funcs.saturating_mul(size_of::<u64>() as u64);
+    let mut sizes = vec![];
+    let mut funcs_data = rest;
+    for _ in 0..header.num_funcs {
+        let (size, new_funcs_data) = u64::read_from_prefix(funcs_data)
+            .map_err(|_| FbrilError::Truncated {
+                expected: header_len.saturating_add(toc_len),
+                actual: actual_len,
+            })?;
+        sizes.push(size);
+        funcs_data = new_funcs_data;
+    }
+
+    // The file should contain exactly the header, the table of contents
+    // & the functions
+    let expected_len = sizes
+        .iter()
+        .fold(header_len.saturating_add(toc_len), |len, size| {
+            len.saturating_add(*size)
+        });
+    if actual_len < expected_len {
+        return Err(FbrilError::Truncated {
+            expected: expected_len,
+            actual: actual_len,
+        });
+    } else if actual_len > expected_len {
+        return Err(FbrilError::TrailingBytes(actual_len - expected_len));
+    }
+
+    let actual_checksum = checksum(rest);
+    if actual_checksum != header.checksum {
+        return Err(FbrilError::ChecksumMismatch {
+            expected: header.checksum,
+            actual: actual_checksum,
+        });
+    }
+
+    let mut offset = 0;
+    let mut program = vec![];
+    for (func, size) in sizes.into_iter().enumerate() {
+        let size = size as usize;
+        let instr_view = get_instr_view(&funcs_data[offset..offset + size])
+            .map_err(|reason| FbrilError::MalformedFunc { func, reason })?;
+        program.push(instr_view);
+        offset += size;
+    }
+    Ok(program)
 }
 
 /* -------------------------------------------------------------------------- */
 /*                                Actual logic                                */
 /* -------------------------------------------------------------------------- */
 
-/// Writes a JSON Bril program to a mmap-ed flat Bril file
-pub fn json_to_fbril(output_file: String) {
-    // Read in the JSON representation of a Bril file from stdin
-    let mut buffer = String::new();
-    std::io::stdin()
-        .read_to_string(&mut buffer)
-        .expect("Unable to read from stdin");
-
-    // Parse the JSON into serde_json's `Value` datatype
-    let json: serde_json::Value =
-        serde_json::from_str(&buffer).expect("Unable to parse malformed JSON");
+/// Converts a JSON Bril program to the contents of a flat Bril file
+/// (returning an error if the program uses an unsupported extension)
+pub fn json_to_fbril_bytes(
+    json: &serde_json::Value,
+) -> Result<Vec<u8>, FbrilError> {
+    check_extensions(json)?;
     let functions = json["functions"]
         .as_array()
         .expect("Expected `functions` to be a JSON array");
+    let instr_stores: Vec<InstrStore> =
+        functions.iter().map(flatten::flatten_instrs).collect();
+    Ok(instr_stores_to_fbril_bytes(instr_stores))
+}
 
+/// Converts a flattened program (one `InstrStore` per function) to the
+/// contents of a flat Bril file (the header, followed by the table of contents
+/// & then the functions)
+pub fn instr_stores_to_fbril_bytes(instr_stores: Vec<InstrStore>) -> Vec<u8> {
     let mut buffer: Vec<u8> = Vec::with_capacity(100000);
 
-    // we only allow 10 functions right now
-    let mut sizes_arr: [u64; 10] = [0; 10];
-
-    for (sizes_idx, func) in functions.iter().enumerate() {
-        let instr_store: InstrStore = flatten::flatten_instrs(func);
+    // The size (no. of bytes) of each function
+    let mut sizes: Vec<u64> = Vec::with_capacity(instr_stores.len());
 
+    for instr_store in instr_stores {
         // Convert an `InstrStore` to an `InstrView`
         let padded_func_name = pad_vec(instr_store.func_name);
         let flat_func_name = padded_func_name.as_slice();
@@ -216,6 +398,8 @@ pub fn json_to_fbril(output_file: String) {
 
         let flat_func_args: &[FlatFuncArg] = flat_func_arg_vec.as_slice();
         let flat_func_ret_ty: FlatType = instr_store.func_ret_ty.into();
+        let flat_func_pos: I32Pair = instr_store.func_pos.into();
+        let flat_func_pos_end: I32Pair = instr_store.func_pos_end.into();
 
         let padded_var_store = pad_vec(instr_store.var_store);
         let flat_var_store: &[u8] = padded_var_store.as_slice();
@@ -248,6 +432,8 @@ pub fn json_to_fbril(output_file: String) {
             func_name: flat_func_name,
             func_args: flat_func_args,
             func_ret_ty: flat_func_ret_ty,
+            func_pos: flat_func_pos,
+            func_pos_end: flat_func_pos_end,
             var_store: flat_var_store,
             arg_idxes_store: flat_arg_idxes_store,
             labels_idxes_store: flat_label_idxes,
@@ -258,22 +444,140 @@ pub fn json_to_fbril(output_file: String) {
 
         let instr_view_bytes = convert_instr_view_to_bytes(&instr_view);
         buffer.extend_from_slice(&instr_view_bytes);
-        sizes_arr[sizes_idx] = instr_view_bytes.len() as u64;
+        sizes.push(instr_view_bytes.len() as u64);
     }
 
-    let header = Header { sizes: sizes_arr };
+    // The table of contents is followed by the functions,
+    // and the checksum covers both of them
+    let mut body = sizes.as_bytes().to_vec();
+    body.extend_from_slice(&buffer);
+
+    let header = Header {
+        magic: FBRIL_MAGIC,
+        version: FBRIL_VERSION,
+        num_funcs: sizes.len() as u64,
+        checksum: checksum(&body),
+    };
+
+    let mut bytes = header.as_bytes().to_vec();
+    bytes.extend_from_slice(&body);
+    bytes
+}
 
-    // TODO: figure out some appropriate filename + size for the mmapped file
-    let mut mmap = mmap_new_file(&output_file, 100000000, true);
+/// Writes a JSON Bril program (read from `stdin`) to a mmap-ed flat Bril file
+pub fn json_to_fbril(output_file: String) -> Result<(), FbrilError> {
+    // Read in the JSON representation of a Bril file from stdin
+    let mut buffer = String::new();
+    std::io::stdin()
+        .read_to_string(&mut buffer)
+        .map_err(|e| FbrilError::Io("stdin".to_string(), e))?;
 
-    // Write the header (containing the offsets) to the file
-    let new_mmap = write_bump(&mut mmap, &header.sizes)
-        .expect("error writing offsets to file");
-    // Then write the contents of the buffer to the file
-    write_bytes(new_mmap, &buffer);
+    // Parse the JSON into serde_json's `Value` datatype
+    let json: serde_json::Value = serde_json::from_str(&buffer)?;
+    let bytes = json_to_fbril_bytes(&json)?;
+    write_fbril(&output_file, &bytes)?;
+
+    println!("succesfully wrote to fbril file!");
+    Ok(())
+}
+
+/// Writes `bytes` (the contents of a flat Bril file) to a mmap-ed file
+pub fn write_fbril(output_file: &str, bytes: &[u8]) -> Result<(), FbrilError> {
+    // The file is exactly as big as its contents
+    let mut mmap = mmap_new_file(output_file, bytes.len() as u64, true)?;
+    write_bytes(&mut mmap, bytes);
+    mmap.flush()
+        .map_err(|e| FbrilError::Io(output_file.to_string(), e))?;
 
     // Note: we're keeping this around as a sanity check
-    let _temp_instr_view = get_instr_view(&buffer);
+    read_fbril(&mmap).expect("wrote a malformed .fbril file");
+    Ok(())
+}
 
-    println!("succesfully wrote to fbril file!");
+/* -------------------------------------------------------------------------- */
+/*                                    Tests                                   */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod memfile_tests {
+    use std::{fs::File, io::BufReader};
+
+    use super::*;
+
+    /// Reads in `test/call.json` & converts it to the contents of
+    /// a flat Bril file
+    fn call_fbril_bytes() -> Vec<u8> {
+        let file = File::open("test/call.json").expect("Unable to open file");
+        let json: serde_json::Value =
+            serde_json::from_reader(BufReader::new(file))
+                .expect("Unable to parse JSON");
+        json_to_fbril_bytes(&json).expect("Unable to flatten program")
+    }
+
+    /// Checks that a freshly written flat Bril file can be read back
+    #[test]
+    fn test_read_fbril() {
+        let bytes = call_fbril_bytes();
+        let program = read_fbril(&bytes).expect("unable to read fbril file");
+        assert_eq!(program.len(), 2);
+    }
+
+    /// Checks that truncated files & files with trailing bytes are rejected
+    #[test]
+    fn test_read_fbril_wrong_size() {
+        let bytes = call_fbril_bytes();
+        for len in [0, size_of::<Header>() - 1, bytes.len() - 1] {
+            assert!(matches!(
+                read_fbril(&bytes[..len]),
+                Err(FbrilError::Truncated { .. })
+            ));
+        }
+
+        let mut padded_bytes = bytes.clone();
+        padded_bytes.extend_from_slice(&[0; 4]);
+        assert!(matches!(
+            read_fbril(&padded_bytes),
+            Err(FbrilError::TrailingBytes(4))
+        ));
+    }
+
+    /// Checks that files with a bad header or corrupted contents are rejected
+    #[test]
+    fn test_read_fbril_corrupt() {
+        let bytes = call_fbril_bytes();
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(read_fbril(&bad_magic), Err(FbrilError::BadMagic)));
+
+        let mut bad_version = bytes.clone();
+        bad_version[4] = 0xff;
+        assert!(matches!(
+            read_fbril(&bad_version),
+            Err(FbrilError::UnsupportedVersion(_))
+        ));
+
+        let mut corrupted = bytes.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            read_fbril(&corrupted),
+            Err(FbrilError::ChecksumMismatch { .. })
+        ));
+    }
+
+    /// Checks that programs using unsupported extensions are rejected
+    /// instead of being flattened
+    #[test]
+    fn test_json_to_fbril_unsupported() {
+        let json = serde_json::json!({"functions": [{"name": "main", "instrs": [
+            {"op": "speculate"},
+            {"op": "commit"}
+        ]}]});
+        assert!(matches!(
+            json_to_fbril_bytes(&json),
+            Err(FbrilError::Unsupported(ExtensionError::Unsupported(
+                Extension::Speculate,
+                _
+            )))
+        ));
+    }
 }
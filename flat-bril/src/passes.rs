@@ -0,0 +1,388 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use thiserror::Error;
+
+use crate::interp::{eval_binop, eval_unop};
+use crate::types::*;
+
+/* -------------------------------------------------------------------------- */
+/*                               Pass framework                               */
+/* -------------------------------------------------------------------------- */
+
+/// An optimization pass, which rewrites the flat arrays in a function's
+/// `InstrStore` in place, and returns whether it changed anything
+/// - Passes never move the bytes in `var_store`: rewriting an arg just
+///   points its entry in `args_idxes_store` at a different variable,
+///   and deleting an instruction just removes it from `instrs`
+pub type Pass = fn(&mut InstrStore) -> bool;
+
+/// Every pass, along with the name used to select it on the command line
+/// (in the order they're run by default)
+pub const PASSES: [(&str, Pass); 3] = [
+    ("const-fold", const_fold),
+    ("copy-prop", copy_prop),
+    ("dce", dce),
+];
+
+/// Error for a pass name that isn't in `PASSES`
+/// (the message lists the names of all the passes)
+#[derive(Debug, Error)]
+#[error(
+    "unknown pass `{0}` (expected one of: {names})",
+    names = PASSES.map(|(name, _)| name).join(", ")
+)]
+pub struct UnknownPass(pub String);
+
+/// Looks up the pass called `name` in `PASSES`
+pub fn get_pass(name: &str) -> Result<Pass, UnknownPass> {
+    PASSES
+        .iter()
+        .find(|(pass_name, _)| *pass_name == name)
+        .map(|(_, pass)| *pass)
+        .ok_or_else(|| UnknownPass(name.to_string()))
+}
+
+/// Runs `passes` (in order) over every function in a program, repeating them
+/// until none of them change the function any further
+pub fn run_passes(instr_stores: &mut [InstrStore], passes: &[Pass]) {
+    for instr_store in instr_stores {
+        // We use `|` (not `||`) so that every pass runs in each iteration
+        while passes
+            .iter()
+            .fold(false, |changed, pass| pass(instr_store) | changed)
+        {}
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   Helpers                                  */
+/* -------------------------------------------------------------------------- */
+
+/// The opcode of `instr` (`None` if `instr` is a label)
+fn get_opcode(instr: &Instr) -> Option<Opcode> {
+    Opcode::u32_to_opcode(instr.op)
+}
+
+/// Extracts the variable name (bytes) that occupies `start_idx` to `end_idx`
+/// (inclusive) in `var_store`
+fn get_var(var_store: &[u8], (start_idx, end_idx): (u32, u32)) -> &[u8] {
+    &var_store[start_idx as usize..=end_idx as usize]
+}
+
+/// The range of `args_idxes_store` that stores the args of `instr`
+/// (which is empty if `instr` has no args)
+fn get_args_range(instr: &Instr) -> Range<usize> {
+    match instr.args {
+        Some((args_start, args_end)) => {
+            args_start as usize..args_end as usize + 1
+        }
+        None => 0..0,
+    }
+}
+
+/// Determines if `op` ends a basic block
+fn is_terminator(op: Opcode) -> bool {
+    matches!(op, Opcode::Jmp | Opcode::Br | Opcode::Ret)
+}
+
+/// Determines if an instruction with the opcode `op` can be deleted when its
+/// `dest` is never used, i.e. it has no side effects & can't fail at runtime
+/// (assuming the program is well-typed)
+fn is_removable(op: Opcode) -> bool {
+    match op {
+        Opcode::Const | Opcode::Undef => true,
+        Opcode::Div | Opcode::Int2char => false,
+        _ => op.is_binop() || op.is_unop(),
+    }
+}
+
+/// Evaluates the value operation `op` on the constant `args`
+/// (returns `None` if the result can't be computed ahead of time)
+fn fold(op: Opcode, args: &[BrilValue]) -> Option<BrilValue> {
+    let value = match *args {
+        [x] if op.is_unop() => eval_unop(op, x).ok()?,
+        [x, y] if op.is_binop() => eval_binop(op, x, y).ok()?,
+        _ => return None,
+    };
+    // Infinities & NaNs can't be written as `const`s in JSON,
+    // so we leave them unfolded
+    match value {
+        BrilValue::FloatVal(f) if !f.is_finite() => None,
+        _ => Some(value),
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   Passes                                   */
+/* -------------------------------------------------------------------------- */
+
+/// Dead code elimination: deletes `nop`s, along with side-effect-free
+/// instructions whose `dest` is either never used anywhere in the function,
+/// or is reassigned before being used in the same basic block
+pub fn dce(instr_store: &mut InstrStore) -> bool {
+    let InstrStore {
+        var_store,
+        args_idxes_store,
+        instrs,
+        ..
+    } = instr_store;
+
+    // Every variable that appears as an arg somewhere in the function
+    let used: HashSet<&[u8]> = instrs
+        .iter()
+        .flat_map(|instr| &args_idxes_store[get_args_range(instr)])
+        .map(|idxes| get_var(var_store, *idxes))
+        .collect();
+
+    // Maps each variable to the (removable) instruction that last assigned
+    // to it in the current basic block, if it hasn't been used since
+    let mut unused_defs: HashMap<&[u8], usize> = HashMap::new();
+    let mut dead = vec![false; instrs.len()];
+
+    for (i, instr) in instrs.iter().enumerate() {
+        let Some(op) = get_opcode(instr) else {
+            // Reached a label, which starts a new basic block
+            unused_defs.clear();
+            continue;
+        };
+        if op == Opcode::Nop {
+            dead[i] = true;
+            continue;
+        }
+
+        for idxes in &args_idxes_store[get_args_range(instr)] {
+            unused_defs.remove(get_var(var_store, *idxes));
+        }
+        if let Some(dest) = instr.dest {
+            let dest = get_var(var_store, dest);
+            // The previous assignment to `dest` is overwritten without
+            // ever being used
+            if let Some(j) = unused_defs.remove(dest) {
+                dead[j] = true;
+            }
+            if is_removable(op) {
+                if used.contains(dest) {
+                    unused_defs.insert(dest, i);
+                } else {
+                    dead[i] = true;
+                }
+            }
+        }
+
+        if is_terminator(op) {
+            unused_defs.clear();
+        }
+    }
+
+    let changed = dead.contains(&true);
+    let mut dead = dead.into_iter();
+    instrs.retain(|_| !dead.next().unwrap());
+    changed
+}
+
+/// Copy propagation: within each basic block, replaces uses of a variable
+/// assigned by `x = id y` with uses of `y` (for as long as neither `x`
+/// nor `y` is reassigned)
+pub fn copy_prop(instr_store: &mut InstrStore) -> bool {
+    let InstrStore {
+        var_store,
+        args_idxes_store,
+        instrs,
+        ..
+    } = instr_store;
+
+    // Maps each copy to the indexes of the original variable in `var_store`
+    let mut copies: HashMap<&[u8], (u32, u32)> = HashMap::new();
+    let mut changed = false;
+
+    for instr in instrs.iter() {
+        let Some(op) = get_opcode(instr) else {
+            // Reached a label, which starts a new basic block
+            copies.clear();
+            continue;
+        };
+
+        // The 1st arg of `set` names a shadow variable (SSA extension),
+        // so it isn't a use of a variable
+        let args = &mut args_idxes_store[get_args_range(instr)];
+        let num_shadow_args = usize::from(op == Opcode::Set);
+        for idxes in args.iter_mut().skip(num_shadow_args) {
+            if let Some(original) = copies.get(get_var(var_store, *idxes)) {
+                *idxes = *original;
+                changed = true;
+            }
+        }
+
+        if let Some(dest) = instr.dest {
+            // Copies of `dest` (and `dest` itself) are now stale
+            let dest = get_var(var_store, dest);
+            copies.remove(dest);
+            copies.retain(|_, original| get_var(var_store, *original) != dest);
+
+            if let (Opcode::Id, [original]) = (op, &*args)
+                && get_var(var_store, *original) != dest
+            {
+                copies.insert(dest, *original);
+            }
+        }
+
+        if is_terminator(op) {
+            copies.clear();
+        }
+    }
+    changed
+}
+
+/// Constant folding: within each basic block, replaces value operations
+/// whose args are all constants with a `const` instruction
+pub fn const_fold(instr_store: &mut InstrStore) -> bool {
+    let InstrStore {
+        var_store,
+        args_idxes_store,
+        instrs,
+        ..
+    } = instr_store;
+
+    // Maps each variable to its value (if it's known to be a constant)
+    let mut consts: HashMap<&[u8], BrilValue> = HashMap::new();
+    let mut changed = false;
+
+    for instr in instrs.iter_mut() {
+        let Some(op) = get_opcode(instr) else {
+            // Reached a label, which starts a new basic block
+            consts.clear();
+            continue;
+        };
+
+        if let Some(dest) = instr.dest {
+            let dest = get_var(var_store, dest);
+            let folded = if op == Opcode::Const {
+                instr.value
+            } else {
+                args_idxes_store[get_args_range(instr)]
+                    .iter()
+                    .map(|idxes| {
+                        consts.get(get_var(var_store, *idxes)).copied()
+                    })
+                    .collect::<Option<Vec<BrilValue>>>()
+                    .and_then(|args| fold(op, &args))
+                    // Ill-typed instructions are left for the interpreter
+                    // to report
+                    .filter(|value| Some(value.get_type()) == instr.ty)
+            };
+
+            match folded {
+                Some(value) => {
+                    if op != Opcode::Const {
+                        instr.op = Opcode::Const.get_index() as u32;
+                        instr.args = None;
+                        instr.value = Some(value);
+                        changed = true;
+                    }
+                    consts.insert(dest, value);
+                }
+                None => {
+                    consts.remove(dest);
+                }
+            }
+        }
+
+        if is_terminator(op) {
+            consts.clear();
+        }
+    }
+    changed
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                    Tests                                   */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod passes_tests {
+    use super::*;
+    use crate::flatten;
+
+    #[test]
+    fn test_get_unknown_pass() {
+        assert!(get_pass("dce").is_ok());
+        let err = get_pass("licm").expect_err("`licm` isn't a pass");
+        assert_eq!(
+            err.to_string(),
+            "unknown pass `licm` (expected one of: const-fold, copy-prop, dce)"
+        );
+    }
+
+    /// Flattens a single `main` function with the instructions `instrs_json`
+    fn flatten_main(instrs_json: serde_json::Value) -> InstrStore {
+        flatten::flatten_instrs(&serde_json::json!({
+            "name": "main",
+            "instrs": instrs_json
+        }))
+    }
+
+    /// The opcodes of the instructions in `instr_store` (labels are `None`)
+    fn opcodes(instr_store: &InstrStore) -> Vec<Option<Opcode>> {
+        instr_store.instrs.iter().map(get_opcode).collect()
+    }
+
+    #[test]
+    fn test_passes() {
+        use Opcode::*;
+
+        let mut instr_store = flatten_main(serde_json::json!([
+            { "op": "const", "dest": "a", "type": "int", "value": 4 },
+            { "op": "const", "dest": "b", "type": "int", "value": 2 },
+            { "op": "id", "dest": "c", "type": "int", "args": ["a"] },
+            { "op": "mul", "dest": "d", "type": "int", "args": ["c", "b"] },
+            { "op": "nop" },
+            { "op": "print", "args": ["d"] }
+        ]));
+        let all_passes: Vec<Pass> =
+            PASSES.iter().map(|(_, pass)| *pass).collect();
+        run_passes(std::slice::from_mut(&mut instr_store), &all_passes);
+
+        // `d` is folded to `8`, so every other instruction is dead
+        assert_eq!(opcodes(&instr_store), vec![Some(Const), Some(Print)]);
+        assert_eq!(instr_store.instrs[0].value, Some(BrilValue::IntVal(8)));
+    }
+
+    #[test]
+    fn test_copy_prop_stops_at_reassignment() {
+        let mut instr_store = flatten_main(serde_json::json!([
+            { "op": "const", "dest": "a", "type": "int", "value": 1 },
+            { "op": "id", "dest": "b", "type": "int", "args": ["a"] },
+            { "op": "print", "args": ["b"] },
+            { "op": "const", "dest": "a", "type": "int", "value": 2 },
+            { "op": "print", "args": ["b"] }
+        ]));
+        assert!(copy_prop(&mut instr_store));
+
+        // Only the 1st `print` can use `a` instead of `b`
+        let printed: Vec<&[u8]> = [2, 4]
+            .iter()
+            .map(|i| {
+                let args = get_args_range(&instr_store.instrs[*i]);
+                get_var(
+                    &instr_store.var_store,
+                    instr_store.args_idxes_store[args.start],
+                )
+            })
+            .collect();
+        assert_eq!(printed, vec![b"a".as_slice(), b"b".as_slice()]);
+    }
+
+    #[test]
+    fn test_const_fold_leaves_errors() {
+        let mut instr_store = flatten_main(serde_json::json!([
+            { "op": "const", "dest": "a", "type": "int", "value": 1 },
+            { "op": "const", "dest": "zero", "type": "int", "value": 0 },
+            { "op": "div", "dest": "b", "type": "int", "args": ["a", "zero"] },
+            { "op": "print", "args": ["b"] }
+        ]));
+
+        // Division by zero has to be reported at runtime
+        assert!(!const_fold(&mut instr_store));
+        assert!(!dce(&mut instr_store));
+    }
+}
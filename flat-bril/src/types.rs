@@ -16,7 +16,8 @@ use zerocopy::{
 ///   `OPCODE_IDX[i] = (start, end)`, such that `OPCODE_BUFFER[start..=end]`
 ///   is the serialized version of the opcode
 /// - We can store the actual `type` and `value` inline in the `Instr` struct
-///   (since they're either an int or a bool,
+///   (since constants are ints, bools, floats or chars and pointer types are
+///   just a primitive type + a nesting depth,
 ///   i.e. they don't need to be heap-allocated)
 /// - `dest` stores the start & end indices (inclusive) of the byte representation
 ///   of the string in the `all_vars` byte vector (see `flatten.rs`)
@@ -26,6 +27,8 @@ use zerocopy::{
 ///   an instruction can have multiple args/labels, so
 ///   `(start, end) = instr.arg ==> all_args_idxes[start..=end] ==> all_vars[...]`
 /// - (Well-formedness condition: we must have end_idx >= start_idx always)
+/// - `pos` and `pos_end` are the `(row, col)` source positions that
+///   `bril2json -p` records (used for error messages in the interpreter)
 #[derive(Debug, PartialEq, Clone)]
 pub struct Instr {
     pub op: u32,
@@ -36,6 +39,8 @@ pub struct Instr {
     pub args: Option<(u32, u32)>,
     pub instr_labels: Option<(u32, u32)>,
     pub funcs: Option<(u32, u32)>,
+    pub pos: Option<(u32, u32)>,
+    pub pos_end: Option<(u32, u32)>,
 }
 
 /// Struct representation of the pair `(i32, i32)`
@@ -59,6 +64,8 @@ pub struct FlatInstr {
     pub funcs: I32Pair,
     pub ty: FlatType,
     pub value: FlatBrilValue,
+    pub pos: I32Pair,
+    pub pos_end: I32Pair,
 }
 
 #[repr(C)]
@@ -71,43 +78,82 @@ pub enum InstrKind {
     Nop,
 }
 
-/// Primitive types in core Bril are either `int` or `bool`
-#[repr(C)]
+/// Primitive types in Bril: `int` and `bool` from core Bril, `float` from
+/// the float extension and `char` from the char extension
+#[repr(u32)]
 #[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
 #[serde(rename_all = "lowercase")]
-pub enum Type {
+pub enum PrimType {
     Int = 0,
     Bool = 1,
+    Float = 2,
+    Char = 3,
+}
+
+/// Types in Bril are either primitive types or pointer types
+/// (from the memory extension)
+/// - A pointer type is represented by the primitive type at the bottom of
+///   the chain of `ptr`s, along with the no. of `ptr`s wrapped around it,
+///   e.g. `ptr<ptr<int>>` is `Ptr(PrimType::Int, 2)`
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(try_from = "serde_json::Value")]
+pub enum Type {
+    Int,
+    Bool,
+    Float,
+    Char,
+    Ptr(PrimType, u32),
 }
 
-#[repr(usize)]
+/// The primitive type stored in a `FlatType` (`Null` means there's no type)
+#[repr(u32)]
 #[derive(
-    Debug,
-    PartialEq,
-    Clone,
-    Copy,
-    Deserialize,
-    IntoBytes,
-    FromZeros,
-    Immutable,
-    KnownLayout,
+    Debug, PartialEq, Clone, Copy, IntoBytes, FromZeros, Immutable, KnownLayout,
 )]
-#[serde(rename_all = "lowercase")]
-pub enum FlatType {
+pub enum FlatPrimType {
     Int = 0,
     Bool = 1,
     Null = 2,
+    Float = 3,
+    Char = 4,
 }
 
-/// The type of primitive values in Bril.    
+/// Flattened version of an `Option<Type>`, amenable to `zerocopy`
+/// - `ptr_depth` is the no. of `ptr`s wrapped around `prim`
+///   (this is 0 for non-pointer types)
+#[repr(C)]
+#[derive(
+    Debug, PartialEq, Clone, Copy, IntoBytes, FromZeros, Immutable, KnownLayout,
+)]
+pub struct FlatType {
+    pub prim: FlatPrimType,
+    pub ptr_depth: u32,
+}
+
+impl FlatType {
+    /// The `FlatType` representing a missing type
+    pub const NULL: FlatType = FlatType {
+        prim: FlatPrimType::Null,
+        ptr_depth: 0,
+    };
+}
+
+/// The type of values in Bril.    
 /// - Note: We call this enum `BrilValue` to avoid namespace clashes
 ///   with `serde_json::Value`
-/// - `SurrogateBool` is needed for padding reasons (to make zerocopy happy)
+/// - `SurrogateBool` & `SurrogateChar` are needed for padding reasons
+///   (to make zerocopy happy)
+/// - Pointers (`PtrVal`) only exist at runtime, they never appear in
+///   `const` instructions
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, PartialEq, Clone, Copy)]
 #[repr(u64)]
 pub enum BrilValue {
     IntVal(i64),
     BoolVal(SurrogateBool),
+    FloatVal(f64),
+    CharVal(SurrogateChar),
+    PtrVal(Pointer),
 }
 
 impl BrilValue {
@@ -116,16 +162,32 @@ impl BrilValue {
         match self {
             BrilValue::IntVal(_) => Type::Int,
             BrilValue::BoolVal(_) => Type::Bool,
+            BrilValue::FloatVal(_) => Type::Float,
+            BrilValue::CharVal(_) => Type::Char,
+            BrilValue::PtrVal(ptr) => ptr.ty,
         }
     }
 }
 
+/// A pointer into the heap (memory extension):
+/// - `alloc` is the index of the allocation the pointer points into
+/// - `offset` is the no. of elements from the start of the allocation
+/// - `ty` is the (pointer) type of the pointer
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Pointer {
+    pub alloc: usize,
+    pub offset: i64,
+    pub ty: Type,
+}
+
 #[derive(Debug, PartialEq, Clone, Copy, IntoBytes, Immutable, FromZeros)]
 #[repr(u64)]
 pub enum FlatBrilValue {
     IntVal(i64),
     BoolVal(SurrogateBool),
     Null(SurrogateNull),
+    FloatVal(f64),
+    CharVal(SurrogateChar),
 }
 
 /// A null which is represented as a u64 to make zerocopy happy
@@ -137,6 +199,11 @@ pub struct SurrogateNull(u64);
 #[derive(Debug, PartialEq, Clone, Copy, IntoBytes, Immutable, FromBytes)]
 pub struct SurrogateBool(u64);
 
+/// A `char` represented as a u64 (storing the char's Unicode scalar value),
+/// so that it has the same representation as `BrilValue::IntVal`'s
+#[derive(Debug, PartialEq, Clone, Copy, IntoBytes, Immutable, FromBytes)]
+pub struct SurrogateChar(u64);
+
 impl Instr {
     /// Represents a label as an `Instr` where
     /// all other fields of the struct are none
@@ -150,6 +217,8 @@ impl Instr {
             args: None,
             instr_labels: None,
             funcs: None,
+            pos: None,
+            pos_end: None,
         }
     }
 
@@ -161,7 +230,9 @@ impl Instr {
             match op {
                 Nop => InstrKind::Nop,
                 Const => InstrKind::Const,
-                Print | Jmp | Br | Ret => InstrKind::EffectOp,
+                Print | Jmp | Br | Ret | Store | Free | Set => {
+                    InstrKind::EffectOp
+                }
                 Call => {
                     // Function calls can be both value op and effect op
                     // depending on whether the `dest` field of the instr
@@ -189,7 +260,9 @@ impl FlatInstr {
             match op {
                 Nop => InstrKind::Nop,
                 Const => InstrKind::Const,
-                Print | Jmp | Br | Ret => InstrKind::EffectOp,
+                Print | Jmp | Br | Ret | Store | Free | Set => {
+                    InstrKind::EffectOp
+                }
                 Call => {
                     // Function calls can be both value op and effect op
                     // depending on whether the `dest` field of the instr
@@ -251,28 +324,92 @@ pub enum Opcode {
     Print = 17,
     Nop = 18,
     Const = 19,
+
+    // Floating-point arithmetic
+    Fadd = 20,
+    Fsub = 21,
+    Fmul = 22,
+    Fdiv = 23,
+
+    // Floating-point comparison
+    Feq = 24,
+    Flt = 25,
+    Fgt = 26,
+    Fle = 27,
+    Fge = 28,
+
+    // Character comparison
+    Ceq = 29,
+    Clt = 30,
+    Cgt = 31,
+    Cle = 32,
+    Cge = 33,
+
+    // Character conversion
+    Char2int = 34,
+    Int2char = 35,
+
+    // Memory
+    Alloc = 36,
+    Free = 37,
+    Store = 38,
+    Load = 39,
+    PtrAdd = 40,
+
+    // SSA
+    Get = 41,
+    Set = 42,
+    Undef = 43,
+
+    // Bitcast
+    Float2Bits = 44,
+    Bits2Float = 45,
 }
 
 impl Opcode {
     /// Determines if an opcode is a binary (value) operation
+    /// that doesn't touch the heap (`ptradd` is the only memory operation
+    /// that is a binop)
     pub fn is_binop(self) -> bool {
-        !matches!(
+        use Opcode::*;
+        matches!(
             self,
-            Opcode::Not
-                | Opcode::Jmp
-                | Opcode::Br
-                | Opcode::Call
-                | Opcode::Ret
-                | Opcode::Id
-                | Opcode::Print
-                | Opcode::Nop
-                | Opcode::Const
+            Add | Mul
+                | Sub
+                | Div
+                | Eq
+                | Lt
+                | Gt
+                | Le
+                | Ge
+                | And
+                | Or
+                | Fadd
+                | Fsub
+                | Fmul
+                | Fdiv
+                | Feq
+                | Flt
+                | Fgt
+                | Fle
+                | Fge
+                | Ceq
+                | Clt
+                | Cgt
+                | Cle
+                | Cge
+                | PtrAdd
         )
     }
 
-    /// Determines if an opcode is a unary (value) operation (i.e. `not`, `id`)
+    /// Determines if an opcode is a unary (value) operation
+    /// (i.e. `not`, `id`, and the char/bitcast conversions)
     pub fn is_unop(self) -> bool {
-        matches!(self, Opcode::Not | Opcode::Id)
+        use Opcode::*;
+        matches!(
+            self,
+            Not | Id | Char2int | Int2char | Float2Bits | Bits2Float
+        )
     }
 
     /// Converts a `u32` value to the corresponding `Opcode`
@@ -339,6 +476,8 @@ pub struct FlatFuncArg {
 ///   (arg type + indexes for the arg name)
 /// - `func_ret_ty` is the return type of the function
 ///   (`None` means the function is void, i.e. has no return type)
+/// - `func_pos` and `func_pos_end` are the `(row, col)` source positions of
+///   the function (if `bril2json -p` recorded them)
 /// - args_idxes_stores |-> var_store
 /// - labels_idxes_store |-> labels_store
 /// - there's only one function so `funcs_store` can just be Vec<u8>
@@ -349,6 +488,8 @@ pub struct InstrStore {
     pub func_name: Vec<u8>,
     pub func_args: Vec<FuncArg>,
     pub func_ret_ty: Option<Type>,
+    pub func_pos: Option<(u32, u32)>,
+    pub func_pos_end: Option<(u32, u32)>,
     pub var_store: Vec<u8>,
     pub args_idxes_store: Vec<(u32, u32)>,
     pub labels_idxes_store: Vec<(u32, u32)>,
@@ -366,6 +507,8 @@ pub struct InstrView<'a> {
     pub func_name: &'a [u8],
     pub func_args: &'a [FlatFuncArg],
     pub func_ret_ty: FlatType,
+    pub func_pos: I32Pair,
+    pub func_pos_end: I32Pair,
     pub var_store: &'a [u8],
     pub arg_idxes_store: &'a [I32Pair],
     pub labels_idxes_store: &'a [I32Pair],
@@ -381,13 +524,20 @@ pub struct InstrViewFamily<'a> {
 }
 
 /// Top-level metadata in the mmap-ed file, appears before all the `Toc`/`InstrView`s
-/// The `sizes` fields contains a list of sizes (no. of bytes) for each
-/// of the functions in the Bril program.
+/// - `magic` is always `FBRIL_MAGIC` & `version` is the `FBRIL_VERSION`
+///   of the format the file was written with
+/// - The header is followed by a table of contents containing `num_funcs`
+///   `u64`s, which are the sizes (no. of bytes) of each of the functions
+///   in the Bril program
+/// - `checksum` is the checksum of everything after the header
+///   (i.e. the table of contents + the functions)
 #[derive(FromBytes, IntoBytes, Debug, Clone, Copy, Immutable, KnownLayout)]
 #[repr(C)]
 pub struct Header {
-    // TODO: change this in the future? right now we only allow at most 10 functions
-    pub sizes: [u64; 10],
+    pub magic: [u8; 4],
+    pub version: u32,
+    pub num_funcs: u64,
+    pub checksum: u64,
 }
 
 /// Table of contents for the flat Bril file
@@ -399,6 +549,8 @@ pub struct Toc {
     pub func_name: usize,
     pub func_args: usize,
     pub func_ret_ty: usize,
+    pub func_pos: usize,
+    pub func_pos_end: usize,
     pub var_store: usize,
     pub arg_idxes_store: usize,
     pub labels_idxes_store: usize,
@@ -414,6 +566,8 @@ impl InstrView<'_> {
         let func_name = self.func_name.len();
         let func_args = self.func_args.len();
         let func_ret_ty = 1; // Each function only has one return type
+        let func_pos = 1; // ... and one source position
+        let func_pos_end = 1;
         let var_store = self.var_store.len();
         let arg_idxes_store = self.arg_idxes_store.len();
         let labels_idxes_store = self.labels_idxes_store.len();
@@ -425,6 +579,8 @@ impl InstrView<'_> {
             func_name,
             func_args,
             func_ret_ty,
+            func_pos,
+            func_pos_end,
             var_store,
             arg_idxes_store,
             labels_idxes_store,
@@ -442,6 +598,7 @@ impl InstrView<'_> {
         let func_name_num_bytes = std::mem::size_of_val(self.func_name);
         let func_args_num_bytes = std::mem::size_of_val(self.func_args);
         let func_ret_ty_num_bytes = size_of::<FlatType>();
+        let func_pos_num_bytes = 2 * size_of::<I32Pair>();
         let var_store_num_bytes = std::mem::size_of_val(self.var_store);
         let arg_idxes_store_num_bytes =
             std::mem::size_of_val(self.arg_idxes_store);
@@ -455,6 +612,7 @@ impl InstrView<'_> {
             + func_name_num_bytes
             + func_args_num_bytes
             + func_ret_ty_num_bytes
+            + func_pos_num_bytes
             + var_store_num_bytes
             + arg_idxes_store_num_bytes
             + labels_idxes_store_num_bytes
@@ -468,12 +626,27 @@ impl InstrView<'_> {
 /*                                  Constants                                 */
 /* -------------------------------------------------------------------------- */
 
-/// A string literal storing all distinct opcodes in core Bril
-pub const OPCODE_BUFFER: &str =
-    "addmulsubdiveqltgtlegenotandorjmpbrcallretidprintnopconst";
+/// Magic number at the start of every `.fbril` file
+pub const FBRIL_MAGIC: [u8; 4] = *b"FBRL";
+
+/// Version of the `.fbril` format, which should be bumped whenever the
+/// layout of `Header`, `Toc` or any of the flat types changes
+pub const FBRIL_VERSION: u32 = 2;
+
+/// A string literal storing all distinct opcodes in core Bril,
+/// followed by the opcodes from the float, char, memory, SSA
+/// and bitcast extensions
+pub const OPCODE_BUFFER: &str = concat!(
+    "addmulsubdiveqltgtlegenotandorjmpbrcallretidprintnopconst",
+    "faddfsubfmulfdivfeqfltfgtflefge",
+    "ceqcltcgtclecgechar2intint2char",
+    "allocfreestoreloadptradd",
+    "getsetundef",
+    "float2bitsbits2float"
+);
 
-/// There are 20 distinct opcodes in core Bril
-pub const NUM_OPCODES: usize = 20;
+/// There are 20 distinct opcodes in core Bril, & 26 more in the extensions
+pub const NUM_OPCODES: usize = 46;
 
 /// Default length of the args array
 /// (Rust `Vec`s are initialized with a capacity that is a power of 2,
@@ -496,48 +669,135 @@ pub const NUM_FUNCS: usize = 8;
 /// Each pair contains the `(start idx, end idx)` of the opcode in `OPCODES`.     
 /// Note that both start and indexes are inclusive.
 pub const OPCODE_IDX: [(usize, usize); NUM_OPCODES] = [
-    (0, 2),   // Add
-    (3, 5),   // Mul
-    (6, 8),   // Sub
-    (9, 11),  // Div
-    (12, 13), // Eq
-    (14, 15), // Lt
-    (16, 17), // Gt
-    (18, 19), // Le
-    (20, 21), // Ge
-    (22, 24), // Not
-    (25, 27), // And
-    (28, 29), // Or
-    (30, 32), // Jmp
-    (33, 34), // Br
-    (35, 38), // Call
-    (39, 41), // Ret
-    (42, 43), // Id
-    (44, 48), // Print
-    (49, 51), // Nop
-    (52, 56), // Const
+    (0, 2),     // Add
+    (3, 5),     // Mul
+    (6, 8),     // Sub
+    (9, 11),    // Div
+    (12, 13),   // Eq
+    (14, 15),   // Lt
+    (16, 17),   // Gt
+    (18, 19),   // Le
+    (20, 21),   // Ge
+    (22, 24),   // Not
+    (25, 27),   // And
+    (28, 29),   // Or
+    (30, 32),   // Jmp
+    (33, 34),   // Br
+    (35, 38),   // Call
+    (39, 41),   // Ret
+    (42, 43),   // Id
+    (44, 48),   // Print
+    (49, 51),   // Nop
+    (52, 56),   // Const
+    (57, 60),   // Fadd
+    (61, 64),   // Fsub
+    (65, 68),   // Fmul
+    (69, 72),   // Fdiv
+    (73, 75),   // Feq
+    (76, 78),   // Flt
+    (79, 81),   // Fgt
+    (82, 84),   // Fle
+    (85, 87),   // Fge
+    (88, 90),   // Ceq
+    (91, 93),   // Clt
+    (94, 96),   // Cgt
+    (97, 99),   // Cle
+    (100, 102), // Cge
+    (103, 110), // Char2int
+    (111, 118), // Int2char
+    (119, 123), // Alloc
+    (124, 127), // Free
+    (128, 132), // Store
+    (133, 136), // Load
+    (137, 142), // PtrAdd
+    (143, 145), // Get
+    (146, 148), // Set
+    (149, 153), // Undef
+    (154, 163), // Float2Bits
+    (164, 173), // Bits2Float
 ];
 
 /* -------------------------------------------------------------------------- */
 /*                          Converting between types                          */
 /* -------------------------------------------------------------------------- */
 
+impl From<PrimType> for Type {
+    fn from(prim: PrimType) -> Self {
+        match prim {
+            PrimType::Int => Type::Int,
+            PrimType::Bool => Type::Bool,
+            PrimType::Float => Type::Float,
+            PrimType::Char => Type::Char,
+        }
+    }
+}
+
+impl From<PrimType> for FlatPrimType {
+    fn from(prim: PrimType) -> Self {
+        match prim {
+            PrimType::Int => FlatPrimType::Int,
+            PrimType::Bool => FlatPrimType::Bool,
+            PrimType::Float => FlatPrimType::Float,
+            PrimType::Char => FlatPrimType::Char,
+        }
+    }
+}
+
+impl TryFrom<FlatPrimType> for PrimType {
+    type Error = ();
+
+    fn try_from(flat_prim: FlatPrimType) -> Result<Self, Self::Error> {
+        match flat_prim {
+            FlatPrimType::Int => Ok(PrimType::Int),
+            FlatPrimType::Bool => Ok(PrimType::Bool),
+            FlatPrimType::Float => Ok(PrimType::Float),
+            FlatPrimType::Char => Ok(PrimType::Char),
+            FlatPrimType::Null => Err(()),
+        }
+    }
+}
+
+// Parses a type from its JSON representation, where `ptr<T>` is represented
+// as the object `{"ptr": T}`
+impl TryFrom<serde_json::Value> for Type {
+    type Error = String;
+
+    fn try_from(json: serde_json::Value) -> Result<Self, Self::Error> {
+        if let Some(pointee) = json.get("ptr") {
+            match Type::try_from(pointee.clone())? {
+                Type::Ptr(prim, depth) => Ok(Type::Ptr(prim, depth + 1)),
+                Type::Int => Ok(Type::Ptr(PrimType::Int, 1)),
+                Type::Bool => Ok(Type::Ptr(PrimType::Bool, 1)),
+                Type::Float => Ok(Type::Ptr(PrimType::Float, 1)),
+                Type::Char => Ok(Type::Ptr(PrimType::Char, 1)),
+            }
+        } else {
+            serde_json::from_value::<PrimType>(json)
+                .map(Type::from)
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
 impl From<Option<Type>> for FlatType {
     fn from(ty_opt: Option<Type>) -> Self {
         match ty_opt {
-            Some(Type::Bool) => FlatType::Bool,
-            Some(Type::Int) => FlatType::Int,
-            None => FlatType::Null,
+            Some(ty) => ty.into(),
+            None => FlatType::NULL,
         }
     }
 }
 
 impl From<Type> for FlatType {
     fn from(ty: Type) -> Self {
-        match ty {
-            Type::Bool => FlatType::Bool,
-            Type::Int => FlatType::Int,
-        }
+        let (prim, ptr_depth) = match ty {
+            Type::Int => (FlatPrimType::Int, 0),
+            Type::Bool => (FlatPrimType::Bool, 0),
+            Type::Float => (FlatPrimType::Float, 0),
+            Type::Char => (FlatPrimType::Char, 0),
+            Type::Ptr(prim, depth) => (prim.into(), depth),
+        };
+        FlatType { prim, ptr_depth }
     }
 }
 
@@ -545,10 +805,11 @@ impl TryFrom<FlatType> for Type {
     type Error = ();
 
     fn try_from(flat_ty: FlatType) -> Result<Self, Self::Error> {
-        match flat_ty {
-            FlatType::Bool => Ok(Type::Bool),
-            FlatType::Int => Ok(Type::Int),
-            FlatType::Null => Err(()),
+        let prim = PrimType::try_from(flat_ty.prim)?;
+        if flat_ty.ptr_depth == 0 {
+            Ok(prim.into())
+        } else {
+            Ok(Type::Ptr(prim, flat_ty.ptr_depth))
         }
     }
 }
@@ -566,6 +827,13 @@ impl From<Option<BrilValue>> for FlatBrilValue {
             Some(BrilValue::BoolVal(surrogate_bool)) => {
                 FlatBrilValue::BoolVal(surrogate_bool)
             }
+            Some(BrilValue::FloatVal(f)) => FlatBrilValue::FloatVal(f),
+            Some(BrilValue::CharVal(surrogate_char)) => {
+                FlatBrilValue::CharVal(surrogate_char)
+            }
+            Some(BrilValue::PtrVal(_)) => {
+                panic!("pointers can't appear as constants in a Bril program")
+            }
             None => FlatBrilValue::Null(SurrogateNull(0)),
         }
     }
@@ -580,6 +848,10 @@ impl TryFrom<FlatBrilValue> for BrilValue {
                 Ok(BrilValue::BoolVal(surrogate_bool))
             }
             FlatBrilValue::IntVal(i) => Ok(BrilValue::IntVal(i)),
+            FlatBrilValue::FloatVal(f) => Ok(BrilValue::FloatVal(f)),
+            FlatBrilValue::CharVal(surrogate_char) => {
+                Ok(BrilValue::CharVal(surrogate_char))
+            }
             FlatBrilValue::Null(_) => Err(()),
         }
     }
@@ -610,6 +882,20 @@ impl From<bool> for SurrogateBool {
     }
 }
 
+// `char::from(surrogate_char)` is useful too
+impl From<SurrogateChar> for char {
+    fn from(surrogate_char: SurrogateChar) -> Self {
+        char::from_u32(surrogate_char.0 as u32)
+            .expect("`SurrogateChar` doesn't contain a valid char")
+    }
+}
+
+impl From<char> for SurrogateChar {
+    fn from(c: char) -> Self {
+        SurrogateChar(c as u64)
+    }
+}
+
 impl From<(u32, u32)> for I32Pair {
     fn from(pair: (u32, u32)) -> Self {
         Self {
@@ -668,6 +954,8 @@ impl From<Instr> for FlatInstr {
             funcs: instr.funcs.into(),
             ty: instr.ty.into(),
             value: instr.value.into(),
+            pos: instr.pos.into(),
+            pos_end: instr.pos_end.into(),
         }
     }
 }
@@ -683,6 +971,8 @@ impl From<FlatInstr> for Instr {
             args: flat_instr.args.into(),
             instr_labels: flat_instr.instr_labels.into(),
             funcs: flat_instr.funcs.into(),
+            pos: flat_instr.pos.into(),
+            pos_end: flat_instr.pos_end.into(),
         }
     }
 }
@@ -710,7 +1000,13 @@ impl From<FlatFuncArg> for FuncArg {
 
 impl From<InstrView<'_>> for InstrStore {
     fn from(instr_view: InstrView) -> Self {
-        let func_name = instr_view.func_name.into();
+        // Remove the null bytes that pad the function name
+        let func_name_len = instr_view
+            .func_name
+            .iter()
+            .rposition(|&b| b != 0)
+            .map_or(0, |i| i + 1);
+        let func_name = instr_view.func_name[..func_name_len].into();
         let func_args: Vec<FuncArg> = instr_view
             .func_args
             .iter()
@@ -718,6 +1014,8 @@ impl From<InstrView<'_>> for InstrStore {
             .collect();
 
         let func_ret_ty = instr_view.func_ret_ty.into();
+        let func_pos = instr_view.func_pos.into();
+        let func_pos_end = instr_view.func_pos_end.into();
 
         let var_store = instr_view.var_store.into();
         let args_idxes_store: Vec<(u32, u32)> = instr_view
@@ -743,6 +1041,8 @@ impl From<InstrView<'_>> for InstrStore {
             func_name,
             func_args,
             func_ret_ty,
+            func_pos,
+            func_pos_end,
             var_store,
             args_idxes_store,
             labels_idxes_store,
@@ -757,21 +1057,80 @@ impl From<InstrView<'_>> for InstrStore {
 /*                               Pretty-Printing                              */
 /* -------------------------------------------------------------------------- */
 
+impl fmt::Display for PrimType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl PrimType {
+    /// Converts a `PrimType` to its string representation
+    pub fn as_str(&self) -> &str {
+        match self {
+            PrimType::Int => "int",
+            PrimType::Bool => "bool",
+            PrimType::Float => "float",
+            PrimType::Char => "char",
+        }
+    }
+}
+
 impl fmt::Display for Type {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Type::Int => write!(f, "int"),
             Type::Bool => write!(f, "bool"),
+            Type::Float => write!(f, "float"),
+            Type::Char => write!(f, "char"),
+            Type::Ptr(prim, depth) => {
+                let depth = *depth as usize;
+                write!(f, "{}{prim}{}", "ptr<".repeat(depth), ">".repeat(depth))
+            }
+        }
+    }
+}
+
+impl From<Type> for bril_rs::Type {
+    fn from(ty: Type) -> Self {
+        match ty {
+            Type::Int => bril_rs::Type::Int,
+            Type::Bool => bril_rs::Type::Bool,
+            Type::Float => bril_rs::Type::Float,
+            Type::Char => bril_rs::Type::Char,
+            Type::Ptr(prim, depth) => (0..depth)
+                .fold(Type::from(prim).into(), |ty: bril_rs::Type, _| {
+                    bril_rs::Type::Pointer(Box::new(ty))
+                }),
         }
     }
 }
 
 impl Type {
-    /// Converts a `Type` to its string representation
-    pub fn as_str(&self) -> &str {
+    /// Converts a `Type` to its JSON representation
+    /// (pointer types are nested `{"ptr": ...}` objects)
+    pub fn to_json(self) -> serde_json::Value {
         match self {
-            Type::Int => "int",
-            Type::Bool => "bool",
+            Type::Int => serde_json::json!("int"),
+            Type::Bool => serde_json::json!("bool"),
+            Type::Float => serde_json::json!("float"),
+            Type::Char => serde_json::json!("char"),
+            Type::Ptr(prim, depth) => {
+                let mut json = serde_json::json!(prim.as_str());
+                for _ in 0..depth {
+                    json = serde_json::json!({ "ptr": json });
+                }
+                json
+            }
+        }
+    }
+
+    /// The type that a pointer of type `self` points to
+    /// (returns `None` if `self` isn't a pointer type)
+    pub fn pointee(&self) -> Option<Type> {
+        match self {
+            Type::Ptr(prim, 1) => Some((*prim).into()),
+            Type::Ptr(prim, depth) => Some(Type::Ptr(*prim, depth - 1)),
+            _ => None,
         }
     }
 }
@@ -783,6 +1142,30 @@ impl fmt::Display for BrilValue {
             BrilValue::BoolVal(b) => {
                 write!(f, "{}", bool::from(*b))
             }
+            // Floats are printed the same way as `brili`/`brilirs` print them
+            BrilValue::FloatVal(v) if v.is_infinite() => {
+                if v.is_sign_positive() {
+                    write!(f, "Infinity")
+                } else {
+                    write!(f, "-Infinity")
+                }
+            }
+            BrilValue::FloatVal(v) if v.is_nan() => write!(f, "NaN"),
+            BrilValue::FloatVal(v) if *v != 0.0 && v.abs().log10() >= 10.0 => {
+                write!(f, "{}", format!("{v:.17e}").replace('e', "e+"))
+            }
+            BrilValue::FloatVal(v) if *v != 0.0 && v.abs().log10() <= -10.0 => {
+                write!(f, "{v:.17e}")
+            }
+            BrilValue::FloatVal(v) => write!(f, "{v:.17}"),
+            BrilValue::CharVal(c) => write!(f, "{}", char::from(*c)),
+            BrilValue::PtrVal(ptr) => {
+                write!(
+                    f,
+                    "Pointer {{ alloc: {}, offset: {} }}",
+                    ptr.alloc, ptr.offset
+                )
+            }
         }
     }
 }
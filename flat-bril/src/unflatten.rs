@@ -21,10 +21,11 @@ pub fn unflatten_instrs(instr_store: &InstrStore) -> serde_json::Value {
         } else {
             let op_str = Opcode::op_idx_to_op_str(instr.op as usize);
 
-            // Extract the `ty` field of the instr as a string
-            let mut ty_str = None;
+            // Extract the `ty` field of the instr as JSON
+            // (pointer types are JSON objects, not strings)
+            let mut ty_json = None;
             if let Some(ty) = &instr.ty {
-                ty_str = Some(ty.as_str());
+                ty_json = Some(ty.to_json());
             }
 
             // Convert the `dest` index of the instr to an actual string
@@ -107,12 +108,24 @@ pub fn unflatten_instrs(instr_store: &InstrStore) -> serde_json::Value {
                                 .unwrap(),
                         );
                     }
+                    BrilValue::FloatVal(f) => {
+                        value_for_json = Some(serde_json::to_value(f).unwrap());
+                    }
+                    BrilValue::CharVal(surrogate_char) => {
+                        value_for_json = Some(
+                            serde_json::to_value(char::from(*surrogate_char))
+                                .unwrap(),
+                        );
+                    }
+                    BrilValue::PtrVal(_) => {
+                        unreachable!("pointers can't appear as constants")
+                    }
                 }
             }
 
             // Build a JSON object corresponding to the right instr kind
             let instr_kind = instr.get_instr_kind();
-            let instr_json = match instr_kind {
+            let mut instr_json = match instr_kind {
                 InstrKind::Label => {
                     // labels are already handled at the beginning of this function
                     unreachable!();
@@ -129,7 +142,7 @@ pub fn unflatten_instrs(instr_store: &InstrStore) -> serde_json::Value {
                     serde_json::json!({
                       "op": op_str,
                       "dest": dest_for_json,
-                      "type": ty_str.expect("Expected string representing a type"),
+                      "type": ty_json.expect("Expected a type"),
                       "value": value_for_json.expect("Missing value"),
                     })
                 }
@@ -140,7 +153,7 @@ pub fn unflatten_instrs(instr_store: &InstrStore) -> serde_json::Value {
                     serde_json::json!({
                       "op": op_str,
                       "dest": dest_for_json,
-                      "type": ty_str.expect("Expected string representing a type"),
+                      "type": ty_json.expect("Expected a type"),
                       "args": args_for_json,
                       "labels": labels_for_json,
                       "funcs": funcs_for_json
@@ -156,6 +169,17 @@ pub fn unflatten_instrs(instr_store: &InstrStore) -> serde_json::Value {
                 }
             };
 
+            // Keep the source positions (if any) that `bril2json -p` recorded
+            let positions = [("pos", instr.pos), ("pos_end", instr.pos_end)];
+            for (key, pos) in positions {
+                if let Some((row, col)) = pos {
+                    instr_json[key] = serde_json::json!({
+                        "row": row,
+                        "col": col
+                    });
+                }
+            }
+
             instr_json_vec.push(instr_json);
         }
     }
@@ -177,10 +201,10 @@ pub fn unflatten_instrs(instr_store: &InstrStore) -> serde_json::Value {
                 .expect("invalid utf-8");
 
         // Extract the type of the function argument
-        let arg_type_str = func_arg.arg_type.as_str();
+        let arg_type_json = func_arg.arg_type.to_json();
         let func_arg_json = serde_json::json!({
             "name": func_arg_str,
-            "type": arg_type_str
+            "type": arg_type_json
         });
         func_args_for_json.push(func_arg_json);
     }
@@ -188,12 +212,12 @@ pub fn unflatten_instrs(instr_store: &InstrStore) -> serde_json::Value {
     // Distinguish between funcs that have a return type & void functions
     // (for void functions, there is no "type" field in the JSON object
     // representing the function)
-    let func_json;
+    let mut func_json;
     if let Some(ret_ty) = &instr_store.func_ret_ty {
         func_json = serde_json::json!({
             "name": func_name,
             "args": func_args_for_json,
-            "type": ret_ty.as_str(),
+            "type": ret_ty.to_json(),
             "instrs": instr_json_vec
         });
     } else {
@@ -203,6 +227,15 @@ pub fn unflatten_instrs(instr_store: &InstrStore) -> serde_json::Value {
             "instrs": instr_json_vec
         });
     }
+    let positions = [
+        ("pos", instr_store.func_pos),
+        ("pos_end", instr_store.func_pos_end),
+    ];
+    for (key, pos) in positions {
+        if let Some((row, col)) = pos {
+            func_json[key] = serde_json::json!({ "row": row, "col": col });
+        }
+    }
 
     func_json
 }